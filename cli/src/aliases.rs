@@ -0,0 +1,144 @@
+use failure::{format_err, Fallible};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+
+/// Aliases map a name to the arguments it expands to, such as `t` to `list +today`
+pub(crate) type Aliases = BTreeMap<String, Vec<String>>;
+
+/// Expand an alias given as the subcommand, before the arguments are parsed.  The expansion may
+/// itself begin with an alias, which is expanded in turn.  An alias whose expansion begins with
+/// its own name, such as `list` for `list -WAITING`, is expanded once, leaving the command of the
+/// same name; any other repetition is an error.  `config` is never expanded, so that a broken
+/// alias can always be fixed with `config`.
+pub(crate) fn expand(args: Vec<OsString>, aliases: &Aliases) -> Fallible<Vec<OsString>> {
+    let mut args = args;
+    let i = match subcommand_index(&args) {
+        Some(i) => i,
+        None => return Ok(args),
+    };
+
+    let mut expanded: Vec<String> = vec![];
+    while let Some(name) = args[i].to_str().map(|s| s.to_string()) {
+        if name == "config" {
+            break;
+        }
+        let expansion = match aliases.get(&name) {
+            Some(expansion) => expansion,
+            None => break,
+        };
+        if expanded.contains(&name) {
+            if expanded.last() == Some(&name) {
+                break;
+            }
+            expanded.push(name);
+            return Err(format_err!("Alias loop: {}", expanded.join(" -> ")));
+        }
+        log::debug!("Expanding alias {:?} to {:?}", name, expansion);
+        args.splice(i..=i, expansion.iter().map(OsString::from));
+        expanded.push(name);
+        if i >= args.len() {
+            break;
+        }
+    }
+    Ok(args)
+}
+
+/// Find the index of the subcommand, skipping the program name and any global options
+pub(crate) fn subcommand_index(args: &[OsString]) -> Option<usize> {
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].to_str() {
+            // the only global option taking a value
+            Some("--format") => i += 2,
+            Some(arg) if arg.starts_with('-') => i += 1,
+            _ => return Some(i),
+        }
+    }
+    None
+}
+
+/// Describe the aliases, for the `--help` output
+pub(crate) fn help(aliases: &Aliases) -> String {
+    let width = aliases.keys().map(|n| n.len()).max().unwrap_or(0);
+    let mut help = String::from("ALIASES:");
+    for (name, expansion) in aliases.iter() {
+        help.push_str(&format!(
+            "\n    {:width$}    {}",
+            name,
+            expansion.join(" "),
+            width = width
+        ));
+    }
+    help
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn aliases(defs: &[(&str, &str)]) -> Aliases {
+        defs.iter()
+            .map(|(n, e)| (n.to_string(), e.split(' ').map(|s| s.into()).collect()))
+            .collect()
+    }
+
+    fn args(s: &str) -> Vec<OsString> {
+        s.split(' ').map(OsString::from).collect()
+    }
+
+    #[test]
+    fn expand_simple() -> Fallible<()> {
+        let a = aliases(&[("t", "list +today")]);
+        assert_eq!(
+            expand(args("task t +work"), &a)?,
+            args("task list +today +work")
+        );
+        assert_eq!(
+            expand(args("task --format json t"), &a)?,
+            args("task --format json list +today")
+        );
+        assert_eq!(expand(args("task add t"), &a)?, args("task add t"));
+        assert_eq!(expand(args("task"), &a)?, args("task"));
+        Ok(())
+    }
+
+    #[test]
+    fn expand_nested() -> Fallible<()> {
+        let a = aliases(&[("w", "t +work"), ("t", "list +today")]);
+        assert_eq!(expand(args("task w"), &a)?, args("task list +today +work"));
+        Ok(())
+    }
+
+    #[test]
+    fn expand_self() -> Fallible<()> {
+        let a = aliases(&[("l", "list"), ("list", "list -WAITING")]);
+        assert_eq!(expand(args("task l"), &a)?, args("task list -WAITING"));
+        Ok(())
+    }
+
+    #[test]
+    fn expand_config() -> Fallible<()> {
+        let a = aliases(&[("c", "config path"), ("config", "list")]);
+        assert_eq!(expand(args("task c"), &a)?, args("task config path"));
+        assert_eq!(expand(args("task config"), &a)?, args("task config"));
+        Ok(())
+    }
+
+    #[test]
+    fn expand_loop() {
+        let a = aliases(&[("a", "b +x"), ("b", "a")]);
+        assert_eq!(
+            expand(args("task a"), &a).unwrap_err().to_string(),
+            "Alias loop: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn help_text() {
+        let a = aliases(&[("t", "list +today"), ("work", "list project:work")]);
+        assert_eq!(
+            help(&a),
+            "ALIASES:\n    t       list +today\n    work    list project:work"
+        );
+    }
+}
@@ -1,20 +1,26 @@
-use clap::{App, Arg, ArgMatches, SubCommand as ClapSubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand as ClapSubCommand};
 use failure::{format_err, Fallible};
-use taskchampion::Status;
 
-use crate::cmd::{ArgMatchResult, CommandInvocation};
+use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
+use crate::modification::Modification;
+use crate::settings;
 
 #[derive(Debug)]
 struct Invocation {
     description: String,
+    modification: Modification,
 }
 
 define_subcommand! {
     fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
         app.subcommand(
-            ClapSubCommand::with_name("add").about("adds a task").arg(
-                Arg::with_name("description")
-                    .help("task description")
+            ClapSubCommand::with_name("add").about("adds a task")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                Arg::with_name("args")
+                    .help("task description and attributes, such as project:home, +tag, or due:tomorrow")
+                    .multiple(true)
+                    .allow_hyphen_values(true)
                     .required(true),
             ),
         )
@@ -23,12 +29,16 @@ define_subcommand! {
     fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
         match matches.subcommand() {
             ("add", Some(matches)) => {
-                // TODO: .unwrap() would be safe here as description is required above
-                let description: String = match matches.value_of("description") {
-                    Some(v) => v.into(),
+                let args: Vec<&str> = matches.values_of("args").unwrap().collect();
+                let mut modification = match Modification::parse(&args) {
+                    Ok(m) => m,
+                    Err(e) => return ArgMatchResult::Err(e),
+                };
+                let description = match modification.description.take() {
+                    Some(d) => d,
                     None => return ArgMatchResult::Err(format_err!("no description provided")),
                 };
-                ArgMatchResult::Ok(Box::new(Invocation { description }))
+                ArgMatchResult::Ok(Box::new(Invocation { description, modification }))
             }
             _ => ArgMatchResult::None,
         }
@@ -37,11 +47,15 @@ define_subcommand! {
 
 subcommand_invocation! {
     fn run(&self, command: &CommandInvocation) -> Fallible<()> {
-        let t = command
-            .get_replica()?
-            .new_task(Status::Pending, self.description.clone())
-            .unwrap();
-        println!("added task {}", t.get_uuid());
+        let mut modification = self.modification.clone();
+        if let Some((_, filter)) = settings::context(&*command.get_settings()?)? {
+            modification.add_context(&filter);
+        }
+        let mut replica = command.get_replica()?;
+        let t = shared::add_task(command, &mut replica, self.description.clone(), |t| {
+            modification.apply(t)
+        })?;
+        println!("Added task {}", shared::task_label(&mut replica, &t)?);
         Ok(())
     }
 }
@@ -56,4 +70,22 @@ mod test {
             assert_eq!(inv.description, "foo bar".to_string());
         });
     }
+
+    #[test]
+    fn parse_command_attributes() {
+        with_subcommand_invocation!(
+            vec!["task", "add", "-x", "foo", "+next", "project:home", "bar"],
+            |inv: &Invocation| {
+                assert_eq!(inv.description, "foo bar".to_string());
+                assert_eq!(inv.modification.add_tags, vec!["next".to_string()]);
+                assert_eq!(inv.modification.remove_tags, vec!["x".to_string()]);
+                assert_eq!(inv.modification.project, Some(Some("home".into())));
+            }
+        );
+    }
+
+    #[test]
+    fn parse_command_no_description() {
+        assert!(crate::parse_test_command_line(vec!["task", "add", "+next"]).is_err());
+    }
 }
@@ -0,0 +1,199 @@
+use crate::dates::parse_date;
+use crate::filter::Filter;
+use crate::output::{self, Format};
+use chrono::{DateTime, Local, Utc};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand as ClapSubCommand};
+use failure::{format_err, Fallible};
+use serde_json::json;
+use taskchampion::{Burndown, BurndownPeriod, Period};
+
+use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
+
+/// The width of the longest bar in the chart
+const CHART_WIDTH: usize = 40;
+
+#[derive(Debug)]
+struct Invocation {
+    period: Period,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    filter: Filter,
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("burndown")
+                .about("chart the number of pending tasks over time, by day or by week")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("period")
+                        .help("length of each period")
+                        .possible_values(&["daily", "weekly"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .help("start of the chart (default today-13d, or today-11w for weekly)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .help("end of the chart (default now)")
+                        .takes_value(true),
+                )
+                .arg(shared::optional_filter_arg()),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("burndown", Some(matches)) => match Invocation::from_matches(matches) {
+                Ok(invocation) => ArgMatchResult::Ok(Box::new(invocation)),
+                Err(e) => ArgMatchResult::Err(e),
+            },
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+impl Invocation {
+    fn from_matches(matches: &ArgMatches) -> Fallible<Self> {
+        let (period, default_from) = match matches.value_of("period").unwrap() {
+            "daily" => (Period::Daily, "today-13d"),
+            _ => (Period::Weekly, "today-11w"),
+        };
+        let from = parse_date(matches.value_of("from").unwrap_or(default_from))?;
+        let to = parse_date(matches.value_of("to").unwrap_or("now"))?;
+        if from > to {
+            return Err(format_err!("The start of the chart is after its end"));
+        }
+        Ok(Invocation {
+            period,
+            from,
+            to,
+            filter: shared::get_optional_filter(matches)?,
+        })
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let mut replica = command.get_replica()?;
+        let tasks = shared::summarized_tasks(command, &mut replica, &self.filter)?;
+        let burndown = Burndown::new(tasks.iter(), self.period, self.from, self.to, &Local);
+        let periods = burndown.periods();
+
+        let mut headings = vec![
+            match self.period {
+                Period::Daily => "day",
+                Period::Weekly => "week",
+            },
+            "added",
+            "completed",
+            "deleted",
+            "remaining",
+        ];
+        // the chart is only useful to humans
+        let chart = command.format == Format::Table;
+        if chart {
+            headings.push("");
+        }
+        let mut t = output::Table::new(&headings);
+        let mut json = vec![];
+        let max = periods
+            .iter()
+            .map(|p| p.remaining + p.completed + p.deleted)
+            .max()
+            .unwrap_or(0);
+        for p in periods {
+            let mut row = vec![
+                p.start.format("%Y-%m-%d").to_string(),
+                p.added.to_string(),
+                p.completed.to_string(),
+                p.deleted.to_string(),
+                p.remaining.to_string(),
+            ];
+            if chart {
+                row.push(bar(p, max));
+            }
+            t.add_row(&row);
+            json.push(json!({
+                "start": p.start.format("%Y-%m-%d").to_string(),
+                "added": p.added,
+                "completed": p.completed,
+                "deleted": p.deleted,
+                "remaining": p.remaining,
+            }));
+        }
+        command.write_output(&[t], &json.into())
+    }
+}
+
+/// Draw a bar for a period: `#` for the tasks remaining at its end, followed by `-` for the tasks
+/// closed during it, scaled so that a total of `max` fills the chart
+fn bar(period: &BurndownPeriod, max: usize) -> String {
+    // round up, so that any remaining tasks are visible
+    let width = |n: usize| {
+        (n * CHART_WIDTH + max.saturating_sub(1))
+            .checked_div(max)
+            .unwrap_or(0)
+    };
+    let remaining = width(period.remaining);
+    let closed = width(period.remaining + period.completed + period.deleted) - remaining;
+    format!("{}{}", "#".repeat(remaining), "-".repeat(closed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(
+            vec![
+                "task",
+                "burndown",
+                "weekly",
+                "--from",
+                "2021-03-01",
+                "+work"
+            ],
+            |inv: &Invocation| {
+                assert_eq!(inv.period, Period::Weekly);
+                assert_eq!(inv.from, parse_date("2021-03-01").unwrap());
+                assert_eq!(inv.filter, Filter::parse(&["+work"]).unwrap());
+            }
+        );
+    }
+
+    #[test]
+    fn parse_command_bad_period() {
+        assert!(crate::parse_test_command_line(vec!["task", "burndown", "monthly"]).is_err());
+    }
+
+    #[test]
+    fn bars() {
+        let period = |remaining, completed| BurndownPeriod {
+            start: NaiveDate::from_ymd(2021, 3, 1),
+            added: 0,
+            completed,
+            deleted: 0,
+            remaining,
+        };
+        assert_eq!(bar(&period(4, 0), 4), "#".repeat(CHART_WIDTH));
+        assert_eq!(bar(&period(1, 0), 80), "#");
+        assert_eq!(
+            bar(&period(2, 1), 4),
+            format!("{}{}", "#".repeat(20), "-".repeat(10))
+        );
+        assert_eq!(
+            bar(&period(3, 1), 4),
+            format!("{}{}", "#".repeat(30), "-".repeat(10))
+        );
+        assert_eq!(bar(&period(0, 0), 0), "");
+    }
+}
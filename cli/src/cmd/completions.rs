@@ -0,0 +1,187 @@
+use clap::{App, Arg, ArgMatches, Shell, SubCommand as ClapSubCommand};
+use failure::Fallible;
+
+use crate::cmd::{ArgMatchResult, CommandInvocation};
+
+/// The shells for which completion scripts can be generated
+const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+/// Subcommands taking task ids, which are completed from the working set using `task _ids`
+const ID_SUBCOMMANDS: &[&str] = &[
+    "debug", "delete", "done", "edit", "info", "modify", "start", "stop",
+];
+
+#[derive(Debug)]
+struct Invocation {
+    shell: Shell,
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("completions")
+                .about("print a shell completion script")
+                .arg(
+                    Arg::with_name("shell")
+                        .help("shell to complete for")
+                        .possible_values(SHELLS)
+                        .required(true),
+                ),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("completions", Some(matches)) => ArgMatchResult::Ok(Box::new(Invocation {
+                // possible_values ensures this parses
+                shell: matches.value_of("shell").unwrap().parse().unwrap(),
+            })),
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, _command: &CommandInvocation) -> Fallible<()> {
+        print!("{}", script(self.shell)?);
+        Ok(())
+    }
+}
+
+/// Generate the completion script for the given shell: clap's completion of subcommands and
+/// options, extended to complete task ids for the subcommands that take them.
+fn script(shell: Shell) -> Fallible<String> {
+    let mut script = vec![];
+    crate::app(&super::user_subcommands()).gen_completions_to("task", shell, &mut script);
+    let script = String::from_utf8(script)?;
+    Ok(match shell {
+        Shell::Bash => bash_ids(script),
+        Shell::Zsh => zsh_ids(script),
+        Shell::Fish => fish_ids(script),
+        _ => script,
+    })
+}
+
+/// Wrap clap's `_task` function in one completing ids after an id subcommand.  Bash cannot show
+/// descriptions, so only the ids are offered.
+fn bash_ids(script: String) -> String {
+    let script = script.replace("complete -F _task -o bashdefault -o default task", "");
+    format!(
+        r#"{}
+_task_ids() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" i
+    for (( i=1; i < COMP_CWORD; i++ )); do
+        case "${{COMP_WORDS[i]}}" in
+            --format)
+                (( i++ ))
+                ;;
+            -*)
+                ;;
+            {})
+                if [[ ${{cur}} != -* ]]; then
+                    COMPREPLY=( $(compgen -W "$(task _ids 2>/dev/null | cut -f1)" -- "${{cur}}") )
+                    return 0
+                fi
+                break
+                ;;
+            *)
+                break
+                ;;
+        esac
+    done
+    _task "$@"
+}}
+
+complete -F _task_ids -o bashdefault -o default task
+"#,
+        script.trim_end(),
+        ID_SUBCOMMANDS.join("|")
+    )
+}
+
+/// Complete the task and filter arguments of id subcommands with `_task_ids`, in place of the
+/// `_files` clap uses for any positional argument.
+fn zsh_ids(script: String) -> String {
+    let mut subcommand = "";
+    let mut lines = vec![];
+    for line in script.lines() {
+        if line.starts_with('(') && line.ends_with(')') {
+            subcommand = line.trim_start_matches('(').trim_end_matches(')');
+        }
+        if ID_SUBCOMMANDS.contains(&subcommand)
+            && (line.starts_with("':task -- ") || line.starts_with("':filter -- "))
+        {
+            lines.push(line.replace(":_files' \\", ":_task_ids' \\"));
+        } else if line == "_task \"$@\"" {
+            lines.push(
+                r#"(( $+functions[_task_ids] )) ||
+_task_ids() {
+    local ids; ids=(${${(f)"$(_call_program ids task _ids 2>/dev/null)"}//$'\t'/:})
+    _describe -t ids 'task id' ids "$@"
+}
+"#
+                .into(),
+            );
+            lines.push(line.into());
+        } else {
+            lines.push(line.into());
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Add a completion of ids, with their descriptions, for the id subcommands
+fn fish_ids(script: String) -> String {
+    format!(
+        "{}complete -c task -n \"__fish_seen_subcommand_from {}\" -f -a \"(task _ids)\"\n",
+        script,
+        ID_SUBCOMMANDS.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(vec!["task", "completions", "zsh"], |inv: &Invocation| {
+            assert!(matches!(inv.shell, Shell::Zsh));
+        });
+    }
+
+    #[test]
+    fn parse_command_bad_shell() {
+        assert!(crate::parse_test_command_line(vec!["task", "completions", "tcsh"]).is_err());
+    }
+
+    #[test]
+    fn bash_script() -> Fallible<()> {
+        let script = script(Shell::Bash)?;
+        assert!(script.contains("complete -F _task_ids -o bashdefault -o default task\n"));
+        assert!(script.contains("debug|delete|done|edit|info|modify|start|stop)"));
+        assert!(!script.contains("___ids"));
+        Ok(())
+    }
+
+    #[test]
+    fn zsh_script() -> Fallible<()> {
+        let script = script(Shell::Zsh)?;
+        assert!(script.contains("_task_ids() {"));
+        assert!(!script.contains("uuid prefix), +tag, -tag, project:name, or status:name:_files'"));
+        assert!(script.contains("':task -- task id, uuid, or uuid prefix:_task_ids' \\"));
+        assert!(script.ends_with("_task \"$@\"\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn fish_script() -> Fallible<()> {
+        let script = script(Shell::Fish)?;
+        assert!(script.contains(
+            "complete -c task -n \"__fish_seen_subcommand_from debug delete done edit info \
+             modify start stop\" -f -a \"(task _ids)\"\n"
+        ));
+        assert!(!script.contains("_ids\""));
+        Ok(())
+    }
+}
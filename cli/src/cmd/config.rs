@@ -0,0 +1,472 @@
+use crate::settings::{self, ConfigFile, Origin};
+use crate::table;
+use clap::{App, Arg, ArgMatches, SubCommand as ClapSubCommand};
+use config::ConfigError;
+use failure::{format_err, Fallible};
+use prettytable::{row, Table};
+use std::io::{self, BufRead, Write};
+use taskchampion::server::RemoteServer;
+use taskchampion::Uuid;
+use yaml_rust::Yaml;
+
+use crate::cmd::{ArgMatchResult, CommandInvocation, SubCommandInvocation};
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Init(InitOptions),
+    Get { key: String },
+    Set { key: String, value: String },
+    Unset { key: String },
+    List,
+    Path,
+}
+
+/// Options for `config init`, given on the command line
+#[derive(Debug, Default, PartialEq)]
+struct InitOptions {
+    origin: Option<String>,
+    no_check: bool,
+}
+
+#[derive(Debug)]
+struct Invocation {
+    action: Action,
+}
+
+/// Get the clap subcommand for `init`, which also appears as `task sync init`
+pub(super) fn init_subcommand<'a>() -> App<'a, 'a> {
+    ClapSubCommand::with_name("init")
+        .about("set up the configuration for this replica and its sync server")
+        .arg(
+            Arg::with_name("origin")
+                .long("origin")
+                .takes_value(true)
+                .help("origin of the sync server, e.g., https://taskchampion.example.com"),
+        )
+        .arg(
+            Arg::with_name("no-check")
+                .long("no-check")
+                .help("do not check that the sync server is reachable"),
+        )
+}
+
+/// Get the invocation for `init`, given its matches
+pub(super) fn init_invocation(matches: &ArgMatches) -> Box<dyn SubCommandInvocation> {
+    Box::new(Invocation {
+        action: Action::Init(InitOptions {
+            origin: matches.value_of("origin").map(|s| s.into()),
+            no_check: matches.is_present("no-check"),
+        }),
+    })
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("config")
+                .about("manage configuration")
+                .subcommand(init_subcommand())
+                .subcommand(
+                    ClapSubCommand::with_name("get")
+                        .about("show the effective value of a setting")
+                        .arg(Arg::with_name("key").required(true)),
+                )
+                .subcommand(
+                    ClapSubCommand::with_name("set")
+                        .about("set a value in the configuration file")
+                        .arg(Arg::with_name("key").required(true))
+                        .arg(Arg::with_name("value").required(true)),
+                )
+                .subcommand(
+                    ClapSubCommand::with_name("unset")
+                        .about("remove a value from the configuration file")
+                        .arg(Arg::with_name("key").required(true)),
+                )
+                .subcommand(
+                    ClapSubCommand::with_name("list")
+                        .about("list all settings, with where each value comes from"),
+                )
+                .subcommand(
+                    ClapSubCommand::with_name("path")
+                        .about("show the path of the configuration file"),
+                ),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("config", Some(matches)) => match matches.subcommand() {
+                ("init", Some(matches)) => ArgMatchResult::Ok(init_invocation(matches)),
+                (name, Some(matches)) => {
+                    let key = || matches.value_of("key").unwrap().to_string();
+                    let action = match name {
+                        "get" => Action::Get { key: key() },
+                        "set" => Action::Set {
+                            key: key(),
+                            value: matches.value_of("value").unwrap().into(),
+                        },
+                        "unset" => Action::Unset { key: key() },
+                        "list" => Action::List,
+                        "path" => Action::Path,
+                        _ => unreachable!(),
+                    };
+                    ArgMatchResult::Ok(Box::new(Invocation { action }))
+                }
+                _ => ArgMatchResult::Err(format_err!("a config subcommand is required")),
+            },
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let path = settings::config_file_path()
+            .ok_or_else(|| format_err!("Could not find the configuration directory"))?;
+        // the path is useful even when the file cannot be loaded, e.g., to fix it
+        if self.action == Action::Path {
+            println!("{}", path.display());
+            return Ok(());
+        }
+        let mut file = ConfigFile::load(path)?;
+        match self.action {
+            Action::Init(ref options) => {
+                let interactive = atty::is(atty::Stream::Stdin);
+                let changed = init(
+                    &mut file,
+                    options,
+                    &mut |question| {
+                        if !interactive {
+                            return Ok(None);
+                        }
+                        print!("{} ", question);
+                        io::stdout().flush()?;
+                        let mut answer = String::new();
+                        io::stdin().lock().read_line(&mut answer)?;
+                        Ok(Some(answer.trim().to_string()))
+                    },
+                    &mut |origin| RemoteServer::new(origin.into(), Uuid::nil()).server_info(),
+                    &mut io::stdout(),
+                )?;
+                if changed {
+                    file.save()?;
+                    println!("Wrote configuration to {:?}", file.path());
+                } else {
+                    println!("Configuration in {:?} is already complete", file.path());
+                }
+            }
+            Action::Get { ref key } => {
+                let settings = command.get_settings()?;
+                match settings.get_str(key) {
+                    Ok(value) => println!("{}", value),
+                    Err(ConfigError::NotFound(_)) => {
+                        return Err(format_err!("`{}` is not set", key))
+                    }
+                    Err(e) => return Err(format_err!("Cannot get `{}`: {}", key, e)),
+                }
+            }
+            Action::Set { ref key, ref value } => {
+                file.set(key, Yaml::String(value.clone()))?;
+                file.save()?;
+                if let Some(Origin::Environment(var)) = settings::origin(key, &file, &settings::env_var_names()) {
+                    eprintln!("Note: ${} overrides this setting", var);
+                }
+            }
+            Action::Unset { ref key } => {
+                if !file.unset(key) {
+                    return Err(format_err!("`{}` is not set in {:?}", key, file.path()));
+                }
+                file.save()?;
+            }
+            Action::List => {
+                let settings = command.get_settings()?;
+                let mut t = Table::new();
+                t.set_format(table::format());
+                t.set_titles(row![b->"key", b->"value", b->"origin"]);
+                let env_vars = settings::env_var_names();
+                for key in settings::all_keys(&file, &env_vars) {
+                    if let (Ok(value), Some(origin)) =
+                        (settings.get_str(&key), settings::origin(&key, &file, &env_vars))
+                    {
+                        t.add_row(row![key, value, origin]);
+                    }
+                }
+                t.printstd();
+            }
+            Action::Path => unreachable!("handled above"),
+        }
+        Ok(())
+    }
+}
+
+/// Fill in any missing settings required to use this replica and sync it with a server, never
+/// overwriting existing settings.  The `prompt` function asks the user a question, returning
+/// None if the user cannot be asked; `check_server` fetches the server's index page.  Returns
+/// true if the file was changed.
+fn init(
+    file: &mut ConfigFile,
+    options: &InitOptions,
+    prompt: &mut dyn FnMut(&str) -> Fallible<Option<String>>,
+    check_server: &mut dyn FnMut(&str) -> Fallible<String>,
+    out: &mut dyn Write,
+) -> Fallible<bool> {
+    let original = file.values();
+    let changed = |file: &ConfigFile| file.values() != original;
+    fn set(file: &mut ConfigFile, out: &mut dyn Write, key: &str, value: String) -> Fallible<()> {
+        writeln!(out, "Setting {} to {}", key, value)?;
+        file.set(key, Yaml::String(value))
+    }
+
+    if file.get("data_dir").is_none() {
+        if let Some(dir) = settings::default_data_dir() {
+            set(file, out, "data_dir", dir)?;
+        }
+    }
+
+    if file.get_str("server.type").as_deref() == Some("local") {
+        writeln!(
+            out,
+            "The default server is local; not configuring a sync server"
+        )?;
+        return Ok(changed(file));
+    }
+
+    // older configurations use top-level keys for the server, and those continue to be used if
+    // present, so that the server section does not suddenly override them
+    let legacy = file.get("server").is_none() && file.get("server_origin").is_some();
+    let key = |k: &str| {
+        if legacy {
+            format!("server_{}", k)
+        } else {
+            format!("server.{}", k)
+        }
+    };
+
+    let origin = match file.get_str(&key("origin")) {
+        Some(origin) => {
+            if options.origin.is_some() && options.origin.as_ref() != Some(&origin) {
+                writeln!(out, "Keeping existing {} {}", key("origin"), origin)?;
+            }
+            origin
+        }
+        None => {
+            let origin = match options.origin {
+                Some(ref origin) => Some(origin.clone()),
+                None => prompt(
+                    "Sync server origin (e.g., https://taskchampion.example.com; blank to skip):",
+                )?,
+            };
+            match origin {
+                Some(origin) if !origin.is_empty() => {
+                    let origin = origin.trim_end_matches('/').to_string();
+                    set(file, out, &key("origin"), origin.clone())?;
+                    origin
+                }
+                _ => {
+                    writeln!(out, "No sync server configured")?;
+                    return Ok(changed(file));
+                }
+            }
+        }
+    };
+
+    if file.get(&key("client_id")).is_none() {
+        set(file, out, &key("client_id"), Uuid::new_v4().to_string())?;
+    }
+
+    if !options.no_check {
+        match check_server(&origin) {
+            Ok(info) => writeln!(out, "Server at {} is {}", origin, info.trim())?,
+            Err(e) => writeln!(
+                out,
+                "Warning: could not reach server at {}: {}",
+                origin,
+                e.to_string().trim()
+            )?,
+        }
+    }
+
+    Ok(changed(file))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn parse_init() {
+        with_subcommand_invocation!(vec!["task", "config", "init"], |inv: &Invocation| {
+            assert_eq!(inv.action, Action::Init(InitOptions::default()));
+        });
+    }
+
+    #[test]
+    fn parse_sync_init() {
+        with_subcommand_invocation!(
+            vec![
+                "task",
+                "sync",
+                "init",
+                "--origin",
+                "https://tc.example.com",
+                "--no-check"
+            ],
+            |inv: &Invocation| {
+                assert_eq!(
+                    inv.action,
+                    Action::Init(InitOptions {
+                        origin: Some("https://tc.example.com".into()),
+                        no_check: true,
+                    })
+                );
+            }
+        );
+    }
+
+    #[test]
+    fn parse_get() {
+        with_subcommand_invocation!(
+            vec!["task", "config", "get", "data_dir"],
+            |inv: &Invocation| {
+                assert_eq!(
+                    inv.action,
+                    Action::Get {
+                        key: "data_dir".into()
+                    }
+                );
+            }
+        );
+    }
+
+    #[test]
+    fn parse_set() {
+        with_subcommand_invocation!(
+            vec![
+                "task",
+                "config",
+                "set",
+                "server.origin",
+                "https://tc.example.com"
+            ],
+            |inv: &Invocation| {
+                assert_eq!(
+                    inv.action,
+                    Action::Set {
+                        key: "server.origin".into(),
+                        value: "https://tc.example.com".into()
+                    }
+                );
+            }
+        );
+    }
+
+    #[test]
+    fn parse_unset() {
+        with_subcommand_invocation!(
+            vec!["task", "config", "unset", "data_dir"],
+            |inv: &Invocation| {
+                assert_eq!(
+                    inv.action,
+                    Action::Unset {
+                        key: "data_dir".into()
+                    }
+                );
+            }
+        );
+    }
+
+    #[test]
+    fn parse_list_path() {
+        with_subcommand_invocation!(vec!["task", "config", "list"], |inv: &Invocation| {
+            assert_eq!(inv.action, Action::List);
+        });
+        with_subcommand_invocation!(vec!["task", "config", "path"], |inv: &Invocation| {
+            assert_eq!(inv.action, Action::Path);
+        });
+    }
+
+    #[test]
+    fn parse_no_subcommand() {
+        assert!(crate::parse_test_command_line(vec!["task", "config"]).is_err());
+    }
+
+    fn run_init(
+        file: &mut ConfigFile,
+        options: InitOptions,
+        answers: Vec<&str>,
+    ) -> Fallible<(bool, Vec<String>)> {
+        let mut answers = answers.into_iter();
+        let mut checked = vec![];
+        let mut out = vec![];
+        let changed = init(
+            file,
+            &options,
+            &mut |_| Ok(answers.next().map(|a| a.to_string())),
+            &mut |origin| {
+                checked.push(origin.to_string());
+                Ok("TaskChampion sync server v0.2.0".into())
+            },
+            &mut out,
+        )?;
+        assert_eq!(answers.next(), None, "not all answers were used");
+        Ok((changed, checked))
+    }
+
+    #[test]
+    fn init_empty() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut file = ConfigFile::load(tmp_dir.path().join("taskchampion.yaml"))?;
+        let (changed, checked) = run_init(
+            &mut file,
+            InitOptions::default(),
+            vec!["https://tc.example.com/"],
+        )?;
+        assert!(changed);
+        assert_eq!(checked, vec!["https://tc.example.com".to_string()]);
+        assert_eq!(
+            file.get_str("server.origin"),
+            Some("https://tc.example.com".into())
+        );
+        Uuid::parse_str(&file.get_str("server.client_id").unwrap())?;
+        assert!(file.get("data_dir").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn init_no_overwrite() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let path = tmp_dir.path().join("taskchampion.yaml");
+        fs::write(
+            &path,
+            "data_dir: /tmp/tasks\nserver_origin: https://old.example.com\nserver_client_id: abc\n",
+        )?;
+        let mut file = ConfigFile::load(&path)?;
+        let options = InitOptions {
+            origin: Some("https://new.example.com".into()),
+            no_check: true,
+        };
+        let (changed, checked) = run_init(&mut file, options, vec![])?;
+        assert!(!changed);
+        assert!(checked.is_empty());
+        assert_eq!(file.get_str("data_dir"), Some("/tmp/tasks".into()));
+        assert_eq!(
+            file.get_str("server_origin"),
+            Some("https://old.example.com".into())
+        );
+        assert_eq!(file.get_str("server_client_id"), Some("abc".into()));
+        assert!(file.get("server").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn init_not_interactive() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut file = ConfigFile::load(tmp_dir.path().join("taskchampion.yaml"))?;
+        let (changed, _) = run_init(&mut file, InitOptions::default(), vec![])?;
+        assert!(changed);
+        assert!(file.get("server").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::output::{self, Format};
+use crate::settings::{self, ConfigFile, Origin};
+use clap::{App, Arg, ArgMatches, SubCommand as ClapSubCommand};
+use failure::{format_err, Fallible};
+use serde_json::json;
+use yaml_rust::Yaml;
+
+use crate::cmd::{ArgMatchResult, CommandInvocation};
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    /// Show the defined contexts, and which is active
+    Show,
+    /// Make the named context active
+    Set(String),
+    /// Make no context active
+    Clear,
+}
+
+#[derive(Debug)]
+struct Invocation {
+    action: Action,
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("context")
+                .about("show or set the context, a filter applied to reports and new tasks")
+                .arg(
+                    Arg::with_name("name")
+                        .help("context to make active, or `none` to clear the context"),
+                ),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("context", Some(matches)) => {
+                let action = match matches.value_of("name") {
+                    None => Action::Show,
+                    Some("none") => Action::Clear,
+                    Some(name) => Action::Set(name.into()),
+                };
+                ArgMatchResult::Ok(Box::new(Invocation { action }))
+            }
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let contexts = settings::contexts(&*command.get_settings()?)?;
+        let path = settings::config_file_path()
+            .ok_or_else(|| format_err!("Could not find the configuration directory"))?;
+        let mut file = ConfigFile::load(path)?;
+        match self.action {
+            Action::Show => {
+                let active = command.get_settings()?.get_str("context").ok();
+                if contexts.is_empty() && command.format == Format::Table {
+                    println!("No contexts are defined; see the `contexts` setting.");
+                    return Ok(());
+                }
+                let mut t = output::Table::new(&["name", "filter", "active"]);
+                let mut json = vec![];
+                for (name, filter) in contexts.iter() {
+                    let is_active = active.as_ref() == Some(name);
+                    t.add_row(&[name.as_ref(), filter.as_ref(), if is_active { "*" } else { "" }]);
+                    json.push(json!({
+                        "name": name,
+                        "filter": filter,
+                        "active": is_active,
+                    }));
+                }
+                command.write_output(&[t], &json.into())?;
+            }
+            Action::Set(ref name) => {
+                if !contexts.contains_key(name) {
+                    return Err(format_err!(
+                        "No context named {:?}; define it as `contexts.{}` in the configuration",
+                        name,
+                        name
+                    ));
+                }
+                file.set("context", Yaml::String(name.clone()))?;
+                file.save()?;
+                println!("Context set to {}.", name);
+                if let Some(Origin::Environment(var)) = settings::origin("context", &file, &settings::env_var_names()) {
+                    eprintln!("Note: ${} overrides this setting", var);
+                }
+            }
+            Action::Clear => {
+                if file.unset("context") {
+                    file.save()?;
+                }
+                println!("Context cleared.");
+                if let Some(Origin::Environment(var)) = settings::origin("context", &file, &settings::env_var_names()) {
+                    eprintln!("Note: ${} overrides this setting", var);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(vec!["task", "context"], |inv: &Invocation| {
+            assert_eq!(inv.action, Action::Show);
+        });
+    }
+
+    #[test]
+    fn parse_command_set() {
+        with_subcommand_invocation!(vec!["task", "context", "work"], |inv: &Invocation| {
+            assert_eq!(inv.action, Action::Set("work".into()));
+        });
+    }
+
+    #[test]
+    fn parse_command_clear() {
+        with_subcommand_invocation!(vec!["task", "context", "none"], |inv: &Invocation| {
+            assert_eq!(inv.action, Action::Clear);
+        });
+    }
+}
@@ -1,7 +1,7 @@
-use crate::table;
+use crate::output;
 use clap::{App, ArgMatches, SubCommand as ClapSubCommand};
 use failure::Fallible;
-use prettytable::{cell, row, Table};
+use serde_json::{json, Map};
 
 use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
 
@@ -33,13 +33,17 @@ subcommand_invocation! {
         let mut replica = command.get_replica()?;
         let task = shared::get_task(&mut replica, &self.task)?;
 
-        let mut t = Table::new();
-        t.set_format(table::format());
-        t.set_titles(row![b->"key", b->"value"]);
+        let mut t = output::Table::new(&["key", "value"]);
+        let mut taskmap = Map::new();
         for (k, v) in task.get_taskmap().iter() {
-            t.add_row(row![k, v]);
+            t.add_row(&[k, v]);
+            taskmap.insert(k.clone(), v.clone().into());
         }
-        t.printstd();
+        let json = json!({
+            "uuid": task.get_uuid().to_string(),
+            "taskmap": taskmap,
+        });
+        command.write_output(&[t], &json)?;
         Ok(())
     }
 }
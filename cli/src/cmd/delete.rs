@@ -1,27 +1,30 @@
-use clap::{App, ArgMatches, SubCommand as ClapSubCommand};
+use clap::{App, AppSettings, ArgMatches, SubCommand as ClapSubCommand};
 use failure::Fallible;
 use taskchampion::Status;
 
 use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
+use crate::filter::Filter;
 
 #[derive(Debug)]
 struct Invocation {
-    task: String,
+    filter: Filter,
 }
 
 define_subcommand! {
     fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
         app.subcommand(
             ClapSubCommand::with_name("delete")
-                .about("mark the given task as deleted")
-                .arg(shared::task_arg()))
+                .about("mark the given tasks as deleted")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(shared::filter_arg()))
     }
 
     fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
         match matches.subcommand() {
-            ("delete", Some(matches)) => ArgMatchResult::Ok(Box::new(Invocation {
-                task: matches.value_of("task").unwrap().into(),
-            })),
+            ("delete", Some(matches)) => match shared::get_filter(matches) {
+                Ok(filter) => ArgMatchResult::Ok(Box::new(Invocation { filter })),
+                Err(e) => ArgMatchResult::Err(e),
+            },
             _ => ArgMatchResult::None,
         }
     }
@@ -29,23 +32,22 @@ define_subcommand! {
 
 subcommand_invocation! {
     fn run(&self, command: &CommandInvocation) -> Fallible<()> {
-        let mut replica = command.get_replica()?;
-        let task = shared::get_task(&mut replica, &self.task)?;
-        let mut task = task.into_mut(&mut replica);
-        task.stop()?;
-        task.set_status(Status::Deleted)?;
-        Ok(())
+        shared::modify_tasks(command, &self.filter, "delete", "Deleted", |task| {
+                task.stop()?;
+                task.set_status(Status::Deleted)
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::filter::TaskId;
 
     #[test]
     fn parse_command() {
         with_subcommand_invocation!(vec!["task", "delete", "1"], |inv: &Invocation| {
-            assert_eq!(inv.task, "1".to_string());
+            assert_eq!(inv.filter.ids, vec![TaskId::WorkingSetId(1)]);
         });
     }
 }
@@ -0,0 +1,81 @@
+use clap::{App, Arg, ArgMatches, SubCommand as ClapSubCommand};
+use failure::{format_err, Fallible};
+
+use crate::cmd::{ArgMatchResult, CommandInvocation};
+
+#[derive(Debug)]
+struct Invocation {
+    repair: bool,
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("diagnostics")
+                .about("check the task database for inconsistencies")
+                .arg(
+                    Arg::with_name("repair")
+                        .long("repair")
+                        .help("fix any problems found"),
+                ),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("diagnostics", Some(matches)) => ArgMatchResult::Ok(Box::new(Invocation {
+                repair: matches.is_present("repair"),
+            })),
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let mut replica = command.get_replica()?;
+        let problems = if self.repair {
+            replica.repair()?
+        } else {
+            replica.check()?
+        };
+        if problems.is_empty() {
+            println!("no problems found");
+            return Ok(());
+        }
+        for problem in problems.iter() {
+            println!("{}", problem);
+        }
+        if self.repair {
+            println!("repaired {} problem(s)", problems.len());
+            Ok(())
+        } else {
+            Err(format_err!(
+                "found {} problem(s); run `task diagnostics --repair` to fix them",
+                problems.len()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(vec!["task", "diagnostics"], |inv: &Invocation| {
+            assert!(!inv.repair);
+        });
+    }
+
+    #[test]
+    fn parse_command_repair() {
+        with_subcommand_invocation!(
+            vec!["task", "diagnostics", "--repair"],
+            |inv: &Invocation| {
+                assert!(inv.repair);
+            }
+        );
+    }
+}
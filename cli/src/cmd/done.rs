@@ -1,27 +1,30 @@
-use clap::{App, ArgMatches, SubCommand as ClapSubCommand};
+use clap::{App, AppSettings, ArgMatches, SubCommand as ClapSubCommand};
 use failure::Fallible;
 use taskchampion::Status;
 
 use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
+use crate::filter::Filter;
 
 #[derive(Debug)]
 struct Invocation {
-    task: String,
+    filter: Filter,
 }
 
 define_subcommand! {
     fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
         app.subcommand(
             ClapSubCommand::with_name("done")
-                .about("finish the given task (status Completed)")
-                .arg(shared::task_arg()))
+                .about("finish the given tasks (status Completed)")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(shared::filter_arg()))
     }
 
     fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
         match matches.subcommand() {
-            ("done", Some(matches)) => ArgMatchResult::Ok(Box::new(Invocation {
-                task: matches.value_of("task").unwrap().into(),
-            })),
+            ("done", Some(matches)) => match shared::get_filter(matches) {
+                Ok(filter) => ArgMatchResult::Ok(Box::new(Invocation { filter })),
+                Err(e) => ArgMatchResult::Err(e),
+            },
             _ => ArgMatchResult::None,
         }
     }
@@ -29,23 +32,41 @@ define_subcommand! {
 
 subcommand_invocation! {
     fn run(&self, command: &CommandInvocation) -> Fallible<()> {
-        let mut replica = command.get_replica()?;
-        let task = shared::get_task(&mut replica, &self.task)?;
-        let mut task = task.into_mut(&mut replica);
-        task.stop()?;
-        task.set_status(Status::Completed)?;
-        Ok(())
+        shared::modify_tasks(command, &self.filter, "complete", "Completed", |task| {
+                task.stop()?;
+                task.set_status(Status::Completed)
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::filter::TaskId;
 
     #[test]
     fn parse_command() {
         with_subcommand_invocation!(vec!["task", "done", "1"], |inv: &Invocation| {
-            assert_eq!(inv.task, "1".to_string());
+            assert_eq!(inv.filter.ids, vec![TaskId::WorkingSetId(1)]);
         });
     }
+
+    #[test]
+    fn parse_command_filter() {
+        with_subcommand_invocation!(
+            vec!["task", "done", "-later", "1,2", "+next"],
+            |inv: &Invocation| {
+                assert_eq!(
+                    inv.filter.ids,
+                    vec![TaskId::WorkingSetId(1), TaskId::WorkingSetId(2)]
+                );
+                assert_eq!(inv.filter.conditions.len(), 2);
+            }
+        );
+    }
+
+    #[test]
+    fn parse_command_invalid_filter() {
+        assert!(crate::parse_test_command_line(vec!["task", "done", "nosuch"]).is_err());
+    }
 }
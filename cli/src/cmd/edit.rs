@@ -0,0 +1,123 @@
+use crate::edit::Edit;
+use chrono::Utc;
+use clap::{App, ArgMatches, SubCommand as ClapSubCommand};
+use failure::{format_err, Fallible};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command as Process;
+use tempdir::TempDir;
+
+use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
+
+#[derive(Debug)]
+struct Invocation {
+    task: String,
+}
+
+/// The prefix of the error message added to the document when it cannot be parsed
+const ERROR_PREFIX: &str = "# Error: ";
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("edit")
+                .about("edit all of a task's properties in $EDITOR")
+                .arg(shared::task_arg()),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("edit", Some(matches)) => ArgMatchResult::Ok(Box::new(Invocation {
+                task: matches.value_of("task").unwrap().into(),
+            })),
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let mut replica = command.get_replica()?;
+        let task = shared::get_task(&mut replica, &self.task)?;
+        let label = shared::task_label(&mut replica, &task)?;
+        let original = Edit::from_task(&task);
+
+        // the document is written in a new directory which only this user can read, and which is
+        // removed afterward
+        let dir = TempDir::new("task-edit")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o700))?;
+        }
+        let path = dir.path().join(format!("task-{}.txt", task.get_uuid()));
+        let header = format!("# Editing task {} ({})\n", label, task.get_uuid());
+        let edit = edit_document(&path, original.render(&header))?;
+
+        if edit == original {
+            println!("No changes made to task {}.", label);
+            return Ok(());
+        }
+        shared::modify_task(command, &mut replica, task, |t| edit.apply(&original, t))?;
+        println!("Edited task {}.", label);
+        Ok(())
+    }
+}
+
+/// Let the user edit the document until it parses, adding the error to the top of the document
+/// each time it does not.  If the user makes no changes after an error, the edit is abandoned.
+fn edit_document(path: &Path, doc: String) -> Fallible<Edit> {
+    let mut doc = doc;
+    loop {
+        let edited = run_editor(path, &doc)?;
+        match Edit::parse(&edited, Utc::now()) {
+            Ok(edit) => return Ok(edit),
+            Err(e) if edited == doc => {
+                return Err(format_err!("{}; the task was not changed", e));
+            }
+            Err(e) => {
+                let rest: Vec<&str> = edited
+                    .lines()
+                    .skip_while(|l| l.starts_with(ERROR_PREFIX))
+                    .collect();
+                doc = format!("{}{}\n{}\n", ERROR_PREFIX, e, rest.join("\n"));
+            }
+        }
+    }
+}
+
+/// Write the document to the file, open it in `$VISUAL` or `$EDITOR` (default `vi`), and read
+/// the result.  The editor command may include arguments, such as `code --wait`.
+fn run_editor(path: &Path, doc: &str) -> Fallible<String> {
+    fs::write(path, doc)?;
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".into());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap();
+    let status = Process::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(|e| format_err!("Could not run editor {:?}: {}", editor, e))?;
+    if !status.success() {
+        return Err(format_err!("Editor {:?} failed ({})", editor, status));
+    }
+    Ok(fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(vec!["task", "edit", "3"], |inv: &Invocation| {
+            assert_eq!(inv.task, "3".to_string());
+        });
+    }
+}
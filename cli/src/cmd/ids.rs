@@ -0,0 +1,74 @@
+use clap::{App, AppSettings, ArgMatches, SubCommand as ClapSubCommand};
+use failure::Fallible;
+use std::io::{self, Write};
+use taskchampion::Replica;
+
+use crate::cmd::{ArgMatchResult, CommandInvocation};
+
+#[derive(Debug)]
+struct Invocation {}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("_ids")
+                .about("list working-set ids and descriptions, for shell completion")
+                .setting(AppSettings::Hidden),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("_ids", Some(_)) => ArgMatchResult::Ok(Box::new(Invocation {})),
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let mut replica = command.get_replica()?;
+        let stdout = io::stdout();
+        write_ids(&mut replica, &mut stdout.lock())
+    }
+}
+
+/// Write a line for each task in the working set, giving its id and description separated by a
+/// tab, as both the zsh and fish completion scripts expect.
+fn write_ids<W: Write>(replica: &mut Replica, w: &mut W) -> Fallible<()> {
+    for (i, task) in replica.working_set()?.iter().enumerate() {
+        if let Some(task) = task {
+            let description = task.get_description().replace(&['\t', '\n'][..], " ");
+            writeln!(w, "{}\t{}", i, description)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use taskchampion::{ReplicaConfig, Status};
+    use tempdir::TempDir;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(vec!["task", "_ids"], |_inv: &Invocation| {});
+    }
+
+    #[test]
+    fn ids() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut replica = Replica::from_config(ReplicaConfig {
+            taskdb_dir: tmp_dir.path().to_path_buf(),
+        })?;
+        replica.new_task(Status::Pending, "first".into())?;
+        replica.new_task(Status::Pending, "second\tline".into())?;
+        replica.new_task(Status::Completed, "done".into())?;
+
+        let mut out = vec![];
+        write_ids(&mut replica, &mut out)?;
+        assert_eq!(String::from_utf8(out)?, "1\tfirst\n2\tsecond line\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use clap::{App, Arg, ArgMatches, SubCommand as ClapSubCommand};
+use failure::Fallible;
+use std::fs::File;
+use std::io;
+use taskchampion::taskwarrior;
+
+use crate::cmd::{ArgMatchResult, CommandInvocation};
+
+#[derive(Debug)]
+struct Invocation {
+    file: Option<String>,
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("import")
+                .about("import tasks in TaskWarrior's JSON format")
+                .arg(
+                    Arg::with_name("file")
+                        .help("file to import (default stdin)"),
+                ),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("import", Some(matches)) => ArgMatchResult::Ok(Box::new(Invocation {
+                file: matches.value_of("file").map(|s| s.into()),
+            })),
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let mut replica = command.get_replica()?;
+        let report = match self.file {
+            Some(ref file) if file != "-" => {
+                taskwarrior::import_json(&mut replica, File::open(file)?)?
+            }
+            _ => taskwarrior::import_json(&mut replica, io::stdin())?,
+        };
+        println!(
+            "imported {} new tasks, updated {}, {} unchanged",
+            report.created, report.updated, report.unchanged
+        );
+        for (i, reason) in report.skipped.iter() {
+            eprintln!("skipped record {}: {}", i, reason);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(vec!["task", "import"], |inv: &Invocation| {
+            assert_eq!(inv.file, None);
+        });
+    }
+
+    #[test]
+    fn parse_command_file() {
+        with_subcommand_invocation!(vec!["task", "import", "tasks.json"], |inv: &Invocation| {
+            assert_eq!(inv.file, Some("tasks.json".to_string()));
+        });
+    }
+}
@@ -1,28 +1,34 @@
-use crate::table;
-use clap::{App, ArgMatches, SubCommand as ClapSubCommand};
+use crate::filter::Filter;
+use crate::output::{self, Format};
+use crate::report::Context;
+use crate::settings;
+use chrono::Utc;
+use clap::{App, AppSettings, ArgMatches, SubCommand as ClapSubCommand};
 use failure::Fallible;
-use prettytable::{cell, row, Table};
+use serde_json::json;
 
 use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
 
 #[derive(Debug)]
 struct Invocation {
-    task: String,
+    filter: Filter,
 }
 
 define_subcommand! {
     fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
         app.subcommand(
             ClapSubCommand::with_name("info")
-                .about("info on the given task")
-                .arg(shared::task_arg()))
+                .about("info on the given tasks")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(shared::filter_arg()))
     }
 
     fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
         match matches.subcommand() {
-            ("info", Some(matches)) => ArgMatchResult::Ok(Box::new(Invocation {
-                task: matches.value_of("task").unwrap().into(),
-            })),
+            ("info", Some(matches)) => match shared::get_filter(matches) {
+                Ok(filter) => ArgMatchResult::Ok(Box::new(Invocation { filter })),
+                Err(e) => ArgMatchResult::Err(e),
+            },
             _ => ArgMatchResult::None,
         }
     }
@@ -30,32 +36,97 @@ define_subcommand! {
 
 subcommand_invocation! {
     fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let coefficients = settings::urgency_coefficients(&*command.get_settings()?)?;
         let mut replica = command.get_replica()?;
-        let task = shared::get_task(&mut replica, &self.task)?;
-        let uuid = task.get_uuid();
-
-        let mut t = Table::new();
-        t.set_format(table::format());
-        t.add_row(row![b->"Uuid", uuid]);
-        if let Some(i) = replica.get_working_set_index(uuid)? {
-            t.add_row(row![b->"Id", i]);
+        let tasks = self.filter.tasks(&mut replica)?;
+        let context = Context::new(&mut replica, Utc::now(), coefficients)?;
+        if tasks.is_empty() && command.format == Format::Table {
+            println!("No matching tasks.");
+            return Ok(());
         }
-        t.add_row(row![b->"Description", task.get_description()]);
-        t.add_row(row![b->"Status", task.get_status()]);
-        t.add_row(row![b->"Active", task.is_active()]);
-        t.printstd();
-        Ok(())
+
+        let mut tables = vec![];
+        // CSV cannot separate tables, so it has a single table of all properties of all tasks
+        let mut csv = output::Table::new(&["uuid", "property", "value"]);
+        let mut json = vec![];
+        for task in tasks.iter() {
+            let uuid = task.get_uuid().to_string();
+            let mut t = output::Table::new_labeled(&["property", "value"]);
+            t.set_flexible(1);
+            t.add_row(&["Uuid", &task.get_uuid().to_string()]);
+            if let Some(i) = context.id(task) {
+                t.add_row(&["Id", &i.to_string()]);
+            }
+            t.add_row(&["Description", task.get_description()]);
+            t.add_row(&["Status", task.get_status().to_string()]);
+            t.add_row(&["Active", &task.is_active().to_string()]);
+            if let Some(project) = task.get_project() {
+                t.add_row(&["Project", project]);
+            }
+            if let Some(priority) = task.get_priority() {
+                t.add_row(&["Priority", &format!("{:?}", priority)]);
+            }
+            let tags = task.get_tags();
+            if !tags.is_empty() {
+                t.add_row(&["Tags", &tags.join(" ")]);
+            }
+            if let Some(due) = task.get_due() {
+                t.add_row(&["Due", &due.to_string()]);
+            }
+            if let Some(wait) = task.get_wait() {
+                t.add_row(&["Wait", &wait.to_string()]);
+            }
+            let urgency = context.urgency(task);
+            t.add_row(&["Urgency", &format!("{:.2}", urgency.value())]);
+            for row in t.rows.iter() {
+                csv.add_row(&[&uuid, &row[0], &row[1]]);
+            }
+            tables.push(t);
+
+            let mut record = context.task_json(task);
+            let mut terms = vec![];
+            if !urgency.terms.is_empty() {
+                let mut t = output::Table::new(&["urgency term", "factor", "coefficient", "value"]);
+                for term in urgency.terms.iter() {
+                    t.add_row(&[
+                        term.name.clone(),
+                        format!("{:.3}", term.factor),
+                        format!("{:.2}", term.coefficient),
+                        format!("{:.2}", term.value()),
+                    ]);
+                    csv.add_row(&[
+                        uuid.clone(),
+                        format!("Urgency {}", term.name),
+                        format!("{:.2}", term.value()),
+                    ]);
+                    terms.push(json!({
+                        "name": term.name,
+                        "factor": term.factor,
+                        "coefficient": term.coefficient,
+                        "value": term.value(),
+                    }));
+                }
+                tables.push(t);
+            }
+            record["urgency_terms"] = terms.into();
+            json.push(record);
+        }
+        if command.format == Format::Csv {
+            tables = vec![csv];
+        }
+        command.write_output(&tables, &json.into())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::filter::TaskId;
 
     #[test]
     fn parse_command() {
         with_subcommand_invocation!(vec!["task", "info", "1"], |inv: &Invocation| {
-            assert_eq!(inv.task, "1".to_string());
+            assert_eq!(inv.filter.ids, vec![TaskId::WorkingSetId(1)]);
         });
     }
 }
@@ -34,7 +34,7 @@ macro_rules! subcommand_invocation {
 #[cfg(test)]
 macro_rules! with_subcommand_invocation {
     ($args:expr, $check:expr) => {
-        let parsed = crate::parse_command_line($args).unwrap();
+        let parsed = crate::parse_test_command_line($args).unwrap();
         let si = parsed
             .subcommand
             .as_any()
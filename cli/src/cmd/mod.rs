@@ -6,33 +6,66 @@ mod macros;
 mod shared;
 
 mod add;
+mod backup;
+mod burndown;
+mod completions;
+mod config;
+mod context;
 mod debug;
 mod delete;
+mod diagnostics;
 mod done;
+mod edit;
+mod export;
 mod gc;
+mod ids;
+mod import;
 mod info;
-mod list;
 mod modify;
-mod pending;
+mod report;
+mod restore;
 mod start;
+mod stats;
 mod stop;
+mod summary;
 mod sync;
+mod timesheet;
 
 /// Get a list of all subcommands in this crate
 pub(crate) fn subcommands() -> Vec<Box<dyn SubCommand>> {
+    let mut subcommands = user_subcommands();
+    subcommands.push(ids::cmd());
+    subcommands
+}
+
+/// Get a list of the subcommands meant for users, omitting the hidden helpers used by completion
+/// scripts
+pub(crate) fn user_subcommands() -> Vec<Box<dyn SubCommand>> {
     vec![
         add::cmd(),
+        backup::cmd(),
+        burndown::cmd(),
+        completions::cmd(),
+        config::cmd(),
+        context::cmd(),
         debug::cmd(),
         delete::cmd(),
+        diagnostics::cmd(),
         done::cmd(),
+        edit::cmd(),
+        export::cmd(),
         gc::cmd(),
+        import::cmd(),
         info::cmd(),
-        list::cmd(),
         modify::cmd(),
-        pending::cmd(),
+        report::cmd(),
+        restore::cmd(),
         start::cmd(),
+        stats::cmd(),
         stop::cmd(),
+        summary::cmd(),
         sync::cmd(),
+        timesheet::cmd(),
     ]
 }
 
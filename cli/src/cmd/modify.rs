@@ -1,23 +1,32 @@
 use crate::cmd::shared;
-use clap::{App, Arg, ArgMatches, SubCommand as ClapSubCommand};
-use failure::Fallible;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand as ClapSubCommand};
+use failure::{format_err, Fallible};
 
 use crate::cmd::{ArgMatchResult, CommandInvocation};
+use crate::filter::Filter;
+use crate::modification::Modification;
 
 #[derive(Debug)]
 struct Invocation {
-    task: String,
-    description: String,
+    filter: Filter,
+    modification: Modification,
 }
 
 define_subcommand! {
     fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
         app.subcommand(
-            ClapSubCommand::with_name("modify").about("modifies a task")
-                .arg(shared::task_arg())
+            ClapSubCommand::with_name("modify").about("modifies tasks")
+                .setting(AppSettings::AllowLeadingHyphen)
             .arg(
-                Arg::with_name("description")
-                    .help("task description")
+                Arg::with_name("filter")
+                    .help("tasks to modify, such as 1,3,5-7; quote several terms, as in \"+tag project:name\"")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("args")
+                    .help("new description and attributes, such as project:home, +tag, or due:")
+                    .multiple(true)
+                    .allow_hyphen_values(true)
                     .required(true),
             ),
         )
@@ -25,10 +34,26 @@ define_subcommand! {
 
     fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
         match matches.subcommand() {
-            ("modify", Some(matches)) => ArgMatchResult::Ok(Box::new(Invocation {
-                task: matches.value_of("task").unwrap().into(),
-                description: matches.value_of("description").unwrap().into(),
-            })),
+            ("modify", Some(matches)) => {
+                let filter: Vec<&str> = matches.value_of("filter").unwrap().split_whitespace().collect();
+                let filter = match Filter::parse(&filter) {
+                    Ok(f) if !f.is_empty() => f,
+                    Ok(_) => return ArgMatchResult::Err(format_err!("no tasks specified")),
+                    Err(e) => return ArgMatchResult::Err(e),
+                };
+                let args: Vec<&str> = matches.values_of("args").unwrap().collect();
+                let modification = match Modification::parse(&args) {
+                    Ok(m) => m,
+                    Err(e) => return ArgMatchResult::Err(e),
+                };
+                if modification == Modification::default() {
+                    return ArgMatchResult::Err(format_err!("no modifications provided"));
+                }
+                ArgMatchResult::Ok(Box::new(Invocation {
+                    filter,
+                    modification,
+                }))
+            }
             _ => ArgMatchResult::None,
         }
     }
@@ -36,27 +61,51 @@ define_subcommand! {
 
 subcommand_invocation! {
     fn run(&self, command: &CommandInvocation) -> Fallible<()> {
-        let mut replica = command.get_replica()?;
-        let task = shared::get_task(&mut replica, &self.task)?;
-
-        let mut task = task.into_mut(&mut replica);
-        task.set_description(self.description.clone())?;
-        println!("modified task {}", task.get_uuid());
-        Ok(())
+        shared::modify_tasks(command, &self.filter, "modify", "Modified", |task| {
+            self.modification.apply(task)
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::filter::{Condition, TaskId};
 
     #[test]
     fn parse_command() {
         with_subcommand_invocation!(
             vec!["task", "modify", "2", "foo bar"],
             |inv: &Invocation| {
-                assert_eq!(inv.task, "2".to_string());
-                assert_eq!(inv.description, "foo bar".to_string());
+                assert_eq!(inv.filter.ids, vec![TaskId::WorkingSetId(2)]);
+                assert_eq!(inv.modification.description, Some("foo bar".into()));
+            }
+        );
+    }
+
+    #[test]
+    fn parse_command_clear() {
+        with_subcommand_invocation!(
+            vec!["task", "modify", "2", "-next", "due:"],
+            |inv: &Invocation| {
+                assert_eq!(inv.modification.description, None);
+                assert_eq!(inv.modification.due, Some(None));
+                assert_eq!(inv.modification.remove_tags, vec!["next".to_string()]);
+            }
+        );
+    }
+
+    #[test]
+    fn parse_command_filter() {
+        with_subcommand_invocation!(
+            vec!["task", "modify", "+work 1-3", "+next"],
+            |inv: &Invocation| {
+                assert_eq!(inv.filter.ids, vec![TaskId::WorkingSetRange(1, 3)]);
+                assert_eq!(
+                    inv.filter.conditions,
+                    vec![Condition::HasTag("work".into())]
+                );
+                assert_eq!(inv.modification.add_tags, vec!["next".to_string()]);
             }
         );
     }
@@ -0,0 +1,149 @@
+use crate::filter::Filter;
+use crate::output;
+use crate::report::{Column, Context, Report, BUILTIN_REPORTS};
+use crate::settings;
+use chrono::Utc;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand as ClapSubCommand};
+use failure::Fallible;
+
+use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
+
+#[derive(Debug)]
+struct Invocation {
+    report: String,
+    filter: Filter,
+}
+
+/// An optional filter, added to the report's own filter
+fn filter_arg<'a>() -> Arg<'a, 'a> {
+    shared::filter_arg()
+        .required(false)
+        .help("additional filter terms, such as +tag or project:name")
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, mut app: App<'a, 'a>) -> App<'a, 'a> {
+        for builtin in BUILTIN_REPORTS {
+            let mut subcommand = ClapSubCommand::with_name(builtin.name)
+                .about(builtin.about)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(filter_arg());
+            if builtin.name == "list" {
+                subcommand = subcommand.alias("pending");
+            }
+            app = app.subcommand(subcommand);
+        }
+        app.subcommand(
+            ClapSubCommand::with_name("report")
+                .about("show the named report, as defined in the configuration")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(Arg::with_name("name").help("report name").required(true))
+                .arg(filter_arg()),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        let (report, matches) = match matches.subcommand() {
+            ("report", Some(matches)) => (matches.value_of("name").unwrap(), matches),
+            (name, Some(matches)) if BUILTIN_REPORTS.iter().any(|b| b.name == name) => (name, matches),
+            // default to the list report when no subcommand is given
+            ("", _) => {
+                return ArgMatchResult::Ok(Box::new(Invocation {
+                    report: "list".into(),
+                    filter: Filter::default(),
+                }))
+            }
+            _ => return ArgMatchResult::None,
+        };
+        let filter = match matches.values_of("filter") {
+            Some(args) => match Filter::parse(&args.collect::<Vec<_>>()) {
+                Ok(filter) => filter,
+                Err(e) => return ArgMatchResult::Err(e),
+            },
+            None => Filter::default(),
+        };
+        ArgMatchResult::Ok(Box::new(Invocation {
+            report: report.into(),
+            filter,
+        }))
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let settings = command.get_settings()?;
+        let mut report = Report::from_config(&settings, &self.report)?;
+        report.filter.extend(self.filter.clone());
+        if let Some((_, filter)) = settings::context(&settings)? {
+            report.filter.extend(filter);
+        }
+        let coefficients = settings::urgency_coefficients(&settings)?;
+
+        let mut replica = command.get_replica()?;
+        let context = Context::new(&mut replica, Utc::now(), coefficients)?;
+        let headings: Vec<_> = report.columns.iter().map(|c| c.heading()).collect();
+        let mut t = output::Table::new(&headings);
+        if let Some(i) = report.columns.iter().position(|c| *c == Column::Description) {
+            t.set_flexible(i);
+        }
+        let mut json = vec![];
+        for task in report.tasks(&mut replica, &context)? {
+            let row: Vec<_> = report
+                .columns
+                .iter()
+                .map(|c| c.render(&task, &context))
+                .collect();
+            t.add_row_with_state(&row, context.state(&task));
+            json.push(context.task_json(&task));
+        }
+        command.write_output(&[t], &json.into())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::Condition;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(vec!["task", "list"], |inv: &Invocation| {
+            assert_eq!(inv.report, "list".to_string());
+            assert!(inv.filter.is_empty());
+        });
+    }
+
+    #[test]
+    fn parse_command_pending() {
+        with_subcommand_invocation!(vec!["task", "pending"], |inv: &Invocation| {
+            assert_eq!(inv.report, "list".to_string());
+        });
+    }
+
+    #[test]
+    fn parse_command_default() {
+        with_subcommand_invocation!(vec!["task"], |inv: &Invocation| {
+            assert_eq!(inv.report, "list".to_string());
+        });
+    }
+
+    #[test]
+    fn parse_command_filter() {
+        with_subcommand_invocation!(vec!["task", "completed", "-work"], |inv: &Invocation| {
+            assert_eq!(inv.report, "completed".to_string());
+            assert_eq!(inv.filter.conditions, vec![Condition::NoTag("work".into())]);
+        });
+    }
+
+    #[test]
+    fn parse_command_named() {
+        with_subcommand_invocation!(
+            vec!["task", "report", "home", "+next"],
+            |inv: &Invocation| {
+                assert_eq!(inv.report, "home".to_string());
+                assert_eq!(inv.filter.conditions.len(), 1);
+            }
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use clap::{App, Arg, ArgMatches, SubCommand as ClapSubCommand};
+use failure::Fallible;
+use std::fs::File;
+
+use crate::cmd::{ArgMatchResult, CommandInvocation};
+
+#[derive(Debug)]
+struct Invocation {
+    file: String,
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("restore")
+                .about("restore a backup into an empty task database")
+                .arg(
+                    Arg::with_name("file")
+                        .help("backup file to restore")
+                        .required(true),
+                ),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("restore", Some(matches)) => ArgMatchResult::Ok(Box::new(Invocation {
+                file: matches.value_of("file").unwrap().into(),
+            })),
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let mut replica = command.get_replica()?;
+        replica.restore_snapshot(File::open(&self.file)?)?;
+        println!("restored from {}", self.file);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(vec!["task", "restore", "tasks.bak"], |inv: &Invocation| {
+            assert_eq!(inv.file, "tasks.bak".to_string());
+        });
+    }
+}
@@ -1,16 +1,246 @@
+use crate::dates::parse_date;
+use crate::filter::{short_uuid, Filter, MIN_UUID_PREFIX};
+use crate::hooks::{self, Hooks};
+use crate::output::{self, Format};
 use crate::settings;
-use clap::Arg;
+use crate::table::{Renderer, Terminal};
+use chrono::{DateTime, Utc};
+use clap::{Arg, ArgMatches};
 use config::{Config, ConfigError};
 use failure::{format_err, Fallible};
+use serde_json::Value;
 use std::cell::{Ref, RefCell};
-use taskchampion::{server, Replica, ReplicaConfig, ServerConfig, Task, Uuid};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use taskchampion::{
+    server, taskwarrior, Replica, ReplicaConfig, ServerConfig, Status, Task, TaskMut, Uuid,
+};
+use tempdir::TempDir;
 
 pub(super) fn task_arg<'a>() -> Arg<'a, 'a> {
     Arg::with_name("task")
-        .help("task id or uuid")
+        .help("task id, uuid, or uuid prefix")
         .required(true)
 }
 
+/// An argument for a filter selecting the tasks to operate on.  Subcommands using this should also
+/// set `AppSettings::AllowLeadingHyphen`, so that `-tag` is not taken for a flag.
+pub(super) fn filter_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("filter")
+        .help(
+            "task ids (such as 1,3,5-7 or a uuid prefix), +tag, -tag, project:name, or status:name",
+        )
+        .multiple(true)
+        .allow_hyphen_values(true)
+        .required(true)
+}
+
+/// Parse the filter given with [`filter_arg`]
+pub(super) fn get_filter(matches: &ArgMatches) -> Fallible<Filter> {
+    let args: Vec<&str> = matches.values_of("filter").unwrap().collect();
+    Filter::parse(&args)
+}
+
+/// An optional filter, for commands which consider all tasks when no filter is given
+pub(super) fn optional_filter_arg<'a>() -> Arg<'a, 'a> {
+    filter_arg()
+        .required(false)
+        .help("filter terms, such as +tag, project:name, or status:name")
+}
+
+/// Parse the filter given with [`optional_filter_arg`], which is empty if no terms were given
+pub(super) fn get_optional_filter(matches: &ArgMatches) -> Fallible<Filter> {
+    match matches.values_of("filter") {
+        Some(args) => Filter::parse(&args.collect::<Vec<_>>()),
+        None => Ok(Filter::default()),
+    }
+}
+
+/// Get the tasks summarized by the statistics reports: tasks of any status matching the filter
+/// and the active context, if any
+pub(super) fn summarized_tasks(
+    command: &CommandInvocation,
+    replica: &mut Replica,
+    filter: &Filter,
+) -> Fallible<Vec<Task>> {
+    let mut filter = filter.clone();
+    if let Some((_, context)) = settings::context(&*command.get_settings()?)? {
+        filter.extend(context);
+    }
+    filter.with_any_status().tasks(replica)
+}
+
+/// Arguments for a range of time, as used by the time-tracking reports.  The range defaults to the
+/// last seven days, including today.
+pub(super) fn range_args<'a>() -> Vec<Arg<'a, 'a>> {
+    vec![
+        Arg::with_name("from")
+            .long("from")
+            .help("start of the range (default today-6d)")
+            .takes_value(true),
+        Arg::with_name("to")
+            .long("to")
+            .help("end of the range (default now)")
+            .takes_value(true),
+    ]
+}
+
+/// Parse the range given with [`range_args`]
+pub(super) fn get_range(matches: &ArgMatches) -> Fallible<(DateTime<Utc>, DateTime<Utc>)> {
+    let from = parse_date(matches.value_of("from").unwrap_or("today-6d"))?;
+    let to = parse_date(matches.value_of("to").unwrap_or("now"))?;
+    if from > to {
+        return Err(format_err!("The start of the range is after its end"));
+    }
+    Ok((from, to))
+}
+
+/// Get a short label for a task given its uuid: its working-set number if it has one, or its
+/// short uuid
+pub(super) fn uuid_label(replica: &mut Replica, uuid: &Uuid) -> Fallible<String> {
+    Ok(match replica.get_working_set_index(uuid)? {
+        Some(i) => i.to_string(),
+        None => short_uuid(uuid),
+    })
+}
+
+/// Get a short label for a task: its working-set number if it has one, or its short uuid
+pub(super) fn task_label(replica: &mut Replica, task: &Task) -> Fallible<String> {
+    uuid_label(replica, task.get_uuid())
+}
+
+/// Apply `modify` to each task selected by the filter, after confirming with the user if there
+/// are many of them, and summarize the result.  The `verb` is used in the confirmation question,
+/// and `past` in the output, e.g., "complete" and "Completed".
+pub(super) fn modify_tasks<F>(
+    command: &CommandInvocation,
+    filter: &Filter,
+    verb: &str,
+    past: &str,
+    mut modify: F,
+) -> Fallible<()>
+where
+    F: FnMut(&mut TaskMut) -> Fallible<()>,
+{
+    let mut replica = command.get_replica()?;
+    let tasks = filter.tasks(&mut replica)?;
+    if tasks.is_empty() {
+        println!("No matching tasks.");
+        return Ok(());
+    }
+    if !command.confirm_bulk(tasks.len(), verb)? {
+        println!("No tasks changed.");
+        return Ok(());
+    }
+
+    let count = tasks.len();
+    let plural = |n: usize| if n == 1 { "task" } else { "tasks" };
+    for (done, task) in tasks.into_iter().enumerate() {
+        let result = task_label(&mut replica, &task).and_then(|label| {
+            let task = modify_task(command, &mut replica, task, &mut modify)?;
+            println!("{} task {} '{}'", past, label, task.get_description());
+            Ok(())
+        });
+        if let Err(e) = result {
+            // earlier changes have been saved, so say how far this got before failing
+            println!(
+                "{} {} of {} {}; the remaining {} not changed.",
+                past,
+                done,
+                count,
+                plural(count),
+                if count - done == 1 {
+                    "task was"
+                } else {
+                    "tasks were"
+                }
+            );
+            return Err(e);
+        }
+    }
+    println!("{} {} {}.", past, count, plural(count));
+    Ok(())
+}
+
+/// Add a new pending task with the given description, set up by `init`.  If there are on-add
+/// hooks, they may veto or rewrite the task before it is added.
+pub(super) fn add_task<F>(
+    command: &CommandInvocation,
+    replica: &mut Replica,
+    description: String,
+    init: F,
+) -> Fallible<Task>
+where
+    F: FnOnce(&mut TaskMut) -> Fallible<()>,
+{
+    let task = match command.hooks_for(hooks::Event::Add)? {
+        None => {
+            let task = replica.new_task(Status::Pending, description)?;
+            let mut task = task.into_mut(replica);
+            init(&mut task)?;
+            task.into_immut()
+        }
+        Some(hooks) => {
+            // create the task in the scratch replica, and add it only once the hooks accept it
+            let record = command.with_scratch_replica(|scratch| {
+                let task = scratch.new_task(Status::Pending, description)?;
+                let mut task = task.into_mut(scratch);
+                init(&mut task)?;
+                Ok(taskwarrior::task_to_json(&task.into_immut()))
+            })?;
+            let record = hooks.on_add(record)?;
+            apply_record(replica, &record)?
+        }
+    };
+    command.record_change(&task);
+    Ok(task)
+}
+
+/// Apply `modify` to a task.  If there are on-modify hooks, they may veto or rewrite the change
+/// before it is made.
+pub(super) fn modify_task<F>(
+    command: &CommandInvocation,
+    replica: &mut Replica,
+    task: Task,
+    modify: F,
+) -> Fallible<Task>
+where
+    F: FnOnce(&mut TaskMut) -> Fallible<()>,
+{
+    let task = match command.hooks_for(hooks::Event::Modify)? {
+        None => {
+            let mut task = task.into_mut(replica);
+            modify(&mut task)?;
+            task.into_immut()
+        }
+        Some(hooks) => {
+            // modify a copy of the task in the scratch replica, and once the hooks accept the
+            // result, make the same changes to the task
+            let original = taskwarrior::task_to_json(&task);
+            let modified = command.with_scratch_replica(|scratch| {
+                let mut task = apply_record(scratch, &original)?.into_mut(scratch);
+                modify(&mut task)?;
+                Ok(taskwarrior::task_to_json(&task.into_immut()))
+            })?;
+            let record = hooks.on_modify(&original, modified)?;
+            let mut task = task.into_mut(replica);
+            taskwarrior::apply_json_changes(&mut task, &original, &record)?;
+            task.into_immut()
+        }
+    };
+    command.record_change(&task);
+    Ok(task)
+}
+
+/// Apply a task in Taskwarrior's JSON format to the replica, returning the resulting task
+fn apply_record(replica: &mut Replica, record: &Value) -> Fallible<Task> {
+    let uuid = taskwarrior::apply_json(replica, record)?;
+    replica
+        .get_task(&uuid)?
+        .ok_or_else(|| format_err!("Task {} was not created", uuid))
+}
+
 pub(super) fn get_task<S: AsRef<str>>(replica: &mut Replica, task_arg: S) -> Fallible<Task> {
     let task_arg = task_arg.as_ref();
 
@@ -27,6 +257,12 @@ pub(super) fn get_task<S: AsRef<str>>(replica: &mut Replica, task_arg: S) -> Fal
         }
     }
 
+    if task_arg.len() >= MIN_UUID_PREFIX {
+        if let Some(task) = replica.get_task_by_uuid_prefix(task_arg)? {
+            return Ok(task);
+        }
+    }
+
     Err(format_err!("Cannot interpret {:?} as a task", task_arg))
 }
 
@@ -34,36 +270,148 @@ pub(super) fn get_task<S: AsRef<str>>(replica: &mut Replica, task_arg: S) -> Fal
 #[derive(Debug)]
 pub struct CommandInvocation {
     pub(crate) subcommand: Box<dyn super::SubCommandInvocation>,
+    pub(crate) format: Format,
     settings: RefCell<Config>,
+    /// The tasks added or modified by this command, for the on-exit hooks
+    changed: RefCell<Vec<Value>>,
+    /// The replica in which changes are made before the hooks accept them, once needed
+    scratch: RefCell<Option<ScratchReplica>>,
+}
+
+/// A replica in a private temporary directory, which is removed with it
+struct ScratchReplica {
+    replica: Replica,
+    _dir: TempDir,
+}
+
+impl fmt::Debug for ScratchReplica {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ScratchReplica")
+    }
 }
 
 impl CommandInvocation {
-    pub(crate) fn new(subcommand: Box<dyn super::SubCommandInvocation>) -> Self {
+    /// Create a new CommandInvocation.  If `settings` is None, they are read when first needed.
+    pub(crate) fn new(
+        subcommand: Box<dyn super::SubCommandInvocation>,
+        format: Format,
+        settings: Option<Config>,
+    ) -> Self {
+        let settings = match settings {
+            Some(mut settings) => {
+                // setting a value in a Config cannot fail
+                settings.set("_loaded", true).unwrap();
+                settings
+            }
+            None => Config::default(),
+        };
         Self {
             subcommand,
-            settings: RefCell::new(Config::default()),
+            format,
+            settings: RefCell::new(settings),
+            changed: RefCell::new(vec![]),
+            scratch: RefCell::new(None),
         }
     }
 
     pub fn run(self) -> Fallible<()> {
-        self.subcommand.run(&self)
+        self.subcommand.run(&self)?;
+        let changed = self.changed.replace(vec![]);
+        if !changed.is_empty() {
+            if let Some(hooks) = self.hooks_for(hooks::Event::Exit)? {
+                hooks.on_exit(&changed)?;
+            }
+        }
+        Ok(())
     }
 
     // -- utilities for command invocations
 
     pub(super) fn get_settings(&self) -> Fallible<Ref<Config>> {
-        {
-            // use the special `_loaded" config value to detect whether we have
-            // loaded the configuration yet
+        // use the special `_loaded" config value to detect whether we have
+        // loaded the configuration yet; the settings are only borrowed mutably
+        // to load them, so callers may hold on to the result of this method
+        let loaded = !matches!(
+            self.settings.borrow().get_bool("_loaded"),
+            Err(ConfigError::NotFound(_))
+        );
+        if !loaded {
             let mut settings = self.settings.borrow_mut();
-            if let Err(ConfigError::NotFound(_)) = settings.get_bool("_loaded") {
-                settings.merge(settings::read_settings()?)?;
-                settings.set("_loaded", true)?;
-            }
+            settings.merge(settings::read_settings()?)?;
+            settings.set("_loaded", true)?;
         }
         Ok(self.settings.borrow())
     }
 
+    /// Confirm an operation on `count` tasks with the user, if that is more than the `bulk`
+    /// setting.  This fails if confirmation is needed but stdin is not a terminal.
+    pub(super) fn confirm_bulk(&self, count: usize, verb: &str) -> Fallible<bool> {
+        let bulk = self.get_settings()?.get_int("bulk")?;
+        if bulk <= 0 || count as i64 <= bulk {
+            return Ok(true);
+        }
+        if !atty::is(atty::Stream::Stdin) {
+            return Err(format_err!(
+                "Refusing to {} {} tasks without confirmation; see the `bulk` setting",
+                verb,
+                count
+            ));
+        }
+        let mut question = verb.to_string();
+        question[..1].make_ascii_uppercase();
+        print!("{} {} tasks? (y/N) ", question, count);
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        Ok(["y", "yes"].contains(&answer.trim().to_lowercase().as_ref()))
+    }
+
+    /// Write the command's output in the selected format; see [`output::write`]
+    pub(super) fn write_output(&self, tables: &[output::Table], json: &Value) -> Fallible<()> {
+        let settings = self.get_settings()?;
+        let renderer = Renderer {
+            terminal: Terminal::detect(),
+            theme: settings::theme(&settings)?,
+            overflow: settings::overflow(&settings)?,
+        };
+        output::write(&mut io::stdout(), self.format, &renderer, tables, json)
+    }
+
+    /// Get the hooks, if there are any for the given event
+    pub(super) fn hooks_for(&self, event: hooks::Event) -> Fallible<Option<Hooks>> {
+        let hooks = settings::hooks(&*self.get_settings()?)?;
+        Ok(match hooks {
+            Some(hooks) if !hooks.find(event)?.is_empty() => Some(hooks),
+            _ => None,
+        })
+    }
+
+    /// Record that the command added or modified a task, replacing any earlier record of the same
+    /// task
+    fn record_change(&self, task: &Task) {
+        let record = taskwarrior::task_to_json(task);
+        let mut changed = self.changed.borrow_mut();
+        changed.retain(|r| r.get("uuid") != record.get("uuid"));
+        changed.push(record);
+    }
+
+    /// Call `f` with the scratch replica, which is created when first needed and then shared by
+    /// every change this command makes
+    fn with_scratch_replica<T, F>(&self, f: F) -> Fallible<T>
+    where
+        F: FnOnce(&mut Replica) -> Fallible<T>,
+    {
+        let mut scratch = self.scratch.borrow_mut();
+        if scratch.is_none() {
+            let dir = TempDir::new("task-scratch")?;
+            let replica = Replica::from_config(ReplicaConfig {
+                taskdb_dir: dir.path().to_path_buf(),
+            })?;
+            *scratch = Some(ScratchReplica { replica, _dir: dir });
+        }
+        f(&mut scratch.as_mut().unwrap().replica)
+    }
+
     pub(super) fn get_replica(&self) -> Fallible<Replica> {
         let settings = self.get_settings()?;
         let taskdb_dir = settings.get_str("data_dir")?.into();
@@ -72,16 +420,35 @@ impl CommandInvocation {
         Ok(Replica::from_config(replica_config)?)
     }
 
-    pub(super) fn get_server(&self) -> Fallible<Box<dyn server::Server>> {
+    /// Get the named server, or the default server if `name` is None, along with a description
+    /// identifying it.  A remote server is identified by the client id, which does not change
+    /// when the way its origin is written does.
+    pub(super) fn get_server(
+        &self,
+        name: Option<&str>,
+    ) -> Fallible<(Box<dyn server::Server>, String)> {
         let settings = self.get_settings()?;
-        let client_id = settings.get_str("server_client_id")?;
-        let client_id = Uuid::parse_str(&client_id)?;
-        let origin = settings.get_str("server_origin")?;
-        log::debug!("Using sync-server with origin {}", origin);
-        log::debug!("Sync client ID: {}", client_id);
-        Ok(server::from_config(ServerConfig::Remote {
-            origin,
-            client_id,
-        })?)
+        let server_config = settings::server_config(&settings, name)?;
+        let identity = match server_config {
+            ServerConfig::Remote {
+                ref origin,
+                ref client_id,
+            } => {
+                log::debug!("Using sync-server with origin {}", origin);
+                log::debug!("Sync client ID: {}", client_id);
+                format!("the server with client id {}", client_id)
+            }
+            ServerConfig::Local { ref server_dir } => {
+                log::debug!("Using local server in {:?}", server_dir);
+                // the server would create its directory anyway; creating it here allows
+                // identifying the server by its canonical path
+                fs::create_dir_all(server_dir)?;
+                format!(
+                    "the local server in {}",
+                    fs::canonicalize(server_dir)?.display()
+                )
+            }
+        };
+        Ok((server::from_config(server_config)?, identity))
     }
 }
@@ -0,0 +1,142 @@
+use crate::dates::{format_date, format_duration};
+use crate::filter::Filter;
+use crate::output::{self, Format};
+use chrono::Utc;
+use clap::{App, AppSettings, ArgMatches, SubCommand as ClapSubCommand};
+use failure::Fallible;
+use serde_json::{json, Value};
+use taskchampion::{Statistics, StatusCounts};
+
+use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
+
+#[derive(Debug)]
+struct Invocation {
+    filter: Filter,
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("stats")
+                .about("show statistics about tasks: counts by status and project, and completion times")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(shared::optional_filter_arg()),
+        )
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("stats", Some(matches)) => match shared::get_optional_filter(matches) {
+                Ok(filter) => ArgMatchResult::Ok(Box::new(Invocation { filter })),
+                Err(e) => ArgMatchResult::Err(e),
+            },
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let mut replica = command.get_replica()?;
+        let tasks = shared::summarized_tasks(command, &mut replica, &self.filter)?;
+        if tasks.is_empty() && command.format == Format::Table {
+            println!("No matching tasks.");
+            return Ok(());
+        }
+        let stats = Statistics::from_tasks(tasks.iter());
+        let counts = stats.counts();
+
+        let mut t = output::Table::new_labeled(&["statistic", "value"]);
+        t.set_flexible(1);
+        t.add_row(&["Pending", &counts.pending.to_string()]);
+        t.add_row(&["Completed", &counts.completed.to_string()]);
+        t.add_row(&["Deleted", &counts.deleted.to_string()]);
+        t.add_row(&["Total", &counts.total().to_string()]);
+        let average = stats.average_completion_time();
+        if let Some(average) = average {
+            t.add_row(&["Average completion time", &format_duration(average)]);
+        }
+        let mut oldest = Value::Null;
+        if let Some((uuid, entry)) = stats.oldest_pending() {
+            // the oldest pending task is one of the tasks summarized
+            let task = tasks.iter().find(|t| t.get_uuid() == &uuid).unwrap();
+            t.add_row(&[
+                "Oldest pending".to_string(),
+                format!(
+                    "{} {} (entered {}, {} ago)",
+                    shared::uuid_label(&mut replica, &uuid)?,
+                    task.get_description(),
+                    format_date(entry),
+                    format_duration(Utc::now() - entry),
+                ),
+            ]);
+            oldest = json!({
+                "uuid": uuid.to_string(),
+                "description": task.get_description(),
+                "entry": entry.to_rfc3339(),
+            });
+        }
+
+        let mut p = output::Table::new(&["project", "pending", "completed", "deleted", "total"]);
+        let mut projects = vec![];
+        // projects in alphabetical order, with tasks without a project last
+        let mut by_project: Vec<_> = stats.by_project().iter().collect();
+        by_project.sort_by_key(|(project, _)| (project.is_none(), *project));
+        for (project, counts) in by_project {
+            p.add_row(&[
+                project.clone().unwrap_or_else(|| "(none)".into()),
+                counts.pending.to_string(),
+                counts.completed.to_string(),
+                counts.deleted.to_string(),
+                counts.total().to_string(),
+            ]);
+            let mut record = counts_json(counts);
+            record["project"] = json!(project);
+            projects.push(record);
+        }
+
+        let mut json = counts_json(&counts);
+        json["average_completion_seconds"] = json!(average.map(|a| a.num_seconds()));
+        json["oldest_pending"] = oldest;
+        json["projects"] = projects.into();
+        command.write_output(&[t, p], &json)
+    }
+}
+
+fn counts_json(counts: &StatusCounts) -> Value {
+    json!({
+        "pending": counts.pending,
+        "completed": counts.completed,
+        "deleted": counts.deleted,
+        "total": counts.total(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::Condition;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(vec!["task", "stats"], |inv: &Invocation| {
+            assert_eq!(inv.filter, Filter::default());
+        });
+    }
+
+    #[test]
+    fn parse_command_filter() {
+        with_subcommand_invocation!(
+            vec!["task", "stats", "project:work", "-home"],
+            |inv: &Invocation| {
+                assert_eq!(
+                    inv.filter.conditions,
+                    vec![
+                        Condition::Project(Some("work".into())),
+                        Condition::NoTag("home".into())
+                    ]
+                );
+            }
+        );
+    }
+}
@@ -1,26 +1,29 @@
-use clap::{App, ArgMatches, SubCommand as ClapSubCommand};
+use clap::{App, AppSettings, ArgMatches, SubCommand as ClapSubCommand};
 use failure::Fallible;
 
 use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
+use crate::filter::Filter;
 
 #[derive(Debug)]
 struct Invocation {
-    task: String,
+    filter: Filter,
 }
 
 define_subcommand! {
     fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
         app.subcommand(
             ClapSubCommand::with_name("stop")
-                .about("stop the given task")
-                .arg(shared::task_arg()))
+                .about("stop the given tasks")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(shared::filter_arg()))
     }
 
     fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
         match matches.subcommand() {
-            ("stop", Some(matches)) => ArgMatchResult::Ok(Box::new(Invocation {
-                task: matches.value_of("task").unwrap().into(),
-            })),
+            ("stop", Some(matches)) => match shared::get_filter(matches) {
+                Ok(filter) => ArgMatchResult::Ok(Box::new(Invocation { filter })),
+                Err(e) => ArgMatchResult::Err(e),
+            },
             _ => ArgMatchResult::None,
         }
     }
@@ -28,21 +31,21 @@ define_subcommand! {
 
 subcommand_invocation! {
     fn run(&self, command: &CommandInvocation) -> Fallible<()> {
-        let mut replica = command.get_replica()?;
-        let task = shared::get_task(&mut replica, &self.task)?;
-        task.into_mut(&mut replica).stop()?;
-        Ok(())
+        shared::modify_tasks(command, &self.filter, "stop", "Stopped", |task| {
+                task.stop()
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::filter::TaskId;
 
     #[test]
     fn parse_command() {
         with_subcommand_invocation!(vec!["task", "stop", "1"], |inv: &Invocation| {
-            assert_eq!(inv.task, "1".to_string());
+            assert_eq!(inv.filter.ids, vec![TaskId::WorkingSetId(1)]);
         });
     }
 }
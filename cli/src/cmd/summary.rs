@@ -0,0 +1,106 @@
+use crate::dates::format_elapsed;
+use crate::output;
+use chrono::{DateTime, Utc};
+use clap::{App, ArgMatches, SubCommand as ClapSubCommand};
+use failure::Fallible;
+use serde_json::json;
+use taskchampion::TimeReport;
+
+use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
+
+#[derive(Debug)]
+struct Invocation {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("summary")
+                .about("summarize the time tracked on tasks, by project and by task")
+                .args(&shared::range_args()))
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("summary", Some(matches)) => match shared::get_range(matches) {
+                Ok((from, to)) => ArgMatchResult::Ok(Box::new(Invocation { from, to })),
+                Err(e) => ArgMatchResult::Err(e),
+            },
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let mut replica = command.get_replica()?;
+        let report = TimeReport::new(&mut replica, self.from, self.to)?;
+
+        // projects in alphabetical order, with tasks without a project last
+        let mut by_project: Vec<_> = report.by_project().into_iter().collect();
+        by_project.sort_by(|(p1, _), (p2, _)| (p1.is_none(), p1).cmp(&(p2.is_none(), p2)));
+        let mut p = output::Table::new(&["project", "time"]);
+        let mut projects = vec![];
+        for (project, duration) in by_project {
+            p.add_row(&[
+                project.clone().unwrap_or_else(|| "(none)".into()),
+                format_elapsed(duration),
+            ]);
+            projects.push(json!({
+                "project": project,
+                "seconds": duration.num_seconds(),
+            }));
+        }
+        p.add_row(&["total".to_string(), format_elapsed(report.total())]);
+
+        // tasks with the most time first
+        let mut by_task: Vec<_> = report.by_task().into_iter().collect();
+        by_task.sort_by(|(u1, d1), (u2, d2)| (d2, u1).cmp(&(d1, u2)));
+        let mut t = output::Table::new(&["id", "project", "time", "description"]);
+        t.set_flexible(3);
+        let mut tasks = vec![];
+        for (uuid, duration) in by_task {
+            let task = match replica.get_task(&uuid)? {
+                Some(task) => task,
+                None => continue,
+            };
+            t.add_row(&[
+                shared::uuid_label(&mut replica, &uuid)?,
+                task.get_project().unwrap_or("").into(),
+                format_elapsed(duration),
+                task.get_description().into(),
+            ]);
+            tasks.push(json!({
+                "uuid": uuid.to_string(),
+                "description": task.get_description(),
+                "project": task.get_project(),
+                "seconds": duration.num_seconds(),
+            }));
+        }
+
+        let json = json!({
+            "projects": projects,
+            "tasks": tasks,
+            "total": report.total().num_seconds(),
+        });
+        command.write_output(&[p, t], &json)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dates::parse_date;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(
+            vec!["task", "summary", "--from", "2021-03-01"],
+            |inv: &Invocation| {
+                assert_eq!(inv.from, parse_date("2021-03-01").unwrap());
+            }
+        );
+    }
+}
@@ -1,19 +1,51 @@
-use clap::{App, ArgMatches, SubCommand as ClapSubCommand};
-use failure::Fallible;
+use crate::hooks;
+use clap::{App, Arg, ArgMatches, SubCommand as ClapSubCommand};
+use failure::{format_err, Fallible};
+use std::fs;
+use std::io;
+use std::path::Path;
+use taskchampion::{server::Server, Replica};
 
 use crate::cmd::{ArgMatchResult, CommandInvocation};
 
+/// The file in the data directory recording the server the replica syncs with
+const SERVER_FILE: &str = "sync-server";
+
 #[derive(Debug)]
-struct Invocation {}
+struct Invocation {
+    server: Option<String>,
+    switch: bool,
+}
 
 define_subcommand! {
     fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
-        app.subcommand(ClapSubCommand::with_name("sync").about("sync with the server"))
+        app.subcommand(
+            ClapSubCommand::with_name("sync")
+                .about("sync with the server")
+                .arg(
+                    Arg::with_name("server")
+                        .long("server")
+                        .takes_value(true)
+                        .help("name of the server profile to sync with (default `server`)"),
+                )
+                .arg(
+                    Arg::with_name("switch")
+                        .long("switch")
+                        .help("sync with this server from now on, sending it all tasks"),
+                )
+                .subcommand(super::config::init_subcommand()),
+        )
     }
 
     fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
         match matches.subcommand() {
-            ("sync", _) => ArgMatchResult::Ok(Box::new(Invocation {})),
+            ("sync", Some(matches)) => match matches.subcommand() {
+                ("init", Some(matches)) => ArgMatchResult::Ok(super::config::init_invocation(matches)),
+                _ => ArgMatchResult::Ok(Box::new(Invocation {
+                    server: matches.value_of("server").map(|s| s.into()),
+                    switch: matches.is_present("switch"),
+                })),
+            },
             _ => ArgMatchResult::None,
         }
     }
@@ -22,18 +54,204 @@ define_subcommand! {
 subcommand_invocation! {
     fn run(&self, command: &CommandInvocation) -> Fallible<()> {
         let mut replica = command.get_replica()?;
-        let mut server = command.get_server()?;
-        replica.sync(&mut server)?;
+        let (mut server, identity) = command.get_server(self.server.as_deref())?;
+        let data_dir = command.get_settings()?.get_str("data_dir")?;
+        sync(
+            &mut replica,
+            Path::new(&data_dir),
+            &mut server,
+            &identity,
+            self.switch,
+        )?;
+        if let Some(hooks) = command.hooks_for(hooks::Event::Sync)? {
+            hooks.on_sync()?;
+        }
         Ok(())
     }
 }
 
+/// Sync the replica, whose data is in `data_dir`, with the given server.  The replica's changes
+/// are sent only to the server it syncs with, so this refuses to sync with a server other than
+/// the one the replica has synced with before, unless `switch` is given.  Switching sends all of
+/// the replica's tasks to the new server.
+fn sync(
+    replica: &mut Replica,
+    data_dir: &Path,
+    server: &mut Box<dyn Server>,
+    identity: &str,
+    switch: bool,
+) -> Fallible<()> {
+    let path = data_dir.join(SERVER_FILE);
+    match fs::read_to_string(&path) {
+        Ok(synced) if synced != identity => {
+            if !switch {
+                return Err(format_err!(
+                    "This replica syncs with {}, not {}; use `task sync --switch` to sync it with {} from now on",
+                    synced,
+                    identity,
+                    identity
+                ));
+            }
+            log::debug!("Switching from {} to {}", synced, identity);
+            replica.reset_sync()?;
+        }
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    replica.sync(server)?;
+    fs::write(&path, identity)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use taskchampion::{server, ReplicaConfig, ServerConfig, Status};
+    use tempdir::TempDir;
 
     #[test]
     fn parse_command() {
-        with_subcommand_invocation!(vec!["task", "sync"], |_inv| {});
+        with_subcommand_invocation!(vec!["task", "sync"], |inv: &Invocation| {
+            assert_eq!(inv.server, None);
+            assert!(!inv.switch);
+        });
+    }
+
+    #[test]
+    fn parse_command_server() {
+        with_subcommand_invocation!(
+            vec!["task", "sync", "--server", "backup", "--switch"],
+            |inv: &Invocation| {
+                assert_eq!(inv.server, Some("backup".to_string()));
+                assert!(inv.switch);
+            }
+        );
+    }
+
+    #[test]
+    fn sync_one_server() -> Fallible<()> {
+        let data_dir = TempDir::new("data")?;
+        let server_a = TempDir::new("server-a")?;
+        let server_b = TempDir::new("server-b")?;
+        let local = |dir: &TempDir| {
+            server::from_config(ServerConfig::Local {
+                server_dir: dir.path().into(),
+            })
+        };
+        let mut replica = Replica::from_config(ReplicaConfig {
+            taskdb_dir: data_dir.path().into(),
+        })?;
+
+        replica.new_task(Status::Pending, "first".into())?;
+        sync(
+            &mut replica,
+            data_dir.path(),
+            &mut local(&server_a)?,
+            "a",
+            false,
+        )?;
+
+        // syncing with server B is refused, and does not lose the new task
+        replica.new_task(Status::Pending, "second".into())?;
+        assert_eq!(
+            sync(&mut replica, data_dir.path(), &mut local(&server_b)?, "b", false)
+                .err()
+                .unwrap()
+                .to_string(),
+            "This replica syncs with a, not b; use `task sync --switch` to sync it with b from now on"
+        );
+        sync(
+            &mut replica,
+            data_dir.path(),
+            &mut local(&server_a)?,
+            "a",
+            false,
+        )?;
+
+        // a new replica syncing with server A gets both tasks
+        let other_dir = TempDir::new("other")?;
+        let mut other = Replica::from_config(ReplicaConfig {
+            taskdb_dir: other_dir.path().into(),
+        })?;
+        other.sync(&mut local(&server_a)?)?;
+        let mut descriptions: Vec<_> = other
+            .all_tasks()?
+            .values()
+            .map(|t| t.get_description().to_string())
+            .collect();
+        descriptions.sort();
+        assert_eq!(descriptions, vec!["first", "second"]);
+        Ok(())
+    }
+
+    #[test]
+    fn sync_switch() -> Fallible<()> {
+        let data_dir = TempDir::new("data")?;
+        let server_a = TempDir::new("server-a")?;
+        let server_b = TempDir::new("server-b")?;
+        let local = |dir: &TempDir| {
+            server::from_config(ServerConfig::Local {
+                server_dir: dir.path().into(),
+            })
+        };
+        let descriptions = |server: &TempDir| -> Fallible<Vec<String>> {
+            let dir = TempDir::new("other")?;
+            let mut other = Replica::from_config(ReplicaConfig {
+                taskdb_dir: dir.path().into(),
+            })?;
+            other.sync(&mut local(server)?)?;
+            let mut descriptions: Vec<_> = other
+                .all_tasks()?
+                .values()
+                .map(|t| t.get_description().to_string())
+                .collect();
+            descriptions.sort();
+            Ok(descriptions)
+        };
+        let mut replica = Replica::from_config(ReplicaConfig {
+            taskdb_dir: data_dir.path().into(),
+        })?;
+
+        replica.new_task(Status::Pending, "first".into())?;
+        sync(
+            &mut replica,
+            data_dir.path(),
+            &mut local(&server_a)?,
+            "a",
+            false,
+        )?;
+        replica.new_task(Status::Pending, "second".into())?;
+
+        // switching to server B sends it all tasks, including those already sent to server A
+        sync(
+            &mut replica,
+            data_dir.path(),
+            &mut local(&server_b)?,
+            "b",
+            true,
+        )?;
+        assert_eq!(descriptions(&server_b)?, vec!["first", "second"]);
+
+        // from now on, the replica syncs with server B
+        assert!(sync(
+            &mut replica,
+            data_dir.path(),
+            &mut local(&server_a)?,
+            "a",
+            false
+        )
+        .is_err());
+        replica.new_task(Status::Pending, "third".into())?;
+        sync(
+            &mut replica,
+            data_dir.path(),
+            &mut local(&server_b)?,
+            "b",
+            true,
+        )?;
+        assert_eq!(descriptions(&server_b)?, vec!["first", "second", "third"]);
+        assert_eq!(descriptions(&server_a)?, vec!["first"]);
+        Ok(())
     }
 }
@@ -0,0 +1,133 @@
+use crate::dates::format_elapsed;
+use crate::output;
+use chrono::{DateTime, Local, Utc};
+use clap::{App, ArgMatches, SubCommand as ClapSubCommand};
+use failure::Fallible;
+use serde_json::json;
+use taskchampion::TimeReport;
+
+use crate::cmd::{shared, ArgMatchResult, CommandInvocation};
+
+#[derive(Debug)]
+struct Invocation {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+define_subcommand! {
+    fn decorate_app<'a>(&self, app: App<'a, 'a>) -> App<'a, 'a> {
+        app.subcommand(
+            ClapSubCommand::with_name("timesheet")
+                .about("show the time tracked on tasks, by interval and by day")
+                .args(&shared::range_args()))
+    }
+
+    fn arg_match<'a>(&self, matches: &ArgMatches<'a>) -> ArgMatchResult {
+        match matches.subcommand() {
+            ("timesheet", Some(matches)) => match shared::get_range(matches) {
+                Ok((from, to)) => ArgMatchResult::Ok(Box::new(Invocation { from, to })),
+                Err(e) => ArgMatchResult::Err(e),
+            },
+            _ => ArgMatchResult::None,
+        }
+    }
+}
+
+subcommand_invocation! {
+    fn run(&self, command: &CommandInvocation) -> Fallible<()> {
+        let mut replica = command.get_replica()?;
+        let report = TimeReport::new(&mut replica, self.from, self.to)?;
+
+        let mut t = output::Table::new(&["date", "start", "end", "time", "id", "description"]);
+        t.set_flexible(5);
+        let mut intervals = vec![];
+        for i in report.intervals() {
+            let start = i.start.with_timezone(&Local);
+            let end = if i.open {
+                "active".to_string()
+            } else {
+                i.end.with_timezone(&Local).format("%H:%M").to_string()
+            };
+            let id = shared::uuid_label(&mut replica, &i.uuid)?;
+            t.add_row(&[
+                start.format("%Y-%m-%d").to_string(),
+                start.format("%H:%M").to_string(),
+                end,
+                format_elapsed(i.duration()),
+                id,
+                i.description.clone(),
+            ]);
+            intervals.push(json!({
+                "uuid": i.uuid.to_string(),
+                "description": i.description,
+                "project": i.project,
+                "start": i.start.to_rfc3339(),
+                "end": i.end.to_rfc3339(),
+                "open": i.open,
+                "seconds": i.duration().num_seconds(),
+            }));
+        }
+
+        let mut d = output::Table::new(&["date", "time"]);
+        let mut days = vec![];
+        for (date, duration) in report.by_day(&Local) {
+            d.add_row(&[date.to_string(), format_elapsed(duration)]);
+            days.push(json!({
+                "date": date.to_string(),
+                "seconds": duration.num_seconds(),
+            }));
+        }
+        d.add_row(&["total".to_string(), format_elapsed(report.total())]);
+
+        let json = json!({
+            "intervals": intervals,
+            "days": days,
+            "total": report.total().num_seconds(),
+        });
+        command.write_output(&[t, d], &json)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dates::parse_date;
+
+    #[test]
+    fn parse_command() {
+        with_subcommand_invocation!(vec!["task", "timesheet"], |inv: &Invocation| {
+            assert_eq!(inv.from, parse_date("today-6d").unwrap());
+        });
+    }
+
+    #[test]
+    fn parse_command_range() {
+        with_subcommand_invocation!(
+            vec![
+                "task",
+                "timesheet",
+                "--from",
+                "2021-03-01",
+                "--to",
+                "2021-03-08"
+            ],
+            |inv: &Invocation| {
+                assert_eq!(inv.from, parse_date("2021-03-01").unwrap());
+                assert_eq!(inv.to, parse_date("2021-03-08").unwrap());
+            }
+        );
+    }
+
+    #[test]
+    fn parse_command_bad_range() {
+        assert!(crate::parse_test_command_line(vec![
+            "task",
+            "timesheet",
+            "--from",
+            "2021-03-08",
+            "--to",
+            "2021-03-01"
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,308 @@
+use chrono::prelude::*;
+use chrono::Duration;
+use failure::{format_err, Fallible};
+
+/// Parse a date expression on the command line, interpreting it in the local timezone.  See
+/// [`parse_date_at`] for the supported syntax.
+pub(crate) fn parse_date(s: &str) -> Fallible<DateTime<Utc>> {
+    parse_date_at(s, Local::now())
+}
+
+/// Parse a date expression relative to the given current time, interpreting it in the timezone of
+/// `now`.  The result is truncated to whole seconds, as that is the precision of task timestamps.
+///
+/// The following expressions are supported:
+///
+/// * ISO 8601 dates and date-times, with or without a timezone (`2020-12-25`,
+///   `2020-12-25T10:30`, `2020-12-25T10:30:00Z`)
+/// * `now`, and `yesterday`, `today`, and `tomorrow` (at midnight)
+/// * `eow` and `eom`, the last second of the current week (ending on Sunday) or month
+/// * weekday names such as `monday` or `fri`, meaning midnight at the next such day
+/// * offsets from now, such as `90min`, `12h`, `3d`, or `2w`
+/// * any of the above followed by `+` or `-` and an offset, such as `now+1h` or `eom-1d`
+pub(crate) fn parse_date_at<Tz: TimeZone>(s: &str, now: DateTime<Tz>) -> Fallible<DateTime<Utc>> {
+    let now = now.with_nanosecond(0).unwrap();
+    let s = s.trim().to_ascii_lowercase();
+
+    if let Some(date) = parse_base(&s, &now)? {
+        return Ok(date);
+    }
+    let out_of_range = || format_err!("Date {:?} is out of range", s);
+    if let Some(offset) = parse_offset(&s)? {
+        return now
+            .with_timezone(&Utc)
+            .checked_add_signed(offset)
+            .ok_or_else(out_of_range);
+    }
+    if let Some(i) = s.rfind(&['+', '-'][..]) {
+        if let Some(offset) = parse_offset(&s[i + 1..])? {
+            if let Some(base) = parse_base(&s[..i], &now)? {
+                let date = if &s[i..=i] == "+" {
+                    base.checked_add_signed(offset)
+                } else {
+                    base.checked_sub_signed(offset)
+                };
+                return date.ok_or_else(out_of_range);
+            }
+        }
+    }
+    Err(format_err!("Cannot interpret {:?} as a date", s))
+}
+
+/// Parse an absolute or named date, returning None if the string is not recognized.
+fn parse_base<Tz: TimeZone>(s: &str, now: &DateTime<Tz>) -> Fallible<Option<DateTime<Utc>>> {
+    let tz = now.timezone();
+    let today = now.naive_local().date();
+    let midnight = |d: NaiveDate| local(&tz, d.and_hms(0, 0, 0)).map(Some);
+    let last_second = |d: NaiveDate| local(&tz, d.and_hms(23, 59, 59)).map(Some);
+
+    match s {
+        "now" => return Ok(Some(now.with_timezone(&Utc))),
+        "yesterday" => return midnight(today.pred()),
+        "today" => return midnight(today),
+        "tomorrow" => return midnight(today.succ()),
+        "eow" => {
+            let days_left = 6 - today.weekday().num_days_from_monday();
+            return last_second(today + Duration::days(days_left.into()));
+        }
+        "eom" => {
+            let first_of_next = if today.month() == 12 {
+                NaiveDate::from_ymd(today.year() + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd(today.year(), today.month() + 1, 1)
+            };
+            return last_second(first_of_next.pred());
+        }
+        _ => {}
+    }
+
+    if let Some(weekday) = parse_weekday(s) {
+        let today_num = today.weekday().num_days_from_monday() as i64;
+        let target_num = weekday.num_days_from_monday() as i64;
+        let mut days = (target_num - today_num).rem_euclid(7);
+        if days == 0 {
+            days = 7;
+        }
+        return midnight(today + Duration::days(days));
+    }
+
+    // the `T` was lowercased above, so the ISO 8601 formats use a lowercase `t`
+    if let Ok(dt) = DateTime::parse_from_rfc3339(&s.replacen('t', "T", 1)) {
+        return Ok(Some(dt.with_timezone(&Utc)));
+    }
+    for format in &["%Y-%m-%dt%H:%M:%S", "%Y-%m-%dt%H:%M"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, format) {
+            return local(&tz, dt).map(Some);
+        }
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return midnight(d);
+    }
+    Ok(None)
+}
+
+/// Parse an offset like `3d` into a Duration, returning None if the string is not an offset, or
+/// an error if the offset is too large to represent.
+fn parse_offset(s: &str) -> Fallible<Option<Duration>> {
+    let split = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) if split > 0 => split,
+        _ => return Ok(None),
+    };
+    let (count, unit) = s.split_at(split);
+    let unit_seconds: i64 = match unit {
+        "s" | "sec" | "secs" => 1,
+        "min" | "mins" => 60,
+        "h" | "hr" | "hrs" => 3600,
+        "d" | "day" | "days" => 86400,
+        "w" | "wk" | "wks" | "week" | "weeks" => 7 * 86400,
+        _ => return Ok(None),
+    };
+    // the count is all digits, so it can only fail to parse by overflowing
+    count
+        .parse::<i64>()
+        .ok()
+        .and_then(|count| count.checked_mul(unit_seconds))
+        .filter(|seconds| *seconds <= Duration::max_value().num_seconds())
+        .map(|seconds| Some(Duration::seconds(seconds)))
+        .ok_or_else(|| format_err!("Offset {:?} is too large", s))
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    if s.len() < 3 {
+        return None;
+    }
+    [
+        ("monday", Weekday::Mon),
+        ("tuesday", Weekday::Tue),
+        ("wednesday", Weekday::Wed),
+        ("thursday", Weekday::Thu),
+        ("friday", Weekday::Fri),
+        ("saturday", Weekday::Sat),
+        ("sunday", Weekday::Sun),
+    ]
+    .iter()
+    .find(|(name, _)| name.starts_with(s))
+    .map(|(_, weekday)| *weekday)
+}
+
+/// Format a timestamp for display in the local timezone, omitting the time at midnight
+pub(crate) fn format_date(dt: DateTime<Utc>) -> String {
+    format_date_in(dt, &Local)
+}
+
+fn format_date_in<Tz: TimeZone>(dt: DateTime<Utc>, tz: &Tz) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let dt = dt.with_timezone(tz);
+    if dt.time() == NaiveTime::from_hms(0, 0, 0) {
+        dt.format("%Y-%m-%d").to_string()
+    } else {
+        dt.format("%Y-%m-%d %H:%M").to_string()
+    }
+}
+
+/// Format a duration compactly, in its largest whole unit, such as `5min`, `3d`, or `2w`
+pub(crate) fn format_duration(d: Duration) -> String {
+    let secs = d.num_seconds();
+    let (count, unit) = match secs.abs() {
+        s if s < 60 => (secs, "s"),
+        s if s < 3600 => (secs / 60, "min"),
+        s if s < 86400 => (secs / 3600, "h"),
+        s if s < 14 * 86400 => (secs / 86400, "d"),
+        s if s < 365 * 86400 => (secs / (7 * 86400), "w"),
+        _ => (secs / (365 * 86400), "y"),
+    };
+    format!("{}{}", count, unit)
+}
+
+/// Format a duration of tracked time precisely, as hours, minutes, and seconds, such as `1:05:00`
+pub(crate) fn format_elapsed(d: Duration) -> String {
+    let secs = d.num_seconds();
+    format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+/// Convert a local date-time in the given timezone to UTC, using the earlier time if it is
+/// ambiguous.
+fn local<Tz: TimeZone>(tz: &Tz, dt: NaiveDateTime) -> Fallible<DateTime<Utc>> {
+    tz.from_local_datetime(&dt)
+        .earliest()
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok_or_else(|| format_err!("{} does not exist in the local timezone", dt))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Wednesday, 2020-12-23 14:15:16.5 in UTC-5
+    fn now() -> DateTime<FixedOffset> {
+        FixedOffset::west(5 * 3600)
+            .ymd(2020, 12, 23)
+            .and_hms_milli(14, 15, 16, 500)
+    }
+
+    /// The given local time in UTC-5, converted to UTC
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32, s: u32) -> DateTime<Utc> {
+        FixedOffset::west(5 * 3600)
+            .ymd(y, m, d)
+            .and_hms(h, min, s)
+            .with_timezone(&Utc)
+    }
+
+    fn parse(s: &str) -> Fallible<DateTime<Utc>> {
+        parse_date_at(s, now())
+    }
+
+    #[test]
+    fn iso() -> Fallible<()> {
+        assert_eq!(
+            parse("2020-12-25T10:30:00Z")?,
+            Utc.ymd(2020, 12, 25).and_hms(10, 30, 0)
+        );
+        assert_eq!(parse("2020-12-25T10:30:00")?, at(2020, 12, 25, 10, 30, 0));
+        assert_eq!(parse("2020-12-25T10:30")?, at(2020, 12, 25, 10, 30, 0));
+        assert_eq!(parse("2020-12-25")?, at(2020, 12, 25, 0, 0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn named() -> Fallible<()> {
+        assert_eq!(parse("now")?, at(2020, 12, 23, 14, 15, 16));
+        assert_eq!(parse("yesterday")?, at(2020, 12, 22, 0, 0, 0));
+        assert_eq!(parse("Today")?, at(2020, 12, 23, 0, 0, 0));
+        assert_eq!(parse("tomorrow")?, at(2020, 12, 24, 0, 0, 0));
+        assert_eq!(parse("eow")?, at(2020, 12, 27, 23, 59, 59));
+        assert_eq!(parse("eom")?, at(2020, 12, 31, 23, 59, 59));
+        Ok(())
+    }
+
+    #[test]
+    fn weekdays() -> Fallible<()> {
+        assert_eq!(parse("thursday")?, at(2020, 12, 24, 0, 0, 0));
+        assert_eq!(parse("mon")?, at(2020, 12, 28, 0, 0, 0));
+        // the same weekday means next week
+        assert_eq!(parse("wed")?, at(2020, 12, 30, 0, 0, 0));
+        assert!(parse("mo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn offsets() -> Fallible<()> {
+        assert_eq!(parse("3d")?, at(2020, 12, 26, 14, 15, 16));
+        assert_eq!(parse("2w")?, at(2021, 1, 6, 14, 15, 16));
+        assert_eq!(parse("now+1h")?, at(2020, 12, 23, 15, 15, 16));
+        assert_eq!(parse("now-90min")?, at(2020, 12, 23, 12, 45, 16));
+        assert_eq!(parse("eom-1d")?, at(2020, 12, 30, 23, 59, 59));
+        assert_eq!(parse("2021-01-01+1w")?, at(2021, 1, 8, 0, 0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn local_timezone() -> Fallible<()> {
+        let expected = Local.ymd(2020, 12, 25).and_hms(0, 0, 0);
+        assert_eq!(parse_date("2020-12-25")?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn format() {
+        let tz = FixedOffset::west(5 * 3600);
+        assert_eq!(format_date_in(at(2020, 12, 25, 0, 0, 0), &tz), "2020-12-25");
+        assert_eq!(
+            format_date_in(at(2020, 12, 25, 10, 30, 15), &tz),
+            "2020-12-25 10:30"
+        );
+        assert_eq!(format_duration(Duration::seconds(59)), "59s");
+        assert_eq!(format_duration(Duration::minutes(90)), "1h");
+        assert_eq!(format_duration(Duration::days(13)), "13d");
+        assert_eq!(format_duration(Duration::days(15)), "2w");
+        assert_eq!(format_duration(Duration::days(800)), "2y");
+        assert_eq!(format_duration(Duration::minutes(-5)), "-5min");
+        assert_eq!(format_elapsed(Duration::seconds(59)), "0:00:59");
+        assert_eq!(format_elapsed(Duration::minutes(65)), "1:05:00");
+        assert_eq!(format_elapsed(Duration::hours(30)), "30:00:00");
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse("someday").is_err());
+        assert!(parse("3").is_err());
+        assert!(parse("now+").is_err());
+        assert!(parse("now+1y").is_err());
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(
+            parse("99999999999999d").err().unwrap().to_string(),
+            "Offset \"99999999999999d\" is too large"
+        );
+        assert_eq!(
+            parse("100000000d").err().unwrap().to_string(),
+            "Date \"100000000d\" is out of range"
+        );
+        assert!(parse("eom-100000000d").is_err());
+        assert!(parse("now+99999999999999999999w").is_err());
+    }
+}
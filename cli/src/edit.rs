@@ -0,0 +1,387 @@
+use crate::dates::parse_date;
+use crate::filter::parse_status;
+use crate::modification::parse_priority;
+use chrono::{DateTime, Duration, Local, Utc};
+use failure::{format_err, Fallible};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use taskchampion::{Annotation, Priority, Status, Task, TaskMut};
+
+/// The format of dates in the document, in local time; `parse_date` accepts this format
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// The explanation at the top of the document
+const HELP: &str = "\
+# Edit the task's properties below, then save the file and exit the editor.
+# Lines beginning with '#' are ignored, and an empty value removes a property.
+#
+# * status is pending, completed, or deleted, and priority is H, M, or L
+# * tags are separated by spaces
+# * dates are in local time, and may be given in any form `task add` accepts
+# * each annotation is on an `annotation:` line, with its date before ` -- `;
+#   add a line (without a date) to add an annotation, or delete one to remove it
+# * user-defined attributes are on `uda.<name>:` lines; add or delete lines to
+#   add or remove them
+";
+
+/// An Edit contains the editable properties of a task, which are rendered into a text document
+/// for the user to edit with `task edit`, and parsed back from the edited document.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Edit {
+    pub(crate) description: String,
+    pub(crate) status: Status,
+    pub(crate) project: Option<String>,
+    pub(crate) priority: Option<Priority>,
+    pub(crate) tags: BTreeSet<String>,
+    pub(crate) due: Option<DateTime<Utc>>,
+    pub(crate) wait: Option<DateTime<Utc>>,
+    pub(crate) annotations: Vec<Annotation>,
+    pub(crate) udas: BTreeMap<String, String>,
+}
+
+impl Edit {
+    pub(crate) fn from_task(task: &Task) -> Edit {
+        Edit {
+            description: task.get_description().into(),
+            status: task.get_status(),
+            project: task.get_project().map(|p| p.into()),
+            priority: task.get_priority(),
+            tags: task.get_tags().iter().map(|t| t.to_string()).collect(),
+            due: task.get_due(),
+            wait: task.get_wait(),
+            annotations: task.get_annotations(),
+            udas: task
+                .get_udas()
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Render the document, beginning with the given header
+    pub(crate) fn render(&self, header: &str) -> String {
+        let date = |d: &Option<DateTime<Utc>>| d.map(format_date).unwrap_or_default();
+        let tags: Vec<&str> = self.tags.iter().map(|t| t.as_ref()).collect();
+        let mut lines = vec![
+            format!("description: {}", self.description),
+            format!("status: {}", self.status.to_string().to_ascii_lowercase()),
+            format!("project: {}", self.project.as_deref().unwrap_or("")),
+            format!(
+                "priority: {}",
+                self.priority.map(format_priority).unwrap_or("")
+            ),
+            format!("tags: {}", tags.join(" ")),
+            format!("due: {}", date(&self.due)),
+            format!("wait: {}", date(&self.wait)),
+        ];
+        for annotation in self.annotations.iter() {
+            lines.push(format!(
+                "annotation: {} -- {}",
+                format_date(annotation.entry),
+                annotation.description
+            ));
+        }
+        for (name, value) in self.udas.iter() {
+            lines.push(format!("uda.{}: {}", name, value));
+        }
+        format!("{}{}\n{}\n", header, HELP, lines.join("\n"))
+    }
+
+    /// Parse an edited document.  New annotations are given the entry time `now`.
+    pub(crate) fn parse(doc: &str, now: DateTime<Utc>) -> Fallible<Edit> {
+        let mut edit = Edit {
+            description: String::new(),
+            status: Status::Pending,
+            project: None,
+            priority: None,
+            tags: BTreeSet::new(),
+            due: None,
+            wait: None,
+            annotations: vec![],
+            udas: BTreeMap::new(),
+        };
+        let mut new_annotations = vec![];
+        let mut seen = HashSet::new();
+
+        for (i, line) in doc.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e| format_err!("Line {}: {}", i + 1, e);
+            let colon = line
+                .find(':')
+                .ok_or_else(|| error(format_err!("expected `<property>: <value>`")))?;
+            let (key, value) = (line[..colon].trim(), line[colon + 1..].trim());
+            if key != "annotation" && !seen.insert(key) {
+                return Err(error(format_err!("`{}` is given more than once", key)));
+            }
+            let optional = |v: &str| {
+                if v.is_empty() {
+                    None
+                } else {
+                    Some(v.to_string())
+                }
+            };
+            let date = |v: &str| optional(v).map(|v| parse_date(&v)).transpose();
+
+            match key {
+                "description" => edit.description = value.into(),
+                "status" => match parse_status(value).map_err(error)? {
+                    Some(status) => edit.status = status,
+                    None => {
+                        return Err(error(format_err!(
+                            "Invalid status {:?}; expected pending, completed, or deleted",
+                            value
+                        )))
+                    }
+                },
+                "project" => edit.project = optional(value),
+                "priority" => {
+                    edit.priority = optional(value)
+                        .map(|p| parse_priority(&p))
+                        .transpose()
+                        .map_err(error)?
+                }
+                "tags" => edit.tags = value.split_whitespace().map(|t| t.into()).collect(),
+                "due" => edit.due = date(value).map_err(error)?,
+                "wait" => edit.wait = date(value).map_err(error)?,
+                "annotation" => {
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let existing = value.find(" -- ").and_then(|i| {
+                        Some(Annotation {
+                            entry: parse_date(&value[..i]).ok()?,
+                            description: value[i + 4..].trim().into(),
+                        })
+                    });
+                    match existing {
+                        Some(annotation) => edit.annotations.push(annotation),
+                        None => new_annotations.push(value.to_string()),
+                    }
+                }
+                _ => match key.strip_prefix("uda.") {
+                    // the same names `TaskMut::set_uda` accepts, checked here so that an invalid
+                    // one is caught before anything is changed
+                    Some(name) if name.is_empty() || name.contains(char::is_whitespace) => {
+                        return Err(error(format_err!("invalid UDA name {:?}", name)))
+                    }
+                    Some(name) => {
+                        if !value.is_empty() {
+                            edit.udas.insert(name.into(), value.into());
+                        }
+                    }
+                    _ => return Err(error(format_err!("unknown property `{}`", key))),
+                },
+            }
+        }
+
+        if edit.description.is_empty() {
+            return Err(format_err!("The description cannot be empty"));
+        }
+
+        // annotations are identified by their entry time, so give each new annotation a
+        // different one
+        let mut entry = now;
+        for description in new_annotations {
+            while edit.annotations.iter().any(|a| a.entry == entry) {
+                entry = entry + Duration::seconds(1);
+            }
+            edit.annotations.push(Annotation { entry, description });
+        }
+        edit.annotations.sort_by_key(|a| a.entry);
+        Ok(edit)
+    }
+
+    /// Apply the differences between this edit and the original to a task
+    pub(crate) fn apply(&self, original: &Edit, task: &mut TaskMut) -> Fallible<()> {
+        if self.description != original.description {
+            task.set_description(self.description.clone())?;
+        }
+        if self.status != original.status {
+            if self.status != Status::Pending {
+                task.stop()?;
+            }
+            task.set_status(self.status.clone())?;
+        }
+        if self.project != original.project {
+            task.set_project(self.project.clone())?;
+        }
+        if self.priority != original.priority {
+            task.set_priority(self.priority)?;
+        }
+        for tag in original.tags.difference(&self.tags) {
+            task.remove_tag(tag)?;
+        }
+        for tag in self.tags.difference(&original.tags) {
+            task.add_tag(tag)?;
+        }
+        if self.due != original.due {
+            task.set_due(self.due)?;
+        }
+        if self.wait != original.wait {
+            task.set_wait(self.wait)?;
+        }
+        for annotation in original.annotations.iter() {
+            // an annotation with the same entry time is replaced, below
+            if !self.annotations.iter().any(|a| a.entry == annotation.entry) {
+                task.remove_annotation(annotation.entry)?;
+            }
+        }
+        for annotation in self.annotations.iter() {
+            if !original.annotations.contains(annotation) {
+                task.add_annotation(annotation.clone())?;
+            }
+        }
+        for name in original.udas.keys() {
+            if !self.udas.contains_key(name) {
+                task.set_uda(name, None)?;
+            }
+        }
+        for (name, value) in self.udas.iter() {
+            if original.udas.get(name) != Some(value) {
+                task.set_uda(name, Some(value.clone()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.with_timezone(&Local).format(DATE_FORMAT).to_string()
+}
+
+fn format_priority(priority: Priority) -> &'static str {
+    match priority {
+        Priority::H => "H",
+        Priority::M => "M",
+        Priority::L => "L",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use taskchampion::{Replica, ReplicaConfig};
+    use tempdir::TempDir;
+
+    fn edit() -> Edit {
+        Edit {
+            description: "buy milk".into(),
+            status: Status::Pending,
+            project: Some("house".into()),
+            priority: Some(Priority::H),
+            tags: vec!["errand".to_string(), "home".to_string()]
+                .into_iter()
+                .collect(),
+            due: Some(Utc.timestamp(1_606_780_800, 0)),
+            wait: None,
+            annotations: vec![Annotation {
+                entry: Utc.timestamp(1_606_143_600, 0),
+                description: "2 litres".into(),
+            }],
+            udas: vec![("estimate".to_string(), "2h".to_string())]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn round_trip() -> Fallible<()> {
+        let doc = edit().render("# header\n");
+        assert!(doc.starts_with("# header\n# Edit"));
+        assert!(doc.contains("\ndescription: buy milk\n"));
+        assert!(doc.contains("\ntags: errand home\n"));
+        assert!(doc.contains("\nwait: \n"));
+        assert!(doc.contains(" -- 2 litres\n"));
+        assert!(doc.contains("\nuda.estimate: 2h\n"));
+        assert_eq!(Edit::parse(&doc, Utc::now())?, edit());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_changes() -> Fallible<()> {
+        let now = Utc.timestamp(1_610_000_000, 0);
+        let doc = edit()
+            .render("")
+            .replace("status: pending", "status: Completed")
+            .replace("priority: H", "priority:")
+            .replace("tags: errand home", "tags: home  next")
+            .replace("uda.estimate: 2h", "uda.estimate:\nuda.size: L")
+            + "annotation: call first\nannotation: check the date -- again\n";
+        let parsed = Edit::parse(&doc, now)?;
+        assert_eq!(parsed.status, Status::Completed);
+        assert_eq!(parsed.priority, None);
+        assert_eq!(parsed.tags.iter().collect::<Vec<_>>(), vec!["home", "next"]);
+        assert_eq!(parsed.annotations.len(), 3);
+        assert_eq!(parsed.annotations[1].entry, now);
+        assert_eq!(parsed.annotations[1].description, "call first");
+        assert_eq!(parsed.annotations[2].entry, now + Duration::seconds(1));
+        assert_eq!(parsed.annotations[2].description, "check the date -- again");
+        assert_eq!(parsed.udas.keys().collect::<Vec<_>>(), vec!["size"]);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |doc: &str| Edit::parse(doc, Utc::now()).unwrap_err().to_string();
+        assert_eq!(
+            parse("status: pending\n"),
+            "The description cannot be empty"
+        );
+        assert_eq!(
+            parse("description: x\nsize: L\n"),
+            "Line 2: unknown property `size`"
+        );
+        assert_eq!(
+            parse("description: x\ndescription: y\n"),
+            "Line 2: `description` is given more than once"
+        );
+        assert_eq!(
+            parse("description: x\nnonsense\n"),
+            "Line 2: expected `<property>: <value>`"
+        );
+        assert!(parse("description: x\nstatus: waiting\n").starts_with("Line 2: Invalid status"));
+        assert!(parse("description: x\nstatus: all\n").starts_with("Line 2: Invalid status"));
+        assert_eq!(
+            parse("description: x\nuda.has space: 1\n"),
+            "Line 2: invalid UDA name \"has space\""
+        );
+        assert!(parse("description: x\ndue: someday\n").starts_with("Line 2: Cannot interpret"));
+    }
+
+    #[test]
+    fn apply() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut replica = Replica::from_config(ReplicaConfig {
+            taskdb_dir: tmp_dir.path().to_path_buf(),
+        })?;
+        let task = replica.new_task(Status::Pending, "buy milk".into())?;
+        let mut task = task.into_mut(&mut replica);
+        task.set_project(Some("house".into()))?;
+        task.set_priority(Some(Priority::H))?;
+        task.add_tag("errand")?;
+        task.add_tag("home")?;
+        task.set_due(Some(Utc.timestamp(1_606_780_800, 0)))?;
+        task.add_annotation(edit().annotations[0].clone())?;
+        task.set_uda("estimate", Some("2h".into()))?;
+        task.start()?;
+        let original = Edit::from_task(&task);
+        assert_eq!(original, edit());
+
+        let mut changed = edit();
+        changed.status = Status::Completed;
+        changed.project = None;
+        changed.tags.remove("errand");
+        changed.annotations[0].description = "1 litre".into();
+        changed.udas.insert("size".into(), "L".into());
+        changed.apply(&original, &mut task)?;
+
+        let task = task.into_immut();
+        assert_eq!(Edit::from_task(&task), changed);
+        assert!(!task.is_active());
+        assert!(task.get_end().is_some());
+        Ok(())
+    }
+}
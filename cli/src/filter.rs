@@ -0,0 +1,404 @@
+use failure::{format_err, Fallible};
+use std::collections::HashSet;
+use taskchampion::{Replica, Status, Task, Uuid};
+
+/// A Filter selects tasks for commands that operate on several tasks at once, such as `done` or
+/// `modify`.  The grammar is a sequence of terms:
+///
+/// * task ids, as working-set numbers, ranges of working-set numbers, full UUIDs, or UUID
+///   prefixes of at least 8 characters, separated by commas (`1,3,5-7`)
+/// * `+<tag>` and `-<tag>`, selecting tasks with and without the tag; the virtual tags `ACTIVE`
+///   and `WAITING` select tasks that are started or waiting
+/// * `project:<name>`, selecting tasks in the project or any of its sub-projects, or tasks with no
+///   project for `project:`
+/// * `status:<status>`, selecting tasks with the given status, or tasks with any status for
+///   `status:all`
+///
+/// A task is selected if it matches any of the ids and all of the other terms.  If no ids are
+/// given, only pending tasks are considered, unless a `status:` term is given.
+#[derive(Debug, PartialEq, Default, Clone)]
+pub(crate) struct Filter {
+    pub(crate) ids: Vec<TaskId>,
+    pub(crate) conditions: Vec<Condition>,
+}
+
+/// A reference to one or more tasks
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum TaskId {
+    WorkingSetId(usize),
+    WorkingSetRange(usize, usize),
+    PartialUuid(String),
+    Uuid(Uuid),
+}
+
+/// A condition on the properties of a task
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Condition {
+    HasTag(String),
+    NoTag(String),
+    Project(Option<String>),
+    /// A status, or None for any status
+    Status(Option<Status>),
+}
+
+/// Tags which are computed from the task's state, rather than set by the user
+pub(crate) const VIRTUAL_TAGS: &[&str] = &["ACTIVE", "WAITING"];
+
+/// The minimum length of a UUID prefix, so that it is not confused with a working-set number.  This
+/// is also the length of the short UUIDs shown by the CLI.
+pub(crate) const MIN_UUID_PREFIX: usize = 8;
+
+/// Get the short form of a uuid, as shown in command output
+pub(crate) fn short_uuid(uuid: &Uuid) -> String {
+    uuid.to_string()[..MIN_UUID_PREFIX].into()
+}
+
+impl Filter {
+    /// Parse a filter from a sequence of command-line arguments
+    pub(crate) fn parse<S: AsRef<str>>(args: &[S]) -> Fallible<Filter> {
+        let mut filter = Filter::default();
+        for arg in args.iter().map(|a| a.as_ref()) {
+            if let Some(tag) = arg.strip_prefix('+').filter(|t| !t.is_empty()) {
+                filter.conditions.push(Condition::HasTag(tag.into()));
+            } else if let Some(tag) = arg.strip_prefix('-').filter(|t| !t.is_empty()) {
+                filter.conditions.push(Condition::NoTag(tag.into()));
+            } else if let Some(project) = arg.strip_prefix("project:") {
+                let project = if project.is_empty() {
+                    None
+                } else {
+                    Some(project.into())
+                };
+                filter.conditions.push(Condition::Project(project));
+            } else if let Some(status) = arg.strip_prefix("status:") {
+                filter
+                    .conditions
+                    .push(Condition::Status(parse_status(status)?));
+            } else {
+                for id in arg.split(',') {
+                    filter.ids.push(parse_id(id).ok_or_else(|| {
+                        format_err!("Cannot interpret {:?} as a task id or filter", id)
+                    })?);
+                }
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Add the terms of another filter to this one
+    pub(crate) fn extend(&mut self, other: Filter) {
+        self.ids.extend(other.ids);
+        self.conditions.extend(other.conditions);
+    }
+
+    /// Select tasks of any status, rather than only pending tasks, unless the filter already has a
+    /// `status:` term
+    pub(crate) fn with_any_status(mut self) -> Filter {
+        if !self.has_status_condition() {
+            self.conditions.push(Condition::Status(None));
+        }
+        self
+    }
+
+    /// True if this filter has no terms
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ids.is_empty() && self.conditions.is_empty()
+    }
+
+    /// Get the tasks selected by this filter.  Tasks named by id are returned in the order they
+    /// were given; otherwise tasks are ordered by working-set number, with tasks outside the
+    /// working set last.
+    pub(crate) fn tasks(&self, replica: &mut Replica) -> Fallible<Vec<Task>> {
+        let candidates = if self.ids.is_empty() {
+            let mut tasks: Vec<(usize, Task)> = vec![];
+            let all = if self.has_status_condition() {
+                replica.all_tasks()?
+            } else {
+                replica.tasks_with_status(Status::Pending)?
+            };
+            for (uuid, task) in all {
+                let index = replica.get_working_set_index(&uuid)?;
+                tasks.push((index.unwrap_or(usize::MAX), task));
+            }
+            tasks.sort_by(|(i1, t1), (i2, t2)| (i1, t1.get_uuid()).cmp(&(i2, t2.get_uuid())));
+            tasks.drain(..).map(|(_, t)| t).collect()
+        } else {
+            self.tasks_by_id(replica)?
+        };
+
+        Ok(candidates
+            .into_iter()
+            .filter(|t| self.conditions.iter().all(|c| c.matches(t)))
+            .collect())
+    }
+
+    fn has_status_condition(&self) -> bool {
+        self.conditions
+            .iter()
+            .any(|c| matches!(c, Condition::Status(_)))
+    }
+
+    fn tasks_by_id(&self, replica: &mut Replica) -> Fallible<Vec<Task>> {
+        let mut seen = HashSet::new();
+        let mut tasks = vec![];
+        // the working set, read once for the first range
+        let mut working_set = None;
+        let mut add = |task: Task| {
+            if seen.insert(*task.get_uuid()) {
+                tasks.push(task);
+            }
+        };
+
+        for id in self.ids.iter() {
+            match id {
+                TaskId::WorkingSetId(i) => add(replica
+                    .get_working_set_task(*i)?
+                    .ok_or_else(|| format_err!("No task with id {}", i))?),
+                TaskId::WorkingSetRange(start, end) => {
+                    if working_set.is_none() {
+                        working_set = Some(replica.working_set()?);
+                    }
+                    // ranges may span gaps in the working set, and extend past its end; a task
+                    // taken from the working set has already been added
+                    let working_set = working_set.as_mut().unwrap();
+                    for slot in working_set
+                        .iter_mut()
+                        .take(end.saturating_add(1))
+                        .skip(*start)
+                    {
+                        if let Some(task) = slot.take() {
+                            add(task);
+                        }
+                    }
+                }
+                TaskId::Uuid(uuid) => add(replica
+                    .get_task(uuid)?
+                    .ok_or_else(|| format_err!("No task with uuid {}", uuid))?),
+                TaskId::PartialUuid(prefix) => add(replica
+                    .get_task_by_uuid_prefix(prefix)?
+                    .ok_or_else(|| format_err!("No task with uuid prefix {}", prefix))?),
+            }
+        }
+        Ok(tasks)
+    }
+}
+
+impl Condition {
+    fn matches(&self, task: &Task) -> bool {
+        match self {
+            Condition::HasTag(tag) => has_tag(task, tag),
+            Condition::NoTag(tag) => !has_tag(task, tag),
+            Condition::Project(None) => task.get_project().is_none(),
+            Condition::Project(Some(project)) => match task.get_project() {
+                Some(p) => {
+                    p == project
+                        || (p.starts_with(project.as_str()) && p[project.len()..].starts_with('.'))
+                }
+                None => false,
+            },
+            Condition::Status(None) => true,
+            Condition::Status(Some(status)) => &task.get_status() == status,
+        }
+    }
+}
+
+/// Check for a tag, including the virtual tags
+fn has_tag(task: &Task, tag: &str) -> bool {
+    match tag {
+        "ACTIVE" => task.is_active(),
+        "WAITING" => task.is_waiting(),
+        _ => task.has_tag(tag),
+    }
+}
+
+fn parse_id(s: &str) -> Option<TaskId> {
+    if let Ok(uuid) = Uuid::parse_str(s) {
+        return Some(TaskId::Uuid(uuid));
+    }
+    // a number or range of numbers is a working-set id, even if it could be a uuid prefix
+    if let Ok(id) = s.parse() {
+        return Some(TaskId::WorkingSetId(id));
+    }
+    if let Some(dash) = s.find('-') {
+        if let (Ok(start), Ok(end)) = (s[..dash].parse(), s[dash + 1..].parse()) {
+            if start <= end {
+                return Some(TaskId::WorkingSetRange(start, end));
+            }
+        }
+    }
+    if s.len() >= MIN_UUID_PREFIX && s.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+        return Some(TaskId::PartialUuid(s.to_ascii_lowercase()));
+    }
+    None
+}
+
+pub(crate) fn parse_status(s: &str) -> Fallible<Option<Status>> {
+    match s.to_ascii_lowercase().as_ref() {
+        "pending" => Ok(Some(Status::Pending)),
+        "completed" => Ok(Some(Status::Completed)),
+        "deleted" => Ok(Some(Status::Deleted)),
+        "all" => Ok(None),
+        _ => Err(format_err!(
+            "Invalid status {:?}; expected pending, completed, deleted, or all",
+            s
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use taskchampion::ReplicaConfig;
+    use tempdir::TempDir;
+
+    #[test]
+    fn parse() -> Fallible<()> {
+        let filter = Filter::parse(&[
+            "1,3,5-7",
+            "+next",
+            "-later",
+            "project:home",
+            "status:Completed",
+            "abcdef12",
+        ])?;
+        assert_eq!(
+            filter,
+            Filter {
+                ids: vec![
+                    TaskId::WorkingSetId(1),
+                    TaskId::WorkingSetId(3),
+                    TaskId::WorkingSetRange(5, 7),
+                    TaskId::PartialUuid("abcdef12".into()),
+                ],
+                conditions: vec![
+                    Condition::HasTag("next".into()),
+                    Condition::NoTag("later".into()),
+                    Condition::Project(Some("home".into())),
+                    Condition::Status(Some(Status::Completed)),
+                ],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_numeric_ids() -> Fallible<()> {
+        let filter =
+            Filter::parse(&["12345678", "10000000-10000002", "12345678-1234", "1234abcd"])?;
+        assert_eq!(
+            filter.ids,
+            vec![
+                TaskId::WorkingSetId(12345678),
+                TaskId::WorkingSetRange(10000000, 10000002),
+                TaskId::PartialUuid("12345678-1234".into()),
+                TaskId::PartialUuid("1234abcd".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Filter::parse(&["foo"]).is_err());
+        assert!(Filter::parse(&["7-5"]).is_err());
+        assert!(Filter::parse(&["abc"]).is_err());
+        assert!(Filter::parse(&["status:waiting"]).is_err());
+    }
+
+    /// Create a replica with pending tasks 1-4 (1 and 3 tagged "next", 2 in project "home.garden")
+    /// and a completed task, returning the UUIDs of all five.
+    fn replica(tmp_dir: &TempDir) -> Fallible<(Replica, Vec<Uuid>)> {
+        let mut replica = Replica::from_config(ReplicaConfig {
+            taskdb_dir: tmp_dir.path().to_path_buf(),
+        })?;
+        let mut uuids = vec![];
+        for i in 1..=5 {
+            let task = replica.new_task(Status::Pending, format!("task {}", i))?;
+            uuids.push(*task.get_uuid());
+            let mut task = task.into_mut(&mut replica);
+            if i % 2 == 1 {
+                task.add_tag("next")?;
+            }
+            if i == 2 {
+                task.set_project(Some("home.garden".into()))?;
+            }
+            if i == 5 {
+                task.set_status(Status::Completed)?;
+            }
+        }
+        Ok((replica, uuids))
+    }
+
+    fn filtered(replica: &mut Replica, args: &[&str]) -> Fallible<Vec<Uuid>> {
+        Ok(Filter::parse(args)?
+            .tasks(replica)?
+            .iter()
+            .map(|t| *t.get_uuid())
+            .collect())
+    }
+
+    #[test]
+    fn tasks_by_id() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let (mut replica, uuids) = replica(&tmp_dir)?;
+        assert_eq!(
+            filtered(&mut replica, &["3,1-2,1"])?,
+            vec![uuids[2], uuids[0], uuids[1]]
+        );
+        assert_eq!(
+            filtered(&mut replica, &["1-4", "+next"])?,
+            vec![uuids[0], uuids[2]]
+        );
+        let prefix = &uuids[4].to_string()[..8];
+        assert_eq!(filtered(&mut replica, &[prefix])?, vec![uuids[4]]);
+        assert!(filtered(&mut replica, &["9"]).is_err());
+        assert_eq!(
+            filtered(&mut replica, &["3-100000000"])?,
+            vec![uuids[2], uuids[3], uuids[4]]
+        );
+        assert_eq!(filtered(&mut replica, &["10000000-10000002"])?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn tasks_by_condition() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let (mut replica, uuids) = replica(&tmp_dir)?;
+        assert_eq!(
+            filtered(&mut replica, &["+next"])?,
+            vec![uuids[0], uuids[2]]
+        );
+        assert_eq!(
+            filtered(&mut replica, &["+next", "status:completed"])?,
+            vec![uuids[4]]
+        );
+        assert_eq!(filtered(&mut replica, &["status:all", "+next"])?.len(), 3);
+        assert_eq!(filtered(&mut replica, &["project:home"])?, vec![uuids[1]]);
+        assert_eq!(filtered(&mut replica, &["project:hom"])?, vec![]);
+        assert_eq!(
+            filtered(&mut replica, &["project:", "-next"])?,
+            vec![uuids[3]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tasks_with_any_status() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let (mut replica, uuids) = replica(&tmp_dir)?;
+        let tasks = |filter: Filter, replica: &mut Replica| -> Fallible<usize> {
+            Ok(filter.with_any_status().tasks(replica)?.len())
+        };
+        assert_eq!(tasks(Filter::default(), &mut replica)?, 5);
+        assert_eq!(tasks(Filter::parse(&["+next"])?, &mut replica)?, 3);
+        assert_eq!(
+            Filter::parse(&["status:completed"])?
+                .with_any_status()
+                .tasks(&mut replica)?
+                .iter()
+                .map(|t| *t.get_uuid())
+                .collect::<Vec<_>>(),
+            vec![uuids[4]]
+        );
+        Ok(())
+    }
+}
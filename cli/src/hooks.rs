@@ -0,0 +1,387 @@
+/*!
+Hooks are executables in the hooks directory which are run when tasks are added or modified, when
+a command that changed tasks exits, and after synchronization.  They follow Taskwarrior's hook
+protocol, exchanging tasks in Taskwarrior's JSON format, one task per line:
+
+* `on-add` hooks receive the new task on stdin, and must print it, possibly modified, as a
+  single line of JSON.
+* `on-modify` hooks receive the original task and the modified task on stdin, and must print the
+  modified task, possibly modified further.
+* `on-exit` hooks receive all tasks added or modified by the command on stdin; any JSON they
+  print is ignored.
+* `on-sync` hooks receive nothing on stdin.
+
+A hook's executable name begins with the event, such as `on-add-check-project`.  Hooks for the
+same event run in order by name, each receiving the output of the previous one.  Any other lines a
+hook prints are feedback, which is shown to the user on stderr, apart from the command's output.
+A hook that exits with a non-zero status vetoes the change, and its feedback becomes the error
+message.  A hook that does not exit within the timeout is killed, and also vetoes the change.
+*/
+
+use failure::{format_err, Fallible};
+use serde_json::Value;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to check whether a running hook has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The events for which hooks are run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Event {
+    Add,
+    Modify,
+    Exit,
+    Sync,
+}
+
+impl Event {
+    /// The prefix of the names of executables run for this event
+    fn prefix(self) -> &'static str {
+        match self {
+            Event::Add => "on-add",
+            Event::Modify => "on-modify",
+            Event::Exit => "on-exit",
+            Event::Sync => "on-sync",
+        }
+    }
+}
+
+/// What a hook printed: the JSON records, and any other non-empty lines
+#[derive(Debug, Default, PartialEq)]
+struct Output {
+    records: Vec<Value>,
+    feedback: Vec<String>,
+}
+
+/// The hooks in a directory, which need not exist
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Hooks {
+    dir: PathBuf,
+    timeout: Duration,
+}
+
+impl Hooks {
+    pub(crate) fn new<P: Into<PathBuf>>(dir: P, timeout: Duration) -> Self {
+        Hooks {
+            dir: dir.into(),
+            timeout,
+        }
+    }
+
+    /// Find the executables for the given event, in order by name
+    pub(crate) fn find(&self, event: Event) -> Fallible<Vec<PathBuf>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(format_err!(
+                    "Could not read hooks directory {:?}: {}",
+                    self.dir,
+                    e
+                ))
+            }
+        };
+        let mut hooks = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            let is_hook = matches!(
+                path.file_name().and_then(|n| n.to_str()),
+                Some(n) if n.starts_with(event.prefix())
+            );
+            if is_hook && is_executable(&path) {
+                hooks.push(path);
+            }
+        }
+        hooks.sort();
+        Ok(hooks)
+    }
+
+    /// Run the on-add hooks for a new task, returning the task as the hooks left it
+    pub(crate) fn on_add(&self, task: Value) -> Fallible<Value> {
+        let mut task = task;
+        for hook in self.find(Event::Add)? {
+            let output = self.run(&hook, &[&task])?;
+            task = single_record(&hook, output, &task)?;
+        }
+        Ok(task)
+    }
+
+    /// Run the on-modify hooks for a modified task, returning the task as the hooks left it
+    pub(crate) fn on_modify(&self, original: &Value, modified: Value) -> Fallible<Value> {
+        let mut modified = modified;
+        for hook in self.find(Event::Modify)? {
+            let output = self.run(&hook, &[original, &modified])?;
+            modified = single_record(&hook, output, original)?;
+        }
+        Ok(modified)
+    }
+
+    /// Run the on-exit hooks with the tasks added or modified by a command
+    pub(crate) fn on_exit(&self, tasks: &[Value]) -> Fallible<()> {
+        let tasks: Vec<&Value> = tasks.iter().collect();
+        for hook in self.find(Event::Exit)? {
+            self.run(&hook, &tasks)?;
+        }
+        Ok(())
+    }
+
+    /// Run the on-sync hooks, after a successful synchronization
+    pub(crate) fn on_sync(&self) -> Fallible<()> {
+        for hook in self.find(Event::Sync)? {
+            self.run(&hook, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Run a hook with the given tasks on stdin, printing its feedback to stderr if it succeeds
+    fn run(&self, hook: &Path, tasks: &[&Value]) -> Fallible<Output> {
+        let name = hook_name(hook);
+        log::debug!("Running hook {:?}", hook);
+        let mut child = Command::new(hook)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format_err!("Could not run hook {}: {}", name, e))?;
+
+        // write and read in separate threads, so that a hook which does not read its input, or
+        // which prints a lot, does not block.  The threads are never joined: a process started by
+        // the hook may hold its stdin or stdout open long after the hook itself has exited.
+        let mut input = String::new();
+        for task in tasks {
+            input.push_str(&task.to_string());
+            input.push('\n');
+        }
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        thread::spawn(move || {
+            // a hook need not read its input, so a broken pipe is not an error
+            let _ = stdin.write_all(input.as_bytes());
+        });
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut output = String::new();
+            let _ = sender.send(stdout.read_to_string(&mut output).map(|_| output));
+        });
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format_err!(
+                    "Hook {} timed out after {} seconds",
+                    name,
+                    self.timeout.as_secs_f64()
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        let stdout = receiver
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .map_err(|_| {
+                format_err!(
+                    "Hook {} did not close its output within {} seconds",
+                    name,
+                    self.timeout.as_secs_f64()
+                )
+            })??;
+
+        let output = parse_output(&name, &stdout)?;
+        if !status.success() {
+            let mut message = format!("Hook {} failed ({})", name, status);
+            if !output.feedback.is_empty() {
+                message.push_str(": ");
+                message.push_str(&output.feedback.join("\n"));
+            }
+            return Err(format_err!("{}", message));
+        }
+        for line in output.feedback.iter() {
+            eprintln!("{}", line);
+        }
+        Ok(output)
+    }
+}
+
+/// Get the name of a hook for messages
+fn hook_name(hook: &Path) -> String {
+    hook.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| hook.display().to_string())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    matches!(fs::metadata(path), Ok(m) if m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Divide a hook's output into JSON records, which are lines beginning with `{`, and feedback
+fn parse_output(name: &str, stdout: &str) -> Fallible<Output> {
+    let mut output = Output::default();
+    for line in stdout.lines() {
+        let line = line.trim();
+        if line.starts_with('{') {
+            let record = serde_json::from_str(line)
+                .map_err(|e| format_err!("Hook {} printed invalid JSON: {}", name, e))?;
+            output.records.push(record);
+        } else if !line.is_empty() {
+            output.feedback.push(line.into());
+        }
+    }
+    Ok(output)
+}
+
+/// Get the single task printed by an on-add or on-modify hook, which must have the same uuid as
+/// the original task
+fn single_record(hook: &Path, mut output: Output, original: &Value) -> Fallible<Value> {
+    let name = hook_name(hook);
+    if output.records.len() != 1 {
+        return Err(format_err!(
+            "Hook {} must print the task as a single line of JSON, but printed {} tasks",
+            name,
+            output.records.len()
+        ));
+    }
+    let task = output.records.remove(0);
+    if task.get("uuid") != original.get("uuid") {
+        return Err(format_err!("Hook {} changed the uuid of the task", name));
+    }
+    Ok(task)
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::os::unix::fs::PermissionsExt;
+    use tempdir::TempDir;
+
+    /// Write an executable shell script to the directory
+    fn script(dir: &TempDir, name: &str, body: &str) -> Fallible<()> {
+        let path = dir.path().join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    }
+
+    fn task() -> Value {
+        json!({"uuid": "8f8e5f3a-3e4c-4c5a-9d0b-6a1c7b0d2e11", "description": "buy milk"})
+    }
+
+    #[test]
+    fn find_hooks() -> Fallible<()> {
+        let dir = TempDir::new("hooks")?;
+        script(&dir, "on-add-b", "")?;
+        script(&dir, "on-add-a", "")?;
+        script(&dir, "on-modify", "")?;
+        fs::write(dir.path().join("on-add-not-executable"), "")?;
+        let hooks = Hooks::new(dir.path(), Duration::from_secs(5));
+        let found = hooks.find(Event::Add)?;
+        assert_eq!(
+            found,
+            vec![dir.path().join("on-add-a"), dir.path().join("on-add-b")]
+        );
+        assert_eq!(hooks.find(Event::Sync)?, Vec::<PathBuf>::new());
+
+        let missing = Hooks::new(dir.path().join("missing"), Duration::from_secs(5));
+        assert_eq!(missing.find(Event::Add)?, Vec::<PathBuf>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn on_add_rewrites() -> Fallible<()> {
+        let dir = TempDir::new("hooks")?;
+        script(&dir, "on-add-1", "sed 's/milk/oat milk/'")?;
+        script(&dir, "on-add-2", "cat; echo 'checked the task'")?;
+        let hooks = Hooks::new(dir.path(), Duration::from_secs(5));
+        let task = hooks.on_add(task())?;
+        assert_eq!(task["description"], "buy oat milk");
+        Ok(())
+    }
+
+    #[test]
+    fn on_add_veto() -> Fallible<()> {
+        let dir = TempDir::new("hooks")?;
+        script(&dir, "on-add", "echo 'no shopping today'; exit 1")?;
+        let hooks = Hooks::new(dir.path(), Duration::from_secs(5));
+        let err = hooks.on_add(task()).unwrap_err().to_string();
+        assert!(err.starts_with("Hook on-add failed"));
+        assert!(err.ends_with(": no shopping today"));
+        Ok(())
+    }
+
+    #[test]
+    fn on_add_bad_output() -> Fallible<()> {
+        let dir = TempDir::new("hooks")?;
+        script(&dir, "on-add", "echo 'forgot the task'")?;
+        let hooks = Hooks::new(dir.path(), Duration::from_secs(5));
+        assert!(hooks.on_add(task()).is_err());
+
+        script(
+            &dir,
+            "on-add",
+            r#"echo '{"uuid": "1b2c3d4e-5f60-4718-8a9b-0c1d2e3f4a5b"}'"#,
+        )?;
+        let err = hooks.on_add(task()).unwrap_err().to_string();
+        assert_eq!(err, "Hook on-add changed the uuid of the task");
+        Ok(())
+    }
+
+    #[test]
+    fn on_modify_input() -> Fallible<()> {
+        let dir = TempDir::new("hooks")?;
+        // print the modified task only if the original was given first
+        script(
+            &dir,
+            "on-modify",
+            "read original; read modified; echo \"$original\" | grep -q 'buy milk' && echo \"$modified\"",
+        )?;
+        let hooks = Hooks::new(dir.path(), Duration::from_secs(5));
+        let mut modified = task();
+        modified["description"] = "buy bread".into();
+        let task = hooks.on_modify(&task(), modified)?;
+        assert_eq!(task["description"], "buy bread");
+        Ok(())
+    }
+
+    #[test]
+    fn timeout() -> Fallible<()> {
+        let dir = TempDir::new("hooks")?;
+        script(&dir, "on-sync", "sleep 5")?;
+        let hooks = Hooks::new(dir.path(), Duration::from_millis(100));
+        let start = Instant::now();
+        let err = hooks.on_sync().unwrap_err().to_string();
+        assert_eq!(err, "Hook on-sync timed out after 0.1 seconds");
+        assert!(start.elapsed() < Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[test]
+    fn output_held_open() -> Fallible<()> {
+        let dir = TempDir::new("hooks")?;
+        script(&dir, "on-sync", "sleep 5 &")?;
+        let hooks = Hooks::new(dir.path(), Duration::from_millis(100));
+        let start = Instant::now();
+        let err = hooks.on_sync().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Hook on-sync did not close its output within 0.1 seconds"
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+        Ok(())
+    }
+}
@@ -1,36 +1,76 @@
-use clap::{App, AppSettings};
+use aliases::Aliases;
+use clap::{App, AppSettings, Arg, ArgMatches};
+use config::Config;
 use failure::Fallible;
 use std::ffi::OsString;
 
+mod aliases;
 mod cmd;
+mod dates;
+mod edit;
+mod filter;
+mod hooks;
+mod modification;
+mod output;
+mod report;
 pub(crate) mod settings;
 mod table;
 
 use cmd::ArgMatchResult;
 pub(crate) use cmd::CommandInvocation;
 
-/// Parse the given command line and return an as-yet un-executed CommandInvocation.
+/// Parse the given command line and return an as-yet un-executed CommandInvocation.  Aliases
+/// defined in the settings are expanded first.
 pub fn parse_command_line<I, T>(iter: I) -> Fallible<CommandInvocation>
 where
     I: IntoIterator<Item = T>,
     T: Into<OsString> + Clone,
 {
-    let subcommands = cmd::subcommands();
+    let args: Vec<OsString> = iter.into_iter().map(Into::into).collect();
+    // `config` reads the settings itself, and must work even when they are broken
+    if is_config_command(&args) {
+        return parse_args(args, None, &Aliases::new());
+    }
 
-    let mut app = App::new("TaskChampion")
-        .version(env!("CARGO_PKG_VERSION"))
-        .about("Personal task-tracking")
-        .setting(AppSettings::ColoredHelp);
+    // if the settings cannot be read, the error is reported when the command needs them
+    let settings = settings::read_settings().ok();
+    let aliases = match settings {
+        Some(ref settings) => settings::aliases(settings).unwrap_or_else(|e| {
+            eprintln!("Warning: ignoring aliases: {}", e);
+            Aliases::new()
+        }),
+        None => Aliases::new(),
+    };
+    parse_args(args, settings, &aliases)
+}
 
-    for subcommand in subcommands.iter() {
-        app = subcommand.decorate_app(app);
+/// Parse the given command line, expanding the given aliases, with the given settings if they have
+/// already been read.
+pub(crate) fn parse_args(
+    args: Vec<OsString>,
+    settings: Option<Config>,
+    aliases: &Aliases,
+) -> Fallible<CommandInvocation> {
+    let args = aliases::expand(args, aliases)?;
+    let aliases_help = aliases::help(aliases);
+
+    let subcommands = cmd::subcommands();
+    let mut app = app(&subcommands);
+    if !aliases.is_empty() {
+        app = app.after_help(aliases_help.as_str());
     }
 
-    let matches = app.get_matches_from_safe(iter)?;
+    let matches = app.get_matches_from_safe(args)?;
+    let format = match find_format(&matches) {
+        Some(format) => format.parse()?,
+        None => output::Format::default(),
+    };
 
     for subcommand in subcommands.iter() {
         match subcommand.arg_match(&matches) {
-            ArgMatchResult::Ok(invocation) => return Ok(CommandInvocation::new(invocation)),
+            ArgMatchResult::Ok(invocation) => {
+                return Ok(CommandInvocation::new(invocation, format, settings))
+            }
             ArgMatchResult::Err(err) => return Err(err),
             ArgMatchResult::None => {}
         }
@@ -41,6 +81,56 @@ where
     unreachable!()
 }
 
+/// Determine whether the command line runs the `config` subcommand
+fn is_config_command(args: &[OsString]) -> bool {
+    aliases::subcommand_index(args).map(|i| args[i] == "config") == Some(true)
+}
+
+/// Parse the given command line without reading the settings or expanding any aliases, for tests
+#[cfg(test)]
+pub(crate) fn parse_test_command_line<I, T>(iter: I) -> Fallible<CommandInvocation>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    parse_args(
+        iter.into_iter().map(Into::into).collect(),
+        None,
+        &Aliases::new(),
+    )
+}
+
+/// Build the clap App for the given subcommands
+pub(crate) fn app<'a>(subcommands: &[Box<dyn cmd::SubCommand>]) -> App<'a, 'a> {
+    let mut app = App::new("TaskChampion")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Personal task-tracking")
+        .setting(AppSettings::ColoredHelp)
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .help("output format")
+                .takes_value(true)
+                .possible_values(output::FORMATS)
+                .global(true),
+        );
+
+    for subcommand in subcommands.iter() {
+        app = subcommand.decorate_app(app);
+    }
+    app
+}
+
+/// Find the global `--format` option, which may be given before or after the subcommand
+fn find_format<'a>(matches: &'a ArgMatches) -> Option<&'a str> {
+    matches.value_of("format").or_else(|| {
+        matches
+            .subcommand()
+            .1
+            .and_then(|matches| matches.value_of("format"))
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -49,12 +139,36 @@ mod test {
     fn test_parse_command_line_success() -> Fallible<()> {
         // This just verifies that one of the subcommands works; the subcommands themselves
         // are tested in their own unit tests.
-        parse_command_line(vec!["task", "pending"].iter())?;
+        parse_test_command_line(["task", "pending"].iter())?;
         Ok(())
     }
 
     #[test]
     fn test_parse_command_line_failure() {
-        assert!(parse_command_line(vec!["task", "--no-such-arg"].iter()).is_err());
+        assert!(parse_test_command_line(["task", "--no-such-arg"].iter()).is_err());
+    }
+
+    #[test]
+    fn test_parse_command_line_format() -> Fallible<()> {
+        let command = parse_test_command_line(["task", "pending"].iter())?;
+        assert_eq!(command.format, output::Format::Table);
+        let command = parse_test_command_line(["task", "--format", "json", "info", "1"].iter())?;
+        assert_eq!(command.format, output::Format::Json);
+        let command = parse_test_command_line(["task", "list", "+next", "--format", "csv"].iter())?;
+        assert_eq!(command.format, output::Format::Csv);
+        assert!(parse_test_command_line(["task", "--format", "yaml"].iter()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_args_aliases() -> Fallible<()> {
+        let mut aliases = Aliases::new();
+        aliases.insert("p".into(), vec!["config".into(), "path".into()]);
+        let command = parse_args(vec!["task".into(), "p".into()], None, &aliases)?;
+        assert_eq!(
+            format!("{:?}", command.subcommand),
+            "Invocation { action: Path }"
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,246 @@
+use crate::dates::parse_date;
+use crate::filter::{Condition, Filter, VIRTUAL_TAGS};
+use chrono::{DateTime, Utc};
+use failure::{format_err, Fallible};
+use taskchampion::{Priority, TaskMut};
+
+/// A Modification is a set of changes to a task, parsed from the command-line arguments of
+/// commands like `add` and `modify`.  The grammar follows Taskwarrior:
+///
+/// * `project:<name>`, `priority:<H|M|L>`, `due:<date>`, and `wait:<date>` set the corresponding
+///   attribute, and an empty value (e.g., `due:`) clears it
+/// * `+<tag>` adds a tag and `-<tag>` removes it
+/// * any other words form the description; after `--`, all words are description
+///
+/// Fields that are None are left unchanged; `Some(None)` clears the attribute.
+#[derive(Debug, PartialEq, Default, Clone)]
+pub(crate) struct Modification {
+    pub(crate) description: Option<String>,
+    pub(crate) project: Option<Option<String>>,
+    pub(crate) priority: Option<Option<Priority>>,
+    pub(crate) due: Option<Option<DateTime<Utc>>>,
+    pub(crate) wait: Option<Option<DateTime<Utc>>>,
+    pub(crate) add_tags: Vec<String>,
+    pub(crate) remove_tags: Vec<String>,
+}
+
+impl Modification {
+    /// Parse a modification from a sequence of command-line arguments
+    pub(crate) fn parse<S: AsRef<str>>(args: &[S]) -> Fallible<Modification> {
+        let mut modification = Modification::default();
+        let mut description: Vec<&str> = vec![];
+        let mut args = args.iter().map(|a| a.as_ref());
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                description.extend(&mut args);
+                break;
+            }
+            if let Some(tag) = arg.strip_prefix('+') {
+                if !tag.is_empty() {
+                    modification.add_tags.push(tag.into());
+                    continue;
+                }
+            }
+            if let Some(tag) = arg.strip_prefix('-') {
+                if !tag.is_empty() {
+                    modification.remove_tags.push(tag.into());
+                    continue;
+                }
+            }
+            if let Some((name, value)) = split_attribute(arg) {
+                let value = if value.is_empty() { None } else { Some(value) };
+                match name {
+                    "project" => modification.project = Some(value.map(|v| v.into())),
+                    "priority" => {
+                        modification.priority = Some(value.map(parse_priority).transpose()?)
+                    }
+                    "due" => modification.due = Some(value.map(parse_date).transpose()?),
+                    "wait" => modification.wait = Some(value.map(parse_date).transpose()?),
+                    _ => unreachable!(),
+                }
+                continue;
+            }
+            description.push(arg);
+        }
+
+        if !description.is_empty() {
+            modification.description = Some(description.join(" "));
+        }
+        Ok(modification)
+    }
+
+    /// Add the tags and project required by a context's filter, so that a new task falls within
+    /// the context.  Tags and a project given explicitly in this modification take precedence.
+    pub(crate) fn add_context(&mut self, filter: &Filter) {
+        for condition in filter.conditions.iter() {
+            match condition {
+                Condition::HasTag(tag)
+                    if !VIRTUAL_TAGS.contains(&tag.as_str())
+                        && !self.add_tags.contains(tag)
+                        && !self.remove_tags.contains(tag) =>
+                {
+                    self.add_tags.push(tag.clone())
+                }
+                Condition::Project(Some(project)) if self.project.is_none() => {
+                    self.project = Some(Some(project.clone()))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Apply this modification to a task
+    pub(crate) fn apply(&self, task: &mut TaskMut) -> Fallible<()> {
+        if let Some(ref description) = self.description {
+            task.set_description(description.clone())?;
+        }
+        if let Some(ref project) = self.project {
+            task.set_project(project.clone())?;
+        }
+        if let Some(priority) = self.priority {
+            task.set_priority(priority)?;
+        }
+        if let Some(due) = self.due {
+            task.set_due(due)?;
+        }
+        if let Some(wait) = self.wait {
+            task.set_wait(wait)?;
+        }
+        for tag in self.add_tags.iter() {
+            task.add_tag(tag)?;
+        }
+        for tag in self.remove_tags.iter() {
+            task.remove_tag(tag)?;
+        }
+        Ok(())
+    }
+}
+
+/// Split an argument of the form `name:value` for a known attribute name
+fn split_attribute(arg: &str) -> Option<(&str, &str)> {
+    let i = arg.find(':')?;
+    let name = &arg[..i];
+    match name {
+        "project" | "priority" | "due" | "wait" => Some((name, &arg[i + 1..])),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_priority(value: &str) -> Fallible<Priority> {
+    match value.to_ascii_uppercase().as_ref() {
+        "H" => Ok(Priority::H),
+        "M" => Ok(Priority::M),
+        "L" => Ok(Priority::L),
+        _ => Err(format_err!(
+            "Invalid priority {:?}; expected H, M, or L",
+            value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use taskchampion::{Replica, ReplicaConfig, Status};
+    use tempdir::TempDir;
+
+    #[test]
+    fn description_only() -> Fallible<()> {
+        let modification = Modification::parse(&["buy", "milk"])?;
+        assert_eq!(
+            modification,
+            Modification {
+                description: Some("buy milk".into()),
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn mixed() -> Fallible<()> {
+        let modification = Modification::parse(&[
+            "buy",
+            "project:home",
+            "+errand",
+            "milk",
+            "-later",
+            "priority:h",
+            "due:2020-12-25T10:30:00Z",
+            "wait:",
+        ])?;
+        assert_eq!(
+            modification,
+            Modification {
+                description: Some("buy milk".into()),
+                project: Some(Some("home".into())),
+                priority: Some(Some(Priority::H)),
+                due: Some(Some(Utc.ymd(2020, 12, 25).and_hms(10, 30, 0))),
+                wait: Some(None),
+                add_tags: vec!["errand".into()],
+                remove_tags: vec!["later".into()],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_attribute_is_description() -> Fallible<()> {
+        let modification = Modification::parse(&["note:", "call", "-", "+"])?;
+        assert_eq!(modification.description, Some("note: call - +".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn double_dash() -> Fallible<()> {
+        let modification = Modification::parse(&["+tag", "--", "+not", "due:tag"])?;
+        assert_eq!(modification.add_tags, vec!["tag".to_string()]);
+        assert_eq!(modification.description, Some("+not due:tag".into()));
+        assert_eq!(modification.due, None);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert!(Modification::parse(&["priority:X"]).is_err());
+        assert!(Modification::parse(&["due:someday"]).is_err());
+    }
+
+    #[test]
+    fn context() -> Fallible<()> {
+        let filter = Filter::parse(&["project:work", "+office", "-home", "+ACTIVE"])?;
+        let mut modification = Modification::parse(&["call", "+urgent"])?;
+        modification.add_context(&filter);
+        assert_eq!(modification.project, Some(Some("work".into())));
+        assert_eq!(modification.add_tags, vec!["urgent", "office"]);
+
+        // explicit values take precedence
+        let mut modification = Modification::parse(&["call", "project:", "-office"])?;
+        modification.add_context(&filter);
+        assert_eq!(modification.project, Some(None));
+        assert!(modification.add_tags.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn apply() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut replica = Replica::from_config(ReplicaConfig {
+            taskdb_dir: tmp_dir.path().to_path_buf(),
+        })?;
+        let task = replica.new_task(Status::Pending, "old".into())?;
+        let mut task = task.into_mut(&mut replica);
+        task.add_tag("later")?;
+        task.set_due(Some(Utc.timestamp(1_600_000_000, 0)))?;
+
+        Modification::parse(&["new", "+next", "-later", "project:work", "due:"])?
+            .apply(&mut task)?;
+        assert_eq!(task.get_description(), "new");
+        assert_eq!(task.get_tags(), vec!["next"]);
+        assert_eq!(task.get_project(), Some("work"));
+        assert_eq!(task.get_due(), None);
+        Ok(())
+    }
+}
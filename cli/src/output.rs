@@ -0,0 +1,304 @@
+use crate::table::{Renderer, RowState};
+use chrono::{TimeZone, Utc};
+use failure::{format_err, Error, Fallible};
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::str::FromStr;
+use taskchampion::{Task, TIMESTAMP_PROPERTIES};
+
+/// The format of a command's output, selected with the global `--format` option
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Format {
+    /// Human-readable tables
+    Table,
+    /// A single JSON value
+    Json,
+    /// Comma-separated values, with a heading row
+    Csv,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Table
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format_err!(
+                "Invalid format {:?}; expected table, json, or csv",
+                s
+            )),
+        }
+    }
+}
+
+/// The names of the formats, as accepted by the `--format` option
+pub(crate) const FORMATS: &[&str] = &["table", "json", "csv"];
+
+/// A Table is a command's output in tabular form, for the table and CSV formats.  In the table
+/// format, it is rendered by a [`Renderer`].
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Table {
+    pub(crate) headings: Vec<String>,
+    pub(crate) rows: Vec<Vec<String>>,
+    /// The state of the task in each row, if any, used to style the row
+    pub(crate) states: Vec<Option<RowState>>,
+    /// If true, the first column of each row labels the row, as for the properties of a single
+    /// task, and the headings are omitted in the table format.
+    pub(crate) labeled: bool,
+    /// The column, if any, which is wrapped or truncated to fit the table to the terminal
+    pub(crate) flexible: Option<usize>,
+}
+
+impl Table {
+    /// Create a new table with the given column headings
+    pub(crate) fn new<S: ToString>(headings: &[S]) -> Self {
+        Table {
+            headings: headings.iter().map(|h| h.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Create a new table of labeled values, such as `property` and `value`
+    pub(crate) fn new_labeled<S: ToString>(headings: &[S]) -> Self {
+        Table {
+            labeled: true,
+            ..Table::new(headings)
+        }
+    }
+
+    /// Set the column which is wrapped or truncated to fit the terminal
+    pub(crate) fn set_flexible(&mut self, column: usize) {
+        self.flexible = Some(column);
+    }
+
+    /// Add a row to the table, with one value for each heading
+    pub(crate) fn add_row<S: ToString>(&mut self, row: &[S]) {
+        self.add_row_with_state(row, None);
+    }
+
+    /// Add a row showing a task in the given state
+    pub(crate) fn add_row_with_state<S: ToString>(&mut self, row: &[S], state: Option<RowState>) {
+        self.rows.push(row.iter().map(|v| v.to_string()).collect());
+        self.states.push(state);
+    }
+
+    fn write_csv<W: Write>(&self, w: &mut W) -> Fallible<()> {
+        let mut writer = csv::Writer::from_writer(w);
+        writer.write_record(&self.headings)?;
+        for row in self.rows.iter() {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Write a command's output in the given format: the tables, separated by blank lines, for the
+/// table and CSV formats, or the JSON value for the JSON format.  The renderer is used for the
+/// table format.
+pub(crate) fn write<W: Write>(
+    w: &mut W,
+    format: Format,
+    renderer: &Renderer,
+    tables: &[Table],
+    json: &Value,
+) -> Fallible<()> {
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *w, json)?;
+            writeln!(w)?;
+        }
+        Format::Table | Format::Csv => {
+            for (i, t) in tables.iter().enumerate() {
+                if i > 0 {
+                    writeln!(w)?;
+                }
+                if format == Format::Table {
+                    renderer.render(w, t)?;
+                } else {
+                    t.write_csv(w)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Convert a task to a JSON object containing all of its properties, with typed values:
+///
+/// * `uuid`, and `status` as `pending`, `completed`, or `deleted`
+/// * timestamps such as `entry` and `due`, in RFC 3339 format
+/// * `tags` and `depends`, as arrays of strings
+/// * `annotations`, as an array of objects with `entry` and `description`
+/// * `starts`, as an array of objects with `start` and (once stopped) `end`
+/// * `udas`, as an object containing the user-defined attributes
+///
+/// Any other property is included as a string.
+pub(crate) fn task_json(task: &Task) -> Map<String, Value> {
+    let mut record = Map::new();
+    let mut tags = vec![];
+    let mut depends = vec![];
+    let mut annotations = vec![];
+    let mut starts = vec![];
+    let mut udas = Map::new();
+
+    record.insert("uuid".into(), task.get_uuid().to_string().into());
+    record.insert(
+        "status".into(),
+        task.get_status().to_string().to_lowercase().into(),
+    );
+    for (k, v) in task.get_taskmap().iter() {
+        if k == "status" {
+            continue;
+        } else if TIMESTAMP_PROPERTIES.contains(&k.as_ref()) {
+            record.insert(k.clone(), timestamp_json(v));
+        } else if let Some(tag) = k.strip_prefix("tag.") {
+            tags.push(tag.to_string());
+        } else if let Some(dep) = k.strip_prefix("dep.") {
+            depends.push(dep.to_string());
+        } else if let Some(ts) = k.strip_prefix("annotation.") {
+            annotations.push((ts.parse().unwrap_or(0), ts, v));
+        } else if let Some(ts) = k.strip_prefix("start.") {
+            starts.push((ts.parse().unwrap_or(0), ts, v));
+        } else if let Some(uda) = k.strip_prefix("uda.") {
+            udas.insert(uda.into(), v.clone().into());
+        } else {
+            record.insert(k.clone(), v.clone().into());
+        }
+    }
+
+    tags.sort();
+    record.insert("tags".into(), tags.into());
+    depends.sort();
+    record.insert("depends".into(), depends.into());
+
+    annotations.sort_by_key(|(n, _, _): &(i64, _, _)| *n);
+    let annotations: Vec<Value> = annotations
+        .drain(..)
+        .map(|(_, ts, description)| {
+            let mut a = Map::new();
+            a.insert("entry".into(), timestamp_json(ts));
+            a.insert("description".into(), description.clone().into());
+            a.into()
+        })
+        .collect();
+    record.insert("annotations".into(), annotations.into());
+
+    starts.sort_by_key(|(n, _, _): &(i64, _, _)| *n);
+    let starts: Vec<Value> = starts
+        .drain(..)
+        .map(|(_, start, end)| {
+            let mut s = Map::new();
+            s.insert("start".into(), timestamp_json(start));
+            if !end.is_empty() {
+                s.insert("end".into(), timestamp_json(end));
+            }
+            s.into()
+        })
+        .collect();
+    record.insert("starts".into(), starts.into());
+
+    record.insert("udas".into(), udas.into());
+    record
+}
+
+/// Convert an epoch timestamp to an RFC 3339 string, or leave it as-is if it is invalid
+fn timestamp_json(ts: &str) -> Value {
+    match ts
+        .parse()
+        .ok()
+        .and_then(|ts| Utc.timestamp_opt(ts, 0).single())
+    {
+        Some(ts) => ts.to_rfc3339().into(),
+        None => ts.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use taskchampion::{taskwarrior, Replica, ReplicaConfig, Uuid};
+    use tempdir::TempDir;
+
+    fn render(format: Format, tables: &[Table], json: &Value) -> String {
+        let mut out = vec![];
+        write(&mut out, format, &Renderer::default(), tables, json).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!("json".parse::<Format>().unwrap(), Format::Json);
+        assert_eq!("csv".parse::<Format>().unwrap(), Format::Csv);
+        assert_eq!("table".parse::<Format>().unwrap(), Format::Table);
+        assert!("yaml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn write_csv() {
+        let mut t = Table::new(&["id", "description"]);
+        t.add_row(&["1", "buy milk, eggs"]);
+        t.add_row(&["2", "say \"hi\""]);
+        assert_eq!(
+            render(Format::Csv, &[t], &Value::Null),
+            "id,description\n1,\"buy milk, eggs\"\n2,\"say \"\"hi\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn write_json() {
+        let t = Table::new(&["id"]);
+        assert_eq!(
+            render(Format::Json, &[t], &json!([{"id": 1}])),
+            "[\n  {\n    \"id\": 1\n  }\n]\n"
+        );
+    }
+
+    #[test]
+    fn task_to_json() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut replica = Replica::from_config(ReplicaConfig {
+            taskdb_dir: tmp_dir.path().to_path_buf(),
+        })?;
+        let uuid = Uuid::new_v4();
+        let record = json!({
+            "uuid": uuid.to_string(),
+            "status": "pending",
+            "description": "buy milk",
+            "due": "20210102T030405Z",
+            "start": "20210102T030405Z",
+            "tags": ["next"],
+            "annotations": [{"entry": "20210102T030405Z", "description": "got it"}],
+            "size": "large",
+        });
+        taskwarrior::import_json(&mut replica, record.to_string().as_bytes())?;
+        let task = replica.get_task(&uuid)?.unwrap();
+
+        let json = Value::Object(task_json(&task));
+        assert_eq!(json["uuid"], json!(uuid.to_string()));
+        assert_eq!(json["status"], json!("pending"));
+        assert_eq!(json["description"], json!("buy milk"));
+        assert_eq!(json["due"], json!("2021-01-02T03:04:05+00:00"));
+        assert_eq!(json["tags"], json!(["next"]));
+        assert_eq!(json["depends"], json!([]));
+        assert_eq!(
+            json["annotations"],
+            json!([{"entry": "2021-01-02T03:04:05+00:00", "description": "got it"}])
+        );
+        assert_eq!(
+            json["starts"],
+            json!([{"start": "2021-01-02T03:04:05+00:00"}])
+        );
+        assert_eq!(json["udas"], json!({"size": "large"}));
+        Ok(())
+    }
+}
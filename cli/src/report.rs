@@ -0,0 +1,551 @@
+use crate::dates::{format_date, format_duration};
+use crate::filter::{short_uuid, Filter};
+use crate::output;
+use crate::table::RowState;
+use chrono::{DateTime, Utc};
+use config::{Config, ConfigError};
+use failure::{format_err, Fallible};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use taskchampion::{
+    Priority, Replica, Status, Task, Urgency, UrgencyCalculator, UrgencyCoefficients, Uuid,
+};
+
+/// A Report is a view of tasks: those selected by a filter, shown in the given columns, sorted
+/// by the given keys, and limited to a number of rows.
+///
+/// Reports are defined in the configuration under `reports.<name>`, with keys `filter` (filter
+/// terms separated by whitespace), `columns` and `sort` (lists, or strings separated by commas),
+/// and `limit`.  The built-in reports in [`BUILTIN_REPORTS`] can be overridden the same way, one
+/// key at a time.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Report {
+    pub(crate) filter: Filter,
+    pub(crate) columns: Vec<Column>,
+    pub(crate) sort: Vec<Sort>,
+    pub(crate) limit: Option<usize>,
+}
+
+/// A column in a report
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Column {
+    Id,
+    Uuid,
+    Status,
+    Active,
+    Project,
+    Tags,
+    Due,
+    Wait,
+    End,
+    Age,
+    Urgency,
+    Description,
+}
+
+/// A sort key in a report: a column, and whether it is sorted in ascending order.  In the
+/// configuration, this is written as the column name with an optional `+` (ascending, the
+/// default) or `-` (descending) suffix.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Sort {
+    pub(crate) column: Column,
+    pub(crate) ascending: bool,
+}
+
+/// The definition of a built-in report, in the same form as in the configuration
+pub(crate) struct BuiltinReport {
+    pub(crate) name: &'static str,
+    pub(crate) about: &'static str,
+    filter: &'static str,
+    columns: &'static str,
+    sort: &'static str,
+    limit: Option<usize>,
+}
+
+/// The built-in reports, which are also subcommands
+pub(crate) const BUILTIN_REPORTS: &[BuiltinReport] = &[
+    BuiltinReport {
+        name: "list",
+        about: "lists pending tasks",
+        filter: "-WAITING",
+        columns: "id,active,project,tags,due,description",
+        sort: "id",
+        limit: None,
+    },
+    BuiltinReport {
+        name: "next",
+        about: "lists the most important pending tasks",
+        filter: "-WAITING",
+        columns: "id,active,age,project,tags,due,description,urgency",
+        sort: "urgency-,id",
+        limit: Some(10),
+    },
+    BuiltinReport {
+        name: "completed",
+        about: "lists completed tasks",
+        filter: "status:completed",
+        columns: "uuid,end,project,tags,description",
+        sort: "end",
+        limit: None,
+    },
+    BuiltinReport {
+        name: "all",
+        about: "lists all tasks",
+        filter: "status:all",
+        columns: "id,uuid,status,active,project,tags,due,description",
+        sort: "id,uuid",
+        limit: None,
+    },
+    BuiltinReport {
+        name: "waiting",
+        about: "lists waiting tasks",
+        filter: "+WAITING",
+        columns: "id,wait,project,tags,due,description",
+        sort: "wait",
+        limit: None,
+    },
+];
+
+/// Information about the replica needed to render and sort report columns
+pub(crate) struct Context {
+    now: DateTime<Utc>,
+    working_set: HashMap<Uuid, usize>,
+    urgency: UrgencyCalculator,
+}
+
+/// A value used for sorting.  Missing values are represented as None, and always sort last.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Num(i64),
+    Str(String),
+}
+
+impl Report {
+    /// Get the named report from the configuration, falling back to the built-in definition
+    pub(crate) fn from_config(settings: &Config, name: &str) -> Fallible<Report> {
+        let builtin = BUILTIN_REPORTS.iter().find(|r| r.name == name);
+        let prefix = format!("reports.{}", name);
+        if builtin.is_none() {
+            if let Err(ConfigError::NotFound(_)) = settings.get_table(&prefix) {
+                return Err(format_err!("No report named {:?}", name));
+            }
+        }
+
+        let get = |key: &str, default: Option<&str>| -> Fallible<Option<Vec<String>>> {
+            let key = format!("{}.{}", prefix, key);
+            let split = |s: &str| -> Vec<String> {
+                s.split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_string())
+                    .collect()
+            };
+            match settings.get_array(&key) {
+                Ok(values) => {
+                    let mut res = vec![];
+                    for value in values {
+                        res.push(value.into_str()?);
+                    }
+                    return Ok(Some(res));
+                }
+                Err(ConfigError::NotFound(_)) => return Ok(default.map(split)),
+                Err(_) => {}
+            }
+            Ok(Some(split(&settings.get_str(&key)?)))
+        };
+
+        let filter = get("filter", builtin.map(|b| b.filter))?.unwrap_or_default();
+        let columns = get("columns", builtin.map(|b| b.columns))?
+            .ok_or_else(|| format_err!("Report {:?} has no columns", name))?;
+        let sort = get("sort", builtin.map(|b| b.sort))?.unwrap_or_default();
+        let limit = match settings.get_int(&format!("{}.limit", prefix)) {
+            Ok(limit) if limit > 0 => Some(limit as usize),
+            Ok(_) => None,
+            Err(ConfigError::NotFound(_)) => builtin.and_then(|b| b.limit),
+            Err(e) => return Err(format_err!("Invalid limit for report {:?}: {}", name, e)),
+        };
+
+        Ok(Report {
+            filter: Filter::parse(&filter)?,
+            columns: columns
+                .iter()
+                .map(|c| Column::parse(c))
+                .collect::<Fallible<_>>()?,
+            sort: sort
+                .iter()
+                .map(|s| Sort::parse(s))
+                .collect::<Fallible<_>>()?,
+            limit,
+        })
+    }
+
+    /// Get the tasks in this report, sorted and limited
+    pub(crate) fn tasks(&self, replica: &mut Replica, context: &Context) -> Fallible<Vec<Task>> {
+        // compute each task's sort values once, as some, such as urgency, are costly
+        let mut keyed: Vec<(Vec<Option<SortValue>>, Task)> = self
+            .filter
+            .tasks(replica)?
+            .into_iter()
+            .map(|t| {
+                let key = self
+                    .sort
+                    .iter()
+                    .map(|sort| sort.column.sort_value(&t, context))
+                    .collect();
+                (key, t)
+            })
+            .collect();
+        keyed.sort_by(|(k1, t1), (k2, t2)| {
+            for ((sort, v1), v2) in self.sort.iter().zip(k1).zip(k2) {
+                let ord = match (v1, v2) {
+                    (Some(v1), Some(v2)) if sort.ascending => v1.cmp(v2),
+                    (Some(v1), Some(v2)) => v2.cmp(v1),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                };
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            t1.get_uuid().cmp(t2.get_uuid())
+        });
+        let mut tasks: Vec<Task> = keyed.into_iter().map(|(_, t)| t).collect();
+        if let Some(limit) = self.limit {
+            tasks.truncate(limit);
+        }
+        Ok(tasks)
+    }
+}
+
+impl Context {
+    pub(crate) fn new(
+        replica: &mut Replica,
+        now: DateTime<Utc>,
+        coefficients: UrgencyCoefficients,
+    ) -> Fallible<Context> {
+        let mut working_set = HashMap::new();
+        for (i, task) in replica.working_set()?.iter().enumerate() {
+            if let Some(task) = task {
+                working_set.insert(*task.get_uuid(), i);
+            }
+        }
+        let urgency = UrgencyCalculator::new_at(replica, coefficients, now)?;
+        Ok(Context {
+            now,
+            working_set,
+            urgency,
+        })
+    }
+
+    /// Calculate the urgency of a task
+    pub(crate) fn urgency(&self, task: &Task) -> Urgency {
+        self.urgency.urgency(task)
+    }
+
+    /// Get the state of a task, used to style its row: active, overdue (a pending task past its
+    /// due date), blocked, or high priority, in that order of precedence
+    pub(crate) fn state(&self, task: &Task) -> Option<RowState> {
+        if task.is_active() {
+            Some(RowState::Active)
+        } else if task.get_status() == Status::Pending
+            && matches!(task.get_due(), Some(due) if due < self.now)
+        {
+            Some(RowState::Overdue)
+        } else if self.urgency.is_blocked(task) {
+            Some(RowState::Blocked)
+        } else if task.get_priority() == Some(Priority::H) {
+            Some(RowState::HighPriority)
+        } else {
+            None
+        }
+    }
+
+    /// Get the working-set index of a task, if it has one
+    pub(crate) fn id(&self, task: &Task) -> Option<usize> {
+        self.working_set.get(task.get_uuid()).copied()
+    }
+
+    /// Convert a task to JSON, as [`output::task_json`], adding its `id` (null if it is not in
+    /// the working set) and `urgency`
+    pub(crate) fn task_json(&self, task: &Task) -> Value {
+        let mut record = output::task_json(task);
+        record.insert("id".into(), self.id(task).map_or(Value::Null, Value::from));
+        record.insert("urgency".into(), self.urgency(task).value().into());
+        record.into()
+    }
+}
+
+impl Column {
+    fn parse(s: &str) -> Fallible<Column> {
+        Ok(match s {
+            "id" => Column::Id,
+            "uuid" => Column::Uuid,
+            "status" => Column::Status,
+            "active" => Column::Active,
+            "project" => Column::Project,
+            "tags" => Column::Tags,
+            "due" => Column::Due,
+            "wait" => Column::Wait,
+            "end" => Column::End,
+            "age" => Column::Age,
+            "urgency" => Column::Urgency,
+            "description" => Column::Description,
+            _ => return Err(format_err!("Unknown report column {:?}", s)),
+        })
+    }
+
+    /// The heading for this column
+    pub(crate) fn heading(self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::Uuid => "uuid",
+            Column::Status => "status",
+            Column::Active => "act",
+            Column::Project => "project",
+            Column::Tags => "tags",
+            Column::Due => "due",
+            Column::Wait => "wait",
+            Column::End => "end",
+            Column::Age => "age",
+            Column::Urgency => "urg",
+            Column::Description => "description",
+        }
+    }
+
+    /// Render this column's value for the given task.  The id of a task outside the working set
+    /// is its short uuid.
+    pub(crate) fn render(self, task: &Task, context: &Context) -> String {
+        let date = |d: Option<DateTime<Utc>>| d.map(format_date).unwrap_or_default();
+        match self {
+            Column::Id => match context.working_set.get(task.get_uuid()) {
+                Some(i) => i.to_string(),
+                None => short_uuid(task.get_uuid()),
+            },
+            Column::Uuid => short_uuid(task.get_uuid()),
+            Column::Status => task.get_status().to_string().into(),
+            Column::Active => if task.is_active() { "*" } else { "" }.into(),
+            Column::Project => task.get_project().unwrap_or("").into(),
+            Column::Tags => task.get_tags().join(" "),
+            Column::Due => date(task.get_due()),
+            Column::Wait => date(task.get_wait()),
+            Column::End => date(task.get_end()),
+            Column::Age => task
+                .get_entry()
+                .map(|e| format_duration(context.now - e))
+                .unwrap_or_default(),
+            Column::Urgency => format!("{:.2}", context.urgency.urgency(task).value()),
+            Column::Description => task.get_description().into(),
+        }
+    }
+
+    fn sort_value(self, task: &Task, context: &Context) -> Option<SortValue> {
+        let string = |s: &str| Some(SortValue::Str(s.into()));
+        let date = |d: Option<DateTime<Utc>>| d.map(|d| SortValue::Num(d.timestamp()));
+        match self {
+            Column::Id => context
+                .working_set
+                .get(task.get_uuid())
+                .map(|i| SortValue::Num(*i as i64)),
+            Column::Uuid => string(&task.get_uuid().to_string()),
+            Column::Status => string(task.get_status().to_string()),
+            Column::Active => Some(SortValue::Num(task.is_active() as i64)),
+            Column::Project => task.get_project().and_then(string),
+            Column::Tags => string(&task.get_tags().join(" ")),
+            Column::Due => date(task.get_due()),
+            Column::Wait => date(task.get_wait()),
+            Column::End => date(task.get_end()),
+            // older tasks have a larger age
+            Column::Age => task.get_entry().map(|e| SortValue::Num(-e.timestamp())),
+            // urgency is sorted to a precision of 0.001
+            Column::Urgency => Some(SortValue::Num(
+                (context.urgency.urgency(task).value() * 1000.0).round() as i64,
+            )),
+            Column::Description => string(task.get_description()),
+        }
+    }
+}
+
+impl Sort {
+    fn parse(s: &str) -> Fallible<Sort> {
+        let (column, ascending) = if let Some(column) = s.strip_suffix('-') {
+            (column, false)
+        } else {
+            (s.strip_suffix('+').unwrap_or(s), true)
+        };
+        Ok(Sort {
+            column: Column::parse(column)?,
+            ascending,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::Condition;
+    use chrono::{Duration, TimeZone};
+    use config::{File, FileFormat};
+    use taskchampion::ReplicaConfig;
+    use tempdir::TempDir;
+
+    fn settings(yaml: &str) -> Fallible<Config> {
+        let mut settings = Config::default();
+        settings.merge(File::from_str(yaml, FileFormat::Yaml))?;
+        Ok(settings)
+    }
+
+    #[test]
+    fn builtin() -> Fallible<()> {
+        let report = Report::from_config(&Config::default(), "list")?;
+        assert_eq!(
+            report.filter.conditions,
+            vec![Condition::NoTag("WAITING".into())]
+        );
+        assert_eq!(report.columns[0], Column::Id);
+        assert_eq!(
+            report.sort,
+            vec![Sort {
+                column: Column::Id,
+                ascending: true
+            }]
+        );
+        assert_eq!(report.limit, None);
+        Ok(())
+    }
+
+    #[test]
+    fn override_builtin() -> Fallible<()> {
+        let settings = settings(
+            "reports:\n  list:\n    columns: [id, description]\n    sort: due-,id+\n    limit: 5\n",
+        )?;
+        let report = Report::from_config(&settings, "list")?;
+        assert_eq!(
+            report.filter.conditions,
+            vec![Condition::NoTag("WAITING".into())]
+        );
+        assert_eq!(report.columns, vec![Column::Id, Column::Description]);
+        assert_eq!(
+            report.sort,
+            vec![
+                Sort {
+                    column: Column::Due,
+                    ascending: false
+                },
+                Sort {
+                    column: Column::Id,
+                    ascending: true
+                }
+            ]
+        );
+        assert_eq!(report.limit, Some(5));
+        Ok(())
+    }
+
+    #[test]
+    fn custom() -> Fallible<()> {
+        let settings = settings(
+            "reports:\n  home:\n    filter: project:home +next\n    columns: description\n",
+        )?;
+        let report = Report::from_config(&settings, "home")?;
+        assert_eq!(report.filter.conditions.len(), 2);
+        assert_eq!(report.columns, vec![Column::Description]);
+        assert_eq!(report.sort, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Fallible<()> {
+        assert!(Report::from_config(&Config::default(), "nosuch").is_err());
+        let bad = settings("reports:\n  bad:\n    columns: id,color\n")?;
+        assert!(Report::from_config(&bad, "bad").is_err());
+        let bad = settings("reports:\n  bad:\n    filter: +next\n")?;
+        assert!(Report::from_config(&bad, "bad").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sort_and_render() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut replica = Replica::from_config(ReplicaConfig {
+            taskdb_dir: tmp_dir.path().to_path_buf(),
+        })?;
+        let due = Utc.ymd(2020, 12, 25).and_hms(12, 0, 0);
+        for (description, days) in &[("later", Some(2)), ("never", None), ("sooner", Some(1))] {
+            let task = replica.new_task(Status::Pending, description.to_string())?;
+            let mut task = task.into_mut(&mut replica);
+            task.set_due(days.map(|d| due + Duration::days(d)))?;
+            task.add_tag("next")?;
+        }
+
+        let settings = settings(
+            "reports:\n  r:\n    columns: id,tags,description\n    sort: due\n    limit: 2\n",
+        )?;
+        let report = Report::from_config(&settings, "r")?;
+        let context = Context::new(&mut replica, due, UrgencyCoefficients::default())?;
+        let tasks = report.tasks(&mut replica, &context)?;
+        let rows: Vec<Vec<String>> = tasks
+            .iter()
+            .map(|t| {
+                report
+                    .columns
+                    .iter()
+                    .map(|c| c.render(t, &context))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["3".to_string(), "next".to_string(), "sooner".to_string()],
+                vec!["1".to_string(), "next".to_string(), "later".to_string()],
+            ]
+        );
+
+        let json = context.task_json(&tasks[0]);
+        assert_eq!(json["id"], 3);
+        assert_eq!(json["description"], "sooner");
+        assert!(json["urgency"].as_f64().unwrap() > 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn row_states() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut replica = Replica::from_config(ReplicaConfig {
+            taskdb_dir: tmp_dir.path().to_path_buf(),
+        })?;
+        let now = Utc.ymd(2020, 12, 25).and_hms(12, 0, 0);
+        let mut tasks = vec![];
+        for i in 0..4 {
+            let task = replica.new_task(Status::Pending, format!("task {}", i))?;
+            let mut task = task.into_mut(&mut replica);
+            match i {
+                0 => {
+                    task.start()?;
+                    task.set_due(Some(now - Duration::days(1)))?;
+                }
+                1 => {
+                    task.set_due(Some(now - Duration::days(1)))?;
+                    task.set_priority(Some(Priority::H))?;
+                }
+                2 => task.set_priority(Some(Priority::H))?,
+                _ => task.set_due(Some(now + Duration::days(1)))?,
+            }
+            tasks.push(task.into_immut());
+        }
+
+        let context = Context::new(&mut replica, now, UrgencyCoefficients::default())?;
+        let states: Vec<_> = tasks.iter().map(|t| context.state(t)).collect();
+        assert_eq!(
+            states,
+            vec![
+                Some(RowState::Active),
+                Some(RowState::Overdue),
+                Some(RowState::HighPriority),
+                None
+            ]
+        );
+        Ok(())
+    }
+}
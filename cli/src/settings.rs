@@ -1,37 +1,697 @@
-use config::{Config, Environment, File, FileSourceFile};
-use failure::Fallible;
+use crate::aliases::Aliases;
+use crate::filter::Filter;
+use crate::hooks::Hooks;
+use crate::table::{self, Overflow, Theme};
+use config::{Config, ConfigError, Environment, File, FileSourceFile, Source, Value};
+use failure::{format_err, Fallible};
+use prettytable::Attr;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::fmt;
 use std::path::PathBuf;
+use std::time::Duration;
+use taskchampion::{ServerConfig, UrgencyCoefficients, Uuid};
+
+mod file;
+
+pub(crate) use file::ConfigFile;
+
+/// Get the path of the configuration file: the value of `$TASKCHAMPION_CONFIG` if set, or
+/// `taskchampion.yaml` (or an existing `taskchampion.yml`) in the user's config directory.  The
+/// file may not exist.
+pub(crate) fn config_file_path() -> Option<PathBuf> {
+    if let Some(config_file) = env::var_os("TASKCHAMPION_CONFIG") {
+        return Some(config_file.into());
+    }
+    let dir = dirs::config_dir()?;
+    let yml = dir.join("taskchampion.yml");
+    if yml.exists() {
+        return Some(yml);
+    }
+    Some(dir.join("taskchampion.yaml"))
+}
+
+/// Get the default data directory, `taskchampion` in the user's local data directory
+pub(crate) fn default_data_dir() -> Option<String> {
+    let mut dir = dirs::data_local_dir()?;
+    dir.push("taskchampion");
+    // the config crate does not support non-string paths
+    Some(dir.to_str().expect("data_local_dir is not utf-8").into())
+}
+
+/// Get the default hooks directory, `taskchampion/hooks` in the user's config directory
+pub(crate) fn default_hooks_dir() -> Option<String> {
+    let mut dir = dirs::config_dir()?;
+    dir.push("taskchampion");
+    dir.push("hooks");
+    Some(dir.to_str().expect("config_dir is not utf-8").into())
+}
 
 pub(crate) fn read_settings() -> Fallible<Config> {
     let mut settings = Config::default();
 
     // set up defaults
-    if let Some(mut dir) = dirs::data_local_dir() {
-        dir.push("taskchampion");
-        settings.set_default(
-            "data_dir",
-            // the config crate does not support non-string paths
-            dir.to_str().expect("data_local_dir is not utf-8"),
-        )?;
+    if let Some(dir) = default_data_dir() {
+        settings.set_default("data_dir", dir)?;
+    }
+    if let Some(dir) = default_hooks_dir() {
+        settings.set_default("hooks_dir", dir)?;
     }
+    settings.set_default("bulk", DEFAULT_BULK)?;
+    settings.set_default("hook_timeout", DEFAULT_HOOK_TIMEOUT)?;
 
     // load either from the path in TASKCHAMPION_CONFIG, or from CONFIG_DIR/taskchampion
-    if let Some(config_file) = env::var_os("TASKCHAMPION_CONFIG") {
+    let config_var = env::var_os("TASKCHAMPION_CONFIG");
+    if let Some(config_file) = config_file_path() {
         log::debug!("Loading configuration from {:?}", config_file);
-        let config_file: PathBuf = config_file.into();
         let config_file: File<FileSourceFile> = config_file.into();
-        settings.merge(config_file.required(true))?;
-        env::remove_var("TASKCHAMPION_CONFIG");
-    } else if let Some(mut dir) = dirs::config_dir() {
-        dir.push("taskchampion");
-        log::debug!("Loading configuration from {:?} (optional)", dir);
-        let config_file: File<FileSourceFile> = dir.into();
-        settings.merge(config_file.required(false))?;
+        settings.merge(config_file.required(config_var.is_some()))?;
     }
 
-    // merge environment variables
-    settings.merge(Environment::with_prefix("TASKCHAMPION"))?;
+    settings.merge(SettingsEnvironment)?;
 
     Ok(settings)
 }
+
+/// The environment variables overriding settings, other than `TASKCHAMPION_CONFIG`, which names
+/// the configuration file rather than setting a `config` key
+#[derive(Clone, Debug)]
+struct SettingsEnvironment;
+
+impl Source for SettingsEnvironment {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<HashMap<String, Value>, ConfigError> {
+        let mut values = Environment::with_prefix("TASKCHAMPION").collect()?;
+        values.remove("config");
+        Ok(values)
+    }
+}
+
+/// The prefix for environment variables overriding settings
+const ENV_PREFIX: &str = "TASKCHAMPION_";
+
+/// Keys which have default values in `read_settings`
+const DEFAULT_KEYS: &[&str] = &["bulk", "data_dir", "hook_timeout", "hooks_dir"];
+
+/// The default for the `bulk` setting: the number of tasks a command may change without asking
+/// for confirmation
+const DEFAULT_BULK: i64 = 3;
+
+/// The default for the `hook_timeout` setting: the number of seconds a hook may run before it is
+/// killed
+const DEFAULT_HOOK_TIMEOUT: i64 = 10;
+
+/// The largest allowed `hook_timeout`, one day
+const MAX_HOOK_TIMEOUT: i64 = 24 * 60 * 60;
+
+/// The origin of the effective value of a setting
+#[derive(Debug, PartialEq)]
+pub(crate) enum Origin {
+    /// A built-in default
+    Default,
+    /// The configuration file
+    File(PathBuf),
+    /// The named environment variable
+    Environment(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::Environment(var) => write!(f, "${}", var),
+        }
+    }
+}
+
+/// Get the environment variable that overrides the given key, if any; nested keys cannot be
+/// overridden.
+pub(crate) fn env_var_for(key: &str) -> Option<String> {
+    if key.contains('.') {
+        None
+    } else {
+        Some(format!("{}{}", ENV_PREFIX, key.to_uppercase()))
+    }
+}
+
+/// Get the names of the environment variables that are set, for `origin` and `all_keys`
+pub(crate) fn env_var_names() -> Vec<String> {
+    env::vars_os()
+        .filter_map(|(var, _)| var.into_string().ok())
+        .collect()
+}
+
+/// Determine where the effective value of the given key comes from, following the same
+/// precedence as `read_settings`, given the names of the environment variables that are set.
+/// Returns None if the key is not set anywhere.
+pub(crate) fn origin(key: &str, file: &ConfigFile, env_vars: &[String]) -> Option<Origin> {
+    if let Some(var) = env_var_for(key) {
+        if env_vars.contains(&var) {
+            return Some(Origin::Environment(var));
+        }
+    }
+    if file.get(key).is_some() {
+        return Some(Origin::File(file.path().into()));
+    }
+    if DEFAULT_KEYS.contains(&key) {
+        return Some(Origin::Default);
+    }
+    None
+}
+
+/// Get all keys that are set, whether by default, in the file, or in the environment, sorted,
+/// given the names of the environment variables that are set.
+pub(crate) fn all_keys(file: &ConfigFile, env_vars: &[String]) -> Vec<String> {
+    let mut keys: Vec<String> = DEFAULT_KEYS.iter().map(|k| k.to_string()).collect();
+    keys.extend(file.values().drain(..).map(|(k, _)| k));
+    for var in env_vars {
+        if let Some(key) = var.strip_prefix(ENV_PREFIX) {
+            if key != "CONFIG" {
+                keys.push(key.to_lowercase());
+            }
+        }
+    }
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Get a string setting, with an error naming the key if it is not set.
+fn get_required_str(settings: &Config, key: &str) -> Fallible<String> {
+    match settings.get_str(key) {
+        Ok(value) => Ok(value),
+        Err(ConfigError::NotFound(_)) => Err(format_err!("Missing configuration key `{}`", key)),
+        Err(e) => Err(format_err!("Invalid configuration key `{}`: {}", key, e)),
+    }
+}
+
+/// Get the configuration for the named server profile, or the default server if `name` is None.
+///
+/// The default server is configured in the `server` section, and named profiles in
+/// `servers.<name>`.  Each has a `type` of `remote` (the default) or `local`.  A remote server
+/// requires `origin` and `client_id`, while a local server requires `server_dir`.  For
+/// compatibility, if there is no `server` section, the default server is a remote server
+/// configured by `server_origin` and `server_client_id`.
+pub(crate) fn server_config(settings: &Config, name: Option<&str>) -> Fallible<ServerConfig> {
+    let prefix = match name {
+        Some(name) => format!("servers.{}", name),
+        None => "server".to_string(),
+    };
+
+    if let Err(ConfigError::NotFound(_)) = settings.get_table(&prefix) {
+        if let Some(name) = name {
+            return Err(format_err!(
+                "No server profile named {:?} (expected configuration section `{}`)",
+                name,
+                prefix
+            ));
+        }
+        let origin = get_required_str(settings, "server_origin")?;
+        let client_id = get_required_str(settings, "server_client_id")?;
+        let client_id = Uuid::parse_str(&client_id)
+            .map_err(|e| format_err!("Invalid configuration key `server_client_id`: {}", e))?;
+        return Ok(ServerConfig::Remote { origin, client_id });
+    }
+
+    let key = |k: &str| format!("{}.{}", prefix, k);
+    let server_type = match settings.get_str(&key("type")) {
+        Ok(t) => t,
+        Err(ConfigError::NotFound(_)) => "remote".to_string(),
+        Err(e) => return Err(e.into()),
+    };
+    match server_type.as_ref() {
+        "remote" => {
+            let origin = get_required_str(settings, &key("origin"))?;
+            let client_id = get_required_str(settings, &key("client_id"))?;
+            let client_id = Uuid::parse_str(&client_id).map_err(|e| {
+                format_err!("Invalid configuration key `{}`: {}", key("client_id"), e)
+            })?;
+            Ok(ServerConfig::Remote { origin, client_id })
+        }
+        "local" => {
+            let server_dir = get_required_str(settings, &key("server_dir"))?;
+            Ok(ServerConfig::Local {
+                server_dir: server_dir.into(),
+            })
+        }
+        _ => Err(format_err!(
+            "Invalid configuration key `{}`: expected `remote` or `local`, got {:?}",
+            key("type"),
+            server_type
+        )),
+    }
+}
+
+/// Get the urgency coefficients, starting from the defaults and overriding them with values in the
+/// `urgency` section, such as `urgency.due`.  Coefficients for specific tags are in
+/// `urgency.tag.<tag>`.
+pub(crate) fn urgency_coefficients(settings: &Config) -> Fallible<UrgencyCoefficients> {
+    let mut coefficients = UrgencyCoefficients::default();
+    {
+        let c = &mut coefficients;
+        let fields: Vec<(&str, &mut f64)> = vec![
+            ("priority_h", &mut c.priority_h),
+            ("priority_m", &mut c.priority_m),
+            ("priority_l", &mut c.priority_l),
+            ("due", &mut c.due),
+            ("age", &mut c.age),
+            ("age_max_days", &mut c.age_max_days),
+            ("active", &mut c.active),
+            ("tags", &mut c.tags),
+            ("project", &mut c.project),
+            ("blocked", &mut c.blocked),
+            ("blocking", &mut c.blocking),
+            ("annotations", &mut c.annotations),
+        ];
+        for (name, field) in fields {
+            let key = format!("urgency.{}", name);
+            match settings.get_float(&key) {
+                Ok(value) => *field = value,
+                Err(ConfigError::NotFound(_)) => {}
+                Err(e) => return Err(format_err!("Invalid configuration key `{}`: {}", key, e)),
+            }
+        }
+    }
+    match settings.get_table("urgency.tag") {
+        Ok(tags) => {
+            for (tag, value) in tags {
+                let value = value.into_float().map_err(|e| {
+                    format_err!("Invalid configuration key `urgency.tag.{}`: {}", tag, e)
+                })?;
+                coefficients.tag.insert(tag, value);
+            }
+        }
+        Err(ConfigError::NotFound(_)) => {}
+        Err(e) => {
+            return Err(format_err!(
+                "Invalid configuration key `urgency.tag`: {}",
+                e
+            ))
+        }
+    }
+    Ok(coefficients)
+}
+
+/// Get the table theme, starting from the default and overriding the style for each row state with
+/// values in the `theme` section, such as `theme.overdue`.
+pub(crate) fn theme(settings: &Config) -> Fallible<Theme> {
+    let mut theme = Theme::default();
+    let fields: Vec<(&str, &mut Vec<Attr>)> = vec![
+        ("active", &mut theme.active),
+        ("overdue", &mut theme.overdue),
+        ("blocked", &mut theme.blocked),
+        ("high_priority", &mut theme.high_priority),
+    ];
+    for (name, field) in fields {
+        let key = format!("theme.{}", name);
+        match settings.get_str(&key) {
+            Ok(value) => {
+                *field = table::parse_style(&value)
+                    .map_err(|e| format_err!("Invalid configuration key `{}`: {}", key, e))?
+            }
+            Err(ConfigError::NotFound(_)) => {}
+            Err(e) => return Err(format_err!("Invalid configuration key `{}`: {}", key, e)),
+        }
+    }
+    Ok(theme)
+}
+
+/// Get the `overflow` setting, which determines how descriptions are fit to the terminal
+pub(crate) fn overflow(settings: &Config) -> Fallible<Overflow> {
+    match settings.get_str("overflow") {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| format_err!("Invalid configuration key `overflow`: {}", e)),
+        Err(ConfigError::NotFound(_)) => Ok(Overflow::default()),
+        Err(e) => Err(format_err!("Invalid configuration key `overflow`: {}", e)),
+    }
+}
+
+/// Get the hooks, configured by `hooks_dir` and `hook_timeout`.  Without a `hooks_dir`, no hooks
+/// are run.
+pub(crate) fn hooks(settings: &Config) -> Fallible<Option<Hooks>> {
+    let dir = match settings.get_str("hooks_dir") {
+        Ok(dir) => dir,
+        Err(ConfigError::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(format_err!("Invalid configuration key `hooks_dir`: {}", e)),
+    };
+    let timeout = match settings.get_float("hook_timeout") {
+        // this comparison also rejects NaN
+        Ok(timeout) if timeout > 0.0 && timeout <= MAX_HOOK_TIMEOUT as f64 => timeout,
+        Ok(_) => {
+            return Err(format_err!(
+                "Invalid configuration key `hook_timeout`: must be a positive number of seconds, at most {}",
+                MAX_HOOK_TIMEOUT
+            ))
+        }
+        Err(ConfigError::NotFound(_)) => DEFAULT_HOOK_TIMEOUT as f64,
+        Err(e) => {
+            return Err(format_err!(
+                "Invalid configuration key `hook_timeout`: {}",
+                e
+            ))
+        }
+    };
+    Ok(Some(Hooks::new(dir, Duration::from_secs_f64(timeout))))
+}
+
+/// Get the terms of a value given either as a string of terms separated by whitespace, or as an
+/// array of terms
+fn terms(key: &str, value: Value) -> Fallible<Vec<String>> {
+    let invalid = |e| format_err!("Invalid configuration key `{}`: {}", key, e);
+    match value.clone().into_array() {
+        Ok(terms) => terms
+            .into_iter()
+            .map(|t| t.into_str())
+            .collect::<Result<_, _>>()
+            .map_err(invalid),
+        Err(_) => Ok(value
+            .into_str()
+            .map_err(invalid)?
+            .split_whitespace()
+            .map(|t| t.to_string())
+            .collect()),
+    }
+}
+
+/// Get the defined contexts, from the `contexts` section, mapping each name to its filter.  A
+/// filter may be given as a string or as an array of terms.
+pub(crate) fn contexts(settings: &Config) -> Fallible<BTreeMap<String, String>> {
+    let table = match settings.get_table("contexts") {
+        Ok(table) => table,
+        Err(ConfigError::NotFound(_)) => return Ok(BTreeMap::new()),
+        Err(e) => return Err(format_err!("Invalid configuration key `contexts`: {}", e)),
+    };
+    let mut contexts = BTreeMap::new();
+    for (name, value) in table {
+        let filter = terms(&format!("contexts.{}", name), value)?.join(" ");
+        contexts.insert(name, filter);
+    }
+    Ok(contexts)
+}
+
+/// Get the command aliases, from the `aliases` section, mapping each name to the arguments it
+/// expands to, given as a string or as an array of arguments.
+pub(crate) fn aliases(settings: &Config) -> Fallible<Aliases> {
+    let table = match settings.get_table("aliases") {
+        Ok(table) => table,
+        Err(ConfigError::NotFound(_)) => return Ok(Aliases::new()),
+        Err(e) => return Err(format_err!("Invalid configuration key `aliases`: {}", e)),
+    };
+    let mut aliases = Aliases::new();
+    for (name, value) in table {
+        let key = format!("aliases.{}", name);
+        let expansion = terms(&key, value)?;
+        if expansion.is_empty() {
+            return Err(format_err!(
+                "Invalid configuration key `{}`: empty alias",
+                key
+            ));
+        }
+        aliases.insert(name, expansion);
+    }
+    Ok(aliases)
+}
+
+/// Get the name and filter of the active context, given by the `context` setting, if any
+pub(crate) fn context(settings: &Config) -> Fallible<Option<(String, Filter)>> {
+    let name = match settings.get_str("context") {
+        Ok(name) if !name.is_empty() => name,
+        Ok(_) | Err(ConfigError::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(format_err!("Invalid configuration key `context`: {}", e)),
+    };
+    let filter = contexts(settings)?.remove(&name).ok_or_else(|| {
+        format_err!(
+            "Context {:?} is not defined (expected configuration key `contexts.{}`)",
+            name,
+            name
+        )
+    })?;
+    let filter = Filter::parse(&filter.split_whitespace().collect::<Vec<_>>())
+        .map_err(|e| format_err!("Invalid filter for context {:?}: {}", name, e))?;
+    if !filter.ids.is_empty() {
+        return Err(format_err!(
+            "Invalid filter for context {:?}: task ids are not allowed",
+            name
+        ));
+    }
+    Ok(Some((name, filter)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+    use yaml_rust::Yaml;
+
+    const CLIENT_ID: &str = "e8e3b4a3-42a5-4f56-8bf1-0d1b35bb2c87";
+
+    fn settings(values: Vec<(&str, &str)>) -> Config {
+        let mut settings = Config::default();
+        for (k, v) in values {
+            settings.set(k, v).unwrap();
+        }
+        settings
+    }
+
+    #[test]
+    fn origins() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let path = tmp_dir.path().join("taskchampion.yaml");
+        let mut file = ConfigFile::load(&path)?;
+        file.set("test_origin_file", Yaml::String("x".into()))?;
+        file.set("test_origin_env", Yaml::String("x".into()))?;
+        let env_vars = vec![
+            "HOME".to_string(),
+            "TASKCHAMPION_CONFIG".to_string(),
+            "TASKCHAMPION_TEST_ORIGIN_ENV".to_string(),
+        ];
+
+        assert_eq!(origin("data_dir", &file, &env_vars), Some(Origin::Default));
+        assert_eq!(
+            origin("test_origin_file", &file, &env_vars),
+            Some(Origin::File(path))
+        );
+        assert_eq!(
+            origin("test_origin_env", &file, &env_vars),
+            Some(Origin::Environment("TASKCHAMPION_TEST_ORIGIN_ENV".into()))
+        );
+        assert_eq!(origin("test_origin_nosuch", &file, &env_vars), None);
+
+        assert_eq!(
+            all_keys(&file, &env_vars),
+            vec![
+                "bulk",
+                "data_dir",
+                "hook_timeout",
+                "hooks_dir",
+                "test_origin_env",
+                "test_origin_file"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn env_var_for_nested() {
+        assert_eq!(
+            env_var_for("data_dir"),
+            Some("TASKCHAMPION_DATA_DIR".into())
+        );
+        assert_eq!(env_var_for("server.origin"), None);
+    }
+
+    #[test]
+    fn legacy_remote() -> Fallible<()> {
+        let settings = settings(vec![
+            ("server_origin", "https://tc.example.com"),
+            ("server_client_id", CLIENT_ID),
+        ]);
+        match server_config(&settings, None)? {
+            ServerConfig::Remote { origin, client_id } => {
+                assert_eq!(origin, "https://tc.example.com");
+                assert_eq!(client_id, Uuid::parse_str(CLIENT_ID)?);
+            }
+            _ => panic!("expected remote server"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_missing_key() {
+        let settings = settings(vec![("server_origin", "https://tc.example.com")]);
+        assert_eq!(
+            server_config(&settings, None).err().unwrap().to_string(),
+            "Missing configuration key `server_client_id`"
+        );
+    }
+
+    #[test]
+    fn default_remote() -> Fallible<()> {
+        let settings = settings(vec![
+            ("server.origin", "https://tc.example.com"),
+            ("server.client_id", CLIENT_ID),
+        ]);
+        match server_config(&settings, None)? {
+            ServerConfig::Remote { origin, .. } => assert_eq!(origin, "https://tc.example.com"),
+            _ => panic!("expected remote server"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn default_local() -> Fallible<()> {
+        let settings = settings(vec![
+            ("server.type", "local"),
+            ("server.server_dir", "/tmp/tc"),
+        ]);
+        match server_config(&settings, None)? {
+            ServerConfig::Local { server_dir } => assert_eq!(server_dir, PathBuf::from("/tmp/tc")),
+            _ => panic!("expected local server"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn named_profile() -> Fallible<()> {
+        let settings = settings(vec![
+            ("server.origin", "https://tc.example.com"),
+            ("server.client_id", CLIENT_ID),
+            ("servers.backup.type", "local"),
+            ("servers.backup.server_dir", "/mnt/backup"),
+        ]);
+        match server_config(&settings, Some("backup"))? {
+            ServerConfig::Local { server_dir } => {
+                assert_eq!(server_dir, PathBuf::from("/mnt/backup"))
+            }
+            _ => panic!("expected local server"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn named_profile_missing_key() {
+        let settings = settings(vec![("servers.backup.type", "local")]);
+        assert_eq!(
+            server_config(&settings, Some("backup"))
+                .err()
+                .unwrap()
+                .to_string(),
+            "Missing configuration key `servers.backup.server_dir`"
+        );
+    }
+
+    #[test]
+    fn unknown_profile() {
+        let settings = settings(vec![]);
+        assert_eq!(
+            server_config(&settings, Some("nosuch"))
+                .err()
+                .unwrap()
+                .to_string(),
+            "No server profile named \"nosuch\" (expected configuration section `servers.nosuch`)"
+        );
+    }
+
+    #[test]
+    fn invalid_type() {
+        let settings = settings(vec![("server.type", "ftp")]);
+        assert_eq!(
+            server_config(&settings, None).err().unwrap().to_string(),
+            "Invalid configuration key `server.type`: expected `remote` or `local`, got \"ftp\""
+        );
+    }
+
+    #[test]
+    fn invalid_client_id() {
+        let settings = settings(vec![
+            ("server.origin", "https://tc.example.com"),
+            ("server.client_id", "not-a-uuid"),
+        ]);
+        assert!(server_config(&settings, None)
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("Invalid configuration key `server.client_id`"));
+    }
+
+    #[test]
+    fn urgency_coefficients_defaults() -> Fallible<()> {
+        let coefficients = urgency_coefficients(&settings(vec![]))?;
+        assert_eq!(coefficients, UrgencyCoefficients::default());
+        Ok(())
+    }
+
+    #[test]
+    fn urgency_coefficients_overrides() -> Fallible<()> {
+        let coefficients = urgency_coefficients(&settings(vec![
+            ("urgency.due", "3.5"),
+            ("urgency.tag.next", "0"),
+            ("urgency.tag.later", "-2"),
+        ]))?;
+        assert_eq!(coefficients.due, 3.5);
+        assert_eq!(coefficients.tag.get("next"), Some(&0.0));
+        assert_eq!(coefficients.tag.get("later"), Some(&-2.0));
+        assert!(urgency_coefficients(&settings(vec![("urgency.age", "old")])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn theme_overrides() -> Fallible<()> {
+        assert_eq!(theme(&settings(vec![]))?, Theme::default());
+        let t = theme(&settings(vec![
+            ("theme.overdue", "bold yellow"),
+            ("theme.active", ""),
+        ]))?;
+        assert_eq!(
+            t.overdue,
+            vec![
+                Attr::Bold,
+                Attr::ForegroundColor(prettytable::color::YELLOW)
+            ]
+        );
+        assert_eq!(t.active, vec![]);
+        assert_eq!(t.blocked, Theme::default().blocked);
+        assert!(theme(&settings(vec![("theme.blocked", "grey")])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn overflow_setting() -> Fallible<()> {
+        assert_eq!(overflow(&settings(vec![]))?, Overflow::Wrap);
+        assert_eq!(
+            overflow(&settings(vec![("overflow", "truncate")]))?,
+            Overflow::Truncate
+        );
+        assert!(overflow(&settings(vec![("overflow", "squash")])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn hooks_setting() -> Fallible<()> {
+        assert_eq!(hooks(&settings(vec![]))?, None);
+        assert_eq!(
+            hooks(&settings(vec![("hooks_dir", "/tmp/hooks")]))?,
+            Some(Hooks::new("/tmp/hooks", Duration::from_secs(10)))
+        );
+        assert_eq!(
+            hooks(&settings(vec![
+                ("hooks_dir", "/tmp/hooks"),
+                ("hook_timeout", "0.5")
+            ]))?,
+            Some(Hooks::new("/tmp/hooks", Duration::from_millis(500)))
+        );
+        for timeout in &["0", "-1", "NaN", "inf", "1e30"] {
+            assert!(hooks(&settings(vec![
+                ("hooks_dir", "/tmp/hooks"),
+                ("hook_timeout", timeout)
+            ]))
+            .is_err());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use failure::{format_err, Fallible};
+use std::fs;
+use std::path::{Path, PathBuf};
+use yaml_rust::yaml::Hash;
+use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
+
+/// A ConfigFile is an editable copy of the YAML configuration file.  Keys are given as
+/// dot-separated paths, such as `server.origin`, as with the `config` crate.  Saving the file
+/// rewrites it entirely, so a file containing comments is never saved, as they would be lost.
+#[derive(Debug)]
+pub(crate) struct ConfigFile {
+    path: PathBuf,
+    root: Hash,
+    /// True if the file contains comments
+    comments: bool,
+}
+
+impl ConfigFile {
+    /// Load the configuration file at the given path; if it does not exist, the result is empty.
+    pub(crate) fn load<P: Into<PathBuf>>(path: P) -> Fallible<ConfigFile> {
+        let path = path.into();
+        let mut comments = false;
+        let root = if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            comments = has_comments(&content);
+            let docs = YamlLoader::load_from_str(&content)
+                .map_err(|e| format_err!("Could not parse {:?}: {}", path, e))?;
+            match docs.into_iter().next() {
+                Some(Yaml::Hash(root)) => root,
+                None | Some(Yaml::Null) => Hash::new(),
+                Some(_) => return Err(format_err!("{:?} does not contain a YAML mapping", path)),
+            }
+        } else {
+            Hash::new()
+        };
+        Ok(ConfigFile {
+            path,
+            root,
+            comments,
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the value at the given key, if it is set
+    pub(crate) fn get(&self, key: &str) -> Option<&Yaml> {
+        let mut value = None;
+        let mut hash = Some(&self.root);
+        for part in key.split('.') {
+            value = hash?.get(&Yaml::String(part.into()));
+            hash = value.and_then(|v| v.as_hash());
+        }
+        value
+    }
+
+    /// Get the value at the given key as a string, if it is set to a scalar value
+    pub(crate) fn get_str(&self, key: &str) -> Option<String> {
+        self.get(key).and_then(scalar_to_string)
+    }
+
+    /// Set the value at the given key, creating any intermediate mappings.  This fails if an
+    /// intermediate key is already set to a non-mapping value.
+    pub(crate) fn set(&mut self, key: &str, value: Yaml) -> Fallible<()> {
+        let parts: Vec<&str> = key.split('.').collect();
+        let (last, parents) = parts
+            .split_last()
+            .expect("split always returns one element");
+        let mut hash = &mut self.root;
+        for (i, part) in parents.iter().enumerate() {
+            let entry = hash
+                .entry(Yaml::String((*part).into()))
+                .or_insert_with(|| Yaml::Hash(Hash::new()));
+            hash = match entry {
+                Yaml::Hash(ref mut h) => h,
+                _ => {
+                    return Err(format_err!(
+                        "Configuration key `{}` is not a section",
+                        parts[..=i].join(".")
+                    ))
+                }
+            };
+        }
+        hash.insert(Yaml::String((*last).into()), value);
+        Ok(())
+    }
+
+    /// Remove the value at the given key, returning true if it was set.  Sections left empty
+    /// are removed as well.
+    pub(crate) fn unset(&mut self, key: &str) -> bool {
+        let parts: Vec<&str> = key.split('.').collect();
+        unset_in(&mut self.root, &parts)
+    }
+
+    /// Get all scalar values in the file, with their full keys, in file order.
+    pub(crate) fn values(&self) -> Vec<(String, String)> {
+        let mut res = vec![];
+        flatten("", &self.root, &mut res);
+        res
+    }
+
+    /// Write the configuration back to its file, creating the containing directory if
+    /// necessary.  This fails if the file contains comments.
+    pub(crate) fn save(&self) -> Fallible<()> {
+        if self.comments {
+            return Err(format_err!(
+                "Not rewriting {:?}, as its comments would be lost; edit it with a text editor instead",
+                self.path
+            ));
+        }
+        let mut out = String::new();
+        YamlEmitter::new(&mut out)
+            .dump(&Yaml::Hash(self.root.clone()))
+            .map_err(|e| format_err!("Could not format configuration: {:?}", e))?;
+        out.push('\n');
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, out)?;
+        Ok(())
+    }
+}
+
+/// Convert a scalar YAML value to a string, as the `config` crate would
+fn scalar_to_string(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(s) => Some(s.clone()),
+        Yaml::Integer(i) => Some(i.to_string()),
+        Yaml::Real(r) => Some(r.clone()),
+        Yaml::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Determine whether YAML text contains a comment: a `#` at the beginning of a line or following
+/// whitespace, outside of a quoted string.  A `#` in a multi-line string may be mistaken for a
+/// comment, which only errs on the side of caution.
+fn has_comments(content: &str) -> bool {
+    content.lines().any(|line| {
+        let mut quote = None;
+        let mut prev = ' ';
+        for c in line.chars() {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None if c == '#' && prev.is_whitespace() => return true,
+                None if (c == '"' || c == '\'')
+                    && (prev.is_whitespace() || "[{,".contains(prev)) =>
+                {
+                    quote = Some(c)
+                }
+                None => {}
+            }
+            prev = c;
+        }
+        false
+    })
+}
+
+fn unset_in(hash: &mut Hash, parts: &[&str]) -> bool {
+    let key = Yaml::String(parts[0].into());
+    if parts.len() == 1 {
+        return hash.remove(&key).is_some();
+    }
+    let removed = match hash.get_mut(&key) {
+        Some(Yaml::Hash(child)) => {
+            let removed = unset_in(child, &parts[1..]);
+            if !child.is_empty() {
+                return removed;
+            }
+            removed
+        }
+        _ => return false,
+    };
+    hash.remove(&key);
+    removed
+}
+
+fn flatten(prefix: &str, hash: &Hash, res: &mut Vec<(String, String)>) {
+    for (k, v) in hash.iter() {
+        let k = match scalar_to_string(k) {
+            Some(k) => k,
+            None => continue,
+        };
+        let key = if prefix.is_empty() {
+            k
+        } else {
+            format!("{}.{}", prefix, k)
+        };
+        match v {
+            Yaml::Hash(child) => flatten(&key, child, res),
+            v => {
+                if let Some(v) = scalar_to_string(v) {
+                    res.push((key, v));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn load_missing() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let file = ConfigFile::load(tmp_dir.path().join("taskchampion.yaml"))?;
+        assert_eq!(file.values(), vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn set_get_save() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let path = tmp_dir.path().join("sub").join("taskchampion.yaml");
+        let mut file = ConfigFile::load(&path)?;
+        file.set("data_dir", Yaml::String("/tmp/tasks".into()))?;
+        file.set(
+            "server.origin",
+            Yaml::String("https://tc.example.com".into()),
+        )?;
+        file.save()?;
+
+        let file = ConfigFile::load(&path)?;
+        assert_eq!(file.get_str("data_dir"), Some("/tmp/tasks".into()));
+        assert_eq!(
+            file.get_str("server.origin"),
+            Some("https://tc.example.com".into())
+        );
+        assert_eq!(file.get_str("server"), None);
+        assert_eq!(file.get_str("server.nosuch"), None);
+        assert_eq!(
+            file.values(),
+            vec![
+                ("data_dir".into(), "/tmp/tasks".into()),
+                ("server.origin".into(), "https://tc.example.com".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn set_through_scalar() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut file = ConfigFile::load(tmp_dir.path().join("taskchampion.yaml"))?;
+        file.set("server", Yaml::String("x".into()))?;
+        assert_eq!(
+            file.set("server.origin", Yaml::String("y".into()))
+                .err()
+                .unwrap()
+                .to_string(),
+            "Configuration key `server` is not a section"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unset() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut file = ConfigFile::load(tmp_dir.path().join("taskchampion.yaml"))?;
+        file.set("servers.backup.type", Yaml::String("local".into()))?;
+        file.set("data_dir", Yaml::String("/tmp".into()))?;
+        assert!(!file.unset("servers.nosuch.type"));
+        assert!(file.unset("servers.backup.type"));
+        assert!(file.get("servers").is_none());
+        assert_eq!(file.values(), vec![("data_dir".into(), "/tmp".into())]);
+        Ok(())
+    }
+
+    #[test]
+    fn comments() {
+        assert!(has_comments("# configuration\ndata_dir: /tmp\n"));
+        assert!(has_comments("data_dir: /tmp  # scratch\n"));
+        assert!(has_comments("theme:\n  # colors\n  header: bold\n"));
+        assert!(!has_comments("data_dir: /tmp#1\n"));
+        assert!(!has_comments("color: \"# ff0000\"\n"));
+        assert!(!has_comments("filter: [\"+a #b\", 'c #d']\n"));
+        assert!(has_comments("description: don't # note\n"));
+    }
+
+    #[test]
+    fn save_with_comments() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let path = tmp_dir.path().join("taskchampion.yaml");
+        fs::write(&path, "# my settings\ndata_dir: /tmp\n")?;
+        let mut file = ConfigFile::load(&path)?;
+        file.set("bulk", Yaml::String("5".into()))?;
+        assert!(file.save().is_err());
+        assert_eq!(
+            fs::read_to_string(&path)?,
+            "# my settings\ndata_dir: /tmp\n"
+        );
+        Ok(())
+    }
+}
@@ -1,4 +1,10 @@
-use prettytable::format;
+use crate::output;
+use failure::{format_err, Fallible};
+use prettytable::{color, format, Attr, Cell, Row};
+use std::env;
+use std::io::Write;
+use std::str::FromStr;
+use unicode_width::UnicodeWidthStr;
 
 pub(crate) fn format() -> format::TableFormat {
     format::FormatBuilder::new()
@@ -6,3 +12,340 @@ pub(crate) fn format() -> format::TableFormat {
         .borders(' ')
         .build()
 }
+
+/// The narrowest the flexible column of a table will be made to fit the terminal
+const MIN_FLEXIBLE_WIDTH: usize = 15;
+
+/// The state of the task shown in a table row, which determines the row's style.  A task in
+/// several states is shown in the first of them, in the order given here.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RowState {
+    Active,
+    Overdue,
+    Blocked,
+    HighPriority,
+}
+
+/// A Theme gives the style for rows in each [`RowState`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Theme {
+    pub(crate) active: Vec<Attr>,
+    pub(crate) overdue: Vec<Attr>,
+    pub(crate) blocked: Vec<Attr>,
+    pub(crate) high_priority: Vec<Attr>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            active: vec![Attr::Bold, Attr::ForegroundColor(color::GREEN)],
+            overdue: vec![Attr::ForegroundColor(color::RED)],
+            blocked: vec![Attr::ForegroundColor(color::BRIGHT_BLACK)],
+            high_priority: vec![Attr::Bold],
+        }
+    }
+}
+
+impl Theme {
+    fn style(&self, state: RowState) -> &[Attr] {
+        match state {
+            RowState::Active => &self.active,
+            RowState::Overdue => &self.overdue,
+            RowState::Blocked => &self.blocked,
+            RowState::HighPriority => &self.high_priority,
+        }
+    }
+}
+
+/// Parse a style, such as `bold red on white`: any of `bold`, `dim`, `italic`, `underline`,
+/// `blink`, and `reverse`, a foreground color, and a background color following `on`.  The colors
+/// are `black`, `red`, `green`, `yellow`, `blue`, `magenta`, `cyan`, and `white`, each optionally
+/// prefixed with `bright_`.  An empty style has no effect.
+pub(crate) fn parse_style(s: &str) -> Fallible<Vec<Attr>> {
+    let mut attrs = vec![];
+    let mut words = s.split_whitespace();
+    while let Some(word) = words.next() {
+        attrs.push(match word {
+            "bold" => Attr::Bold,
+            "dim" => Attr::Dim,
+            "italic" => Attr::Italic(true),
+            "underline" => Attr::Underline(true),
+            "blink" => Attr::Blink,
+            "reverse" => Attr::Reverse,
+            "on" => {
+                let background = words
+                    .next()
+                    .ok_or_else(|| format_err!("Expected a color after `on` in style {:?}", s))?;
+                Attr::BackgroundColor(parse_color(background)?)
+            }
+            _ => Attr::ForegroundColor(parse_color(word)?),
+        });
+    }
+    Ok(attrs)
+}
+
+fn parse_color(s: &str) -> Fallible<color::Color> {
+    let (name, bright) = match s.strip_prefix("bright_") {
+        Some(name) => (name, true),
+        None => (s, false),
+    };
+    let color = match name {
+        "black" => color::BLACK,
+        "red" => color::RED,
+        "green" => color::GREEN,
+        "yellow" => color::YELLOW,
+        "blue" => color::BLUE,
+        "magenta" => color::MAGENTA,
+        "cyan" => color::CYAN,
+        "white" => color::WHITE,
+        _ => return Err(format_err!("Unknown color or attribute {:?}", s)),
+    };
+    // the bright colors follow the normal colors, in the same order
+    Ok(if bright { color + 8 } else { color })
+}
+
+/// How to fit the flexible column of a table, such as a task's description, to the terminal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Overflow {
+    /// Wrap the value onto several lines
+    Wrap,
+    /// Truncate the value, ending with an ellipsis
+    Truncate,
+}
+
+impl Default for Overflow {
+    fn default() -> Self {
+        Overflow::Wrap
+    }
+}
+
+impl FromStr for Overflow {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        match s {
+            "wrap" => Ok(Overflow::Wrap),
+            "truncate" => Ok(Overflow::Truncate),
+            _ => Err(format_err!("expected `wrap` or `truncate`, got {:?}", s)),
+        }
+    }
+}
+
+/// The properties of the terminal that affect how tables are rendered
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Terminal {
+    /// The width of the terminal, if known
+    pub(crate) width: Option<usize>,
+    /// Whether to use color and other styles
+    pub(crate) color: bool,
+}
+
+impl Terminal {
+    /// Detect the properties of the terminal on stdout.  If stdout is not a terminal, the width is
+    /// unknown and color is disabled; color is also disabled if `NO_COLOR` is set.
+    pub(crate) fn detect() -> Self {
+        if !atty::is(atty::Stream::Stdout) {
+            return Terminal::default();
+        }
+        Terminal {
+            width: terminal_size::terminal_size().map(|(w, _)| w.0 as usize),
+            color: !matches!(env::var_os("NO_COLOR"), Some(v) if !v.is_empty()),
+        }
+    }
+}
+
+/// A Renderer writes [`output::Table`]s for humans, fitting them to the terminal and styling
+/// their rows according to the theme.
+#[derive(Debug, Default)]
+pub(crate) struct Renderer {
+    pub(crate) terminal: Terminal,
+    pub(crate) theme: Theme,
+    pub(crate) overflow: Overflow,
+}
+
+impl Renderer {
+    pub(crate) fn render<W: Write>(&self, w: &mut W, table: &output::Table) -> Fallible<()> {
+        let rows = self.fit(table);
+
+        let mut t = prettytable::Table::new();
+        t.set_format(format());
+        let bold = |s: &String| Cell::new(s).with_style(Attr::Bold);
+        if !table.labeled {
+            t.set_titles(Row::new(table.headings.iter().map(bold).collect()));
+        }
+        for (row, state) in rows.iter().zip(table.states.iter()) {
+            let style = state.map(|s| self.theme.style(s)).unwrap_or_default();
+            t.add_row(Row::new(
+                row.iter()
+                    .enumerate()
+                    .map(|(i, v)| {
+                        let mut cell = if table.labeled && i == 0 {
+                            bold(v)
+                        } else {
+                            Cell::new(v)
+                        };
+                        for attr in style {
+                            cell.style(*attr);
+                        }
+                        cell
+                    })
+                    .collect(),
+            ));
+        }
+
+        if self.terminal.color {
+            if let Some(mut term) = term::TerminfoTerminal::new(&mut *w) {
+                t.print_term(&mut term)?;
+                return Ok(());
+            }
+        }
+        t.print(w)?;
+        Ok(())
+    }
+
+    /// Get the table's rows, with the values in the flexible column wrapped or truncated so that
+    /// the table fits in the terminal.
+    fn fit(&self, table: &output::Table) -> Vec<Vec<String>> {
+        let (width, flexible) = match (self.terminal.width, table.flexible) {
+            (Some(width), Some(flexible)) => (width, flexible),
+            _ => return table.rows.clone(),
+        };
+
+        let mut widths = vec![0; table.headings.len()];
+        let headings = if table.labeled {
+            None
+        } else {
+            Some(&table.headings)
+        };
+        for row in headings.into_iter().chain(table.rows.iter()) {
+            for (i, value) in row.iter().enumerate() {
+                let w = value.lines().map(|l| l.width()).max().unwrap_or(0);
+                widths[i] = widths[i].max(w);
+            }
+        }
+
+        // each column is followed by a separator, and the table begins with a border
+        let others: usize = widths
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != flexible)
+            .map(|(_, w)| w + 1)
+            .sum();
+        let available = width.saturating_sub(others + 2).max(MIN_FLEXIBLE_WIDTH);
+        if widths[flexible] <= available {
+            return table.rows.clone();
+        }
+
+        table
+            .rows
+            .iter()
+            .map(|row| {
+                let mut row = row.clone();
+                row[flexible] = match self.overflow {
+                    Overflow::Wrap => textwrap::fill(&row[flexible], available),
+                    Overflow::Truncate => truncate(&row[flexible], available),
+                };
+                row
+            })
+            .collect()
+    }
+}
+
+/// Truncate a string to the given display width, ending it with an ellipsis if it is too long
+fn truncate(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.into();
+    }
+    let mut result = String::new();
+    for c in s.chars() {
+        result.push(c);
+        if result.width() >= width {
+            result.pop();
+            break;
+        }
+    }
+    result.push('…');
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table() -> output::Table {
+        let mut t = output::Table::new(&["id", "description"]);
+        t.set_flexible(1);
+        t.add_row(&["1", "a rather long description of the task"]);
+        t.add_row_with_state(&["2", "short"], Some(RowState::Active));
+        t
+    }
+
+    fn render(renderer: &Renderer, table: &output::Table) -> String {
+        let mut out = vec![];
+        renderer.render(&mut out, table).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parse_styles() -> Fallible<()> {
+        assert_eq!(parse_style("")?, vec![]);
+        assert_eq!(
+            parse_style("bold red on bright_white")?,
+            vec![
+                Attr::Bold,
+                Attr::ForegroundColor(color::RED),
+                Attr::BackgroundColor(color::BRIGHT_WHITE)
+            ]
+        );
+        assert!(parse_style("purple").is_err());
+        assert!(parse_style("red on").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn truncate_width() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly 10", 10), "exactly 10");
+        assert_eq!(truncate("a bit too long", 10), "a bit too…");
+    }
+
+    #[test]
+    fn render_plain() {
+        let out = render(&Renderer::default(), &table());
+        assert!(out.contains(" id description"));
+        assert!(out.contains("a rather long description of the task"));
+        assert!(!out.contains('\x1b'));
+    }
+
+    #[test]
+    fn render_wrapped() {
+        let renderer = Renderer {
+            terminal: Terminal {
+                width: Some(25),
+                color: false,
+            },
+            ..Default::default()
+        };
+        let out = render(&renderer, &table());
+        assert!(out.lines().all(|l| l.width() <= 25));
+        assert!(out.contains(" 1  a rather long "));
+        assert!(out.contains("    description of the "));
+        assert_eq!(out.lines().count(), 5);
+    }
+
+    #[test]
+    fn render_truncated() {
+        let renderer = Renderer {
+            terminal: Terminal {
+                width: Some(25),
+                color: false,
+            },
+            overflow: Overflow::Truncate,
+            ..Default::default()
+        };
+        let out = render(&renderer, &table());
+        assert!(out.lines().all(|l| l.width() <= 25));
+        assert!(out.contains("a rather long descr…"));
+        assert_eq!(out.lines().count(), 3);
+    }
+}
@@ -1,6 +1,8 @@
 use assert_cmd::prelude::*;
 use predicates::prelude::*;
+use std::fs;
 use std::process::Command;
+use tempdir::TempDir;
 
 // This tests that the task binary is running and parsing arguments.  The details of subcommands
 // are handled with unit tests.
@@ -40,3 +42,28 @@ fn invalid_option() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn invalid_aliases() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = TempDir::new("cli")?;
+    let config = dir.path().join("taskchampion.yml");
+    fs::write(&config, "aliases:\n  x: []\n")?;
+
+    // invalid aliases are ignored with a warning
+    let mut cmd = Command::cargo_bin("task")?;
+    cmd.env("TASKCHAMPION_CONFIG", &config).arg("--help");
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Warning: ignoring aliases"));
+
+    // and `config` does not read them at all
+    let mut cmd = Command::cargo_bin("task")?;
+    cmd.env("TASKCHAMPION_CONFIG", &config)
+        .args(&["config", "path"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("taskchampion.yml"))
+        .stderr(predicate::str::is_empty());
+
+    Ok(())
+}
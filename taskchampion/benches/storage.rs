@@ -0,0 +1,98 @@
+//! Benchmarks for indexed storage lookups against a large task database.
+//!
+//! Run with `cargo bench -p taskchampion`.  This uses a simple timing loop rather than a
+//! benchmarking framework, so results are only roughly comparable between runs.
+
+use failure::Fallible;
+use std::time::{Duration, Instant};
+use taskchampion::taskstorage::{KVStorage, TaskMap, TaskStorage};
+use taskchampion::{Replica, Status, Uuid};
+use tempdir::TempDir;
+
+/// Number of tasks in the benchmark database
+const NUM_TASKS: usize = 50_000;
+
+/// One in this many tasks is pending; the rest are completed
+const PENDING_RATIO: usize = 10;
+
+/// Number of iterations of each benchmark
+const ITERATIONS: u32 = 20;
+
+/// Populate the storage with NUM_TASKS tasks in a single transaction, returning the uuids of the
+/// pending tasks.
+fn populate(storage: &mut KVStorage) -> Fallible<Vec<Uuid>> {
+    let mut pending = vec![];
+    let mut txn = storage.txn()?;
+    for i in 0..NUM_TASKS {
+        let uuid = Uuid::new_v4();
+        let status = if i % PENDING_RATIO == 0 { "P" } else { "C" };
+        let mut task = TaskMap::new();
+        task.insert("status".into(), status.into());
+        task.insert("description".into(), format!("task {}", i));
+        txn.set_task(uuid, task)?;
+        if status == "P" {
+            txn.add_to_working_set(&uuid)?;
+            pending.push(uuid);
+        }
+    }
+    txn.commit()?;
+    Ok(pending)
+}
+
+fn bench<F: FnMut() -> Fallible<()>>(name: &str, mut f: F) -> Fallible<()> {
+    let mut total = Duration::default();
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        f()?;
+        total += start.elapsed();
+    }
+    println!("{:<40} {:>12.3?}/iter", name, total / ITERATIONS);
+    Ok(())
+}
+
+fn main() -> Fallible<()> {
+    let tmp_dir = TempDir::new("bench")?;
+    let mut storage = KVStorage::new(tmp_dir.path())?;
+
+    let start = Instant::now();
+    let pending = populate(&mut storage)?;
+    println!(
+        "populated {} tasks ({} pending) in {:.3?}",
+        NUM_TASKS,
+        pending.len(),
+        start.elapsed()
+    );
+
+    let mut replica = Replica::new(Box::new(storage));
+    let uuid = pending[pending.len() / 2];
+
+    bench("working-set index lookup", || {
+        assert!(replica.get_working_set_index(&uuid)?.is_some());
+        Ok(())
+    })?;
+    bench("working-set scan", || {
+        let ws = replica.working_set()?;
+        assert!(ws
+            .iter()
+            .any(|t| t.as_ref().map(|t| t.get_uuid()) == Some(&uuid)));
+        Ok(())
+    })?;
+    bench("pending tasks (status index)", || {
+        assert_eq!(
+            replica.tasks_with_status(Status::Pending)?.len(),
+            pending.len()
+        );
+        Ok(())
+    })?;
+    bench("pending tasks (full scan)", || {
+        let n = replica
+            .all_tasks()?
+            .values()
+            .filter(|t| t.get_status() == Status::Pending)
+            .count();
+        assert_eq!(n, pending.len());
+        Ok(())
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use crate::task::TIMESTAMP_PROPERTIES;
+use crate::taskdb::TaskDB;
+use crate::taskstorage::{Operation, TaskMap};
+use chrono::{TimeZone, Utc};
+use failure::Fallible;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use uuid::Uuid;
+
+/// A Problem is an inconsistency in a replica's data, as found by
+/// [`crate::Replica::check`].  Each problem can be fixed by [`crate::Replica::repair`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Problem {
+    /// A working-set entry refers to a task that does not exist.  Repair removes the entry.
+    MissingWorkingSetTask { index: usize, uuid: Uuid },
+
+    /// A task appears in the working set more than once; this is the later entry.  Repair
+    /// removes the entry.
+    DuplicateWorkingSetEntry { index: usize, uuid: Uuid },
+
+    /// Operations waiting to be synchronized refer to a task that does not exist and was not
+    /// deleted.  Repair drops those operations.
+    DanglingOperations { uuid: Uuid, count: usize },
+
+    /// A task has a timestamp property that is not a valid timestamp.  Repair removes the
+    /// property.
+    InvalidTimestamp {
+        uuid: Uuid,
+        property: String,
+        value: String,
+    },
+
+    /// A task has a status that is not one of `P`, `C`, or `D`.  Such tasks are treated as
+    /// pending, so repair sets the status to `P`.
+    UnknownStatus { uuid: Uuid, value: String },
+
+    /// A task has a `start.*` key whose name is not a timestamp, or which is still open although
+    /// the task is no longer pending.  Repair removes the key in the first case, and closes it
+    /// at the task's end (or last modification) in the second.
+    OrphanedStart { uuid: Uuid, property: String },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::MissingWorkingSetTask { index, uuid } => write!(
+                f,
+                "working set entry {} refers to missing task {}",
+                index, uuid
+            ),
+            Problem::DuplicateWorkingSetEntry { index, uuid } => write!(
+                f,
+                "working set entry {} duplicates an earlier entry for task {}",
+                index, uuid
+            ),
+            Problem::DanglingOperations { uuid, count } => write!(
+                f,
+                "{} unsynchronized operation(s) refer to missing task {}",
+                count, uuid
+            ),
+            Problem::InvalidTimestamp {
+                uuid,
+                property,
+                value,
+            } => write!(
+                f,
+                "task {} has invalid timestamp {:?} in {}",
+                uuid, value, property
+            ),
+            Problem::UnknownStatus { uuid, value } => {
+                write!(f, "task {} has unknown status {:?}", uuid, value)
+            }
+            Problem::OrphanedStart { uuid, property } => {
+                write!(f, "task {} has orphaned {}", uuid, property)
+            }
+        }
+    }
+}
+
+/// Check the given TaskDB for problems, returning them in a stable order.
+pub(crate) fn check(taskdb: &mut TaskDB) -> Fallible<Vec<Problem>> {
+    let mut problems = vec![];
+    let mut tasks: Vec<(Uuid, TaskMap)> = taskdb.all_tasks()?;
+    tasks.sort_by_key(|(uuid, _)| *uuid);
+    let uuids: HashSet<Uuid> = tasks.iter().map(|(u, _)| *u).collect();
+
+    let mut seen = HashSet::new();
+    for (index, uuid) in taskdb.working_set()?.iter().enumerate() {
+        if let Some(uuid) = uuid {
+            if !uuids.contains(uuid) {
+                problems.push(Problem::MissingWorkingSetTask { index, uuid: *uuid });
+            } else if !seen.insert(*uuid) {
+                problems.push(Problem::DuplicateWorkingSetEntry { index, uuid: *uuid });
+            }
+        }
+    }
+
+    problems.extend(check_operations(&taskdb.local_operations()?, &uuids));
+
+    for (uuid, task) in tasks.iter() {
+        check_task(*uuid, task, &mut problems);
+    }
+
+    Ok(problems)
+}
+
+/// Find operations referring to tasks that do not exist.  Operations for a task that was
+/// deleted are expected, so only tasks whose last operation is not a Delete are reported.
+fn check_operations(operations: &[Operation], uuids: &HashSet<Uuid>) -> Vec<Problem> {
+    // missing uuids, in order of first appearance
+    let mut missing: Vec<Uuid> = vec![];
+    // (count of operations, whether the last was a Delete) for each missing uuid
+    let mut state: HashMap<Uuid, (usize, bool)> = HashMap::new();
+    for op in operations {
+        let uuid = op.uuid();
+        let deleted = matches!(op, Operation::Delete { .. });
+        if uuids.contains(uuid) {
+            continue;
+        }
+        let entry = state.entry(*uuid).or_insert_with(|| {
+            missing.push(*uuid);
+            (0, false)
+        });
+        entry.0 += 1;
+        entry.1 = deleted;
+    }
+
+    missing
+        .drain(..)
+        .filter_map(|uuid| match state[&uuid] {
+            (_, true) => None,
+            (count, false) => Some(Problem::DanglingOperations { uuid, count }),
+        })
+        .collect()
+}
+
+/// Determine whether a value is an epoch timestamp within the range of dates that can be
+/// represented
+pub(crate) fn is_timestamp(value: &str) -> bool {
+    match value.parse() {
+        Ok(ts) => Utc.timestamp_opt(ts, 0).single().is_some(),
+        Err(_) => false,
+    }
+}
+
+fn check_task(uuid: Uuid, task: &TaskMap, problems: &mut Vec<Problem>) {
+    let status = task.get("status").map(|s| s.as_ref()).unwrap_or("P");
+    if !["P", "C", "D"].contains(&status) {
+        problems.push(Problem::UnknownStatus {
+            uuid,
+            value: status.into(),
+        });
+    }
+
+    let mut properties: Vec<(&String, &String)> = task.iter().collect();
+    properties.sort();
+    for (property, value) in properties {
+        if TIMESTAMP_PROPERTIES.contains(&property.as_ref()) && !is_timestamp(value) {
+            problems.push(Problem::InvalidTimestamp {
+                uuid,
+                property: property.clone(),
+                value: value.clone(),
+            });
+        } else if let Some(started) = property.strip_prefix("start.") {
+            if !is_timestamp(started) || (value.is_empty() && status != "P") {
+                problems.push(Problem::OrphanedStart {
+                    uuid,
+                    property: property.clone(),
+                });
+            } else if !value.is_empty() && !is_timestamp(value) {
+                problems.push(Problem::InvalidTimestamp {
+                    uuid,
+                    property: property.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::taskstorage::taskmap_with;
+
+    #[test]
+    fn check_operations_deleted() {
+        let uuid1 = Uuid::new_v4();
+        let uuid2 = Uuid::new_v4();
+        let ops = vec![
+            Operation::Create { uuid: uuid1 },
+            Operation::Delete { uuid: uuid1 },
+            Operation::Create { uuid: uuid2 },
+        ];
+        let mut uuids = HashSet::new();
+        assert_eq!(
+            check_operations(&ops, &uuids),
+            vec![Problem::DanglingOperations {
+                uuid: uuid2,
+                count: 1
+            }]
+        );
+        uuids.insert(uuid2);
+        assert_eq!(check_operations(&ops, &uuids), vec![]);
+    }
+
+    #[test]
+    fn check_task_ok() {
+        let mut problems = vec![];
+        let task = taskmap_with(vec![
+            ("status".into(), "C".into()),
+            ("end".into(), "1234".into()),
+            ("start.1200".into(), "1230".into()),
+        ]);
+        check_task(Uuid::new_v4(), &task, &mut problems);
+        assert_eq!(problems, vec![]);
+    }
+
+    #[test]
+    fn check_task_problems() {
+        let uuid = Uuid::new_v4();
+        let mut problems = vec![];
+        let task = taskmap_with(vec![
+            ("status".into(), "X".into()),
+            ("due".into(), "tomorrow".into()),
+            ("start.later".into(), "".into()),
+            ("start.1200".into(), "soon".into()),
+            ("wait".into(), "99999999999999999".into()),
+            ("start.99999999999999999".into(), "".into()),
+        ]);
+        check_task(uuid, &task, &mut problems);
+        assert_eq!(
+            problems,
+            vec![
+                Problem::UnknownStatus {
+                    uuid,
+                    value: "X".into()
+                },
+                Problem::InvalidTimestamp {
+                    uuid,
+                    property: "due".into(),
+                    value: "tomorrow".into()
+                },
+                Problem::InvalidTimestamp {
+                    uuid,
+                    property: "start.1200".into(),
+                    value: "soon".into()
+                },
+                Problem::OrphanedStart {
+                    uuid,
+                    property: "start.99999999999999999".into()
+                },
+                Problem::OrphanedStart {
+                    uuid,
+                    property: "start.later".into()
+                },
+                Problem::InvalidTimestamp {
+                    uuid,
+                    property: "wait".into(),
+                    value: "99999999999999999".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn check_task_open_start_not_pending() {
+        let uuid = Uuid::new_v4();
+        let mut problems = vec![];
+        let task = taskmap_with(vec![
+            ("status".into(), "D".into()),
+            ("start.1200".into(), "".into()),
+        ]);
+        check_task(uuid, &task, &mut problems);
+        assert_eq!(
+            problems,
+            vec![Problem::OrphanedStart {
+                uuid,
+                property: "start.1200".into()
+            }]
+        );
+    }
+}
@@ -4,4 +4,8 @@ use failure::Fail;
 pub enum Error {
     #[fail(display = "Task Database Error: {}", _0)]
     DBError(String),
+    #[fail(display = "Snapshot Error: {}", _0)]
+    SnapshotError(String),
+    #[fail(display = "UUID prefix {} is ambiguous; it matches {}", _0, _1)]
+    AmbiguousUuidPrefix(String, String),
 }
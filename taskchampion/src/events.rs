@@ -0,0 +1,180 @@
+use crate::taskstorage::Operation;
+use uuid::Uuid;
+
+/// The origin of a change to the task database.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChangeSource {
+    /// The change was made by this replica.
+    Local,
+    /// The change was received from the server during synchronization.
+    Sync,
+}
+
+/// An event describing a change to the task database.  Events are delivered to observers only
+/// after the change has been committed to storage.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Event {
+    /// A task was created
+    TaskCreated { uuid: Uuid, source: ChangeSource },
+
+    /// A property of a task was set (if `value` is Some) or removed (if `value` is None)
+    TaskUpdated {
+        uuid: Uuid,
+        property: String,
+        value: Option<String>,
+        source: ChangeSource,
+    },
+
+    /// A task was deleted
+    TaskDeleted { uuid: Uuid, source: ChangeSource },
+
+    /// The working set was rebuilt and changed, so existing working-set indexes may have changed.
+    /// This only ever occurs locally, as the working set is not synchronized.
+    WorkingSetRenumbered,
+}
+
+impl Event {
+    pub(crate) fn from_operation(op: &Operation, source: ChangeSource) -> Event {
+        match op {
+            Operation::Create { uuid } => Event::TaskCreated {
+                uuid: *uuid,
+                source,
+            },
+            Operation::Delete { uuid } => Event::TaskDeleted {
+                uuid: *uuid,
+                source,
+            },
+            Operation::Update {
+                uuid,
+                property,
+                value,
+                ..
+            } => Event::TaskUpdated {
+                uuid: *uuid,
+                property: property.clone(),
+                value: value.clone(),
+                source,
+            },
+        }
+    }
+
+    /// Get the source of this event.
+    pub fn source(&self) -> ChangeSource {
+        match self {
+            Event::TaskCreated { source, .. } => *source,
+            Event::TaskUpdated { source, .. } => *source,
+            Event::TaskDeleted { source, .. } => *source,
+            Event::WorkingSetRenumbered => ChangeSource::Local,
+        }
+    }
+}
+
+/// An Observer is notified of each change to the task database.  This is implemented for any
+/// `FnMut(&Event)`, so closures can be used directly.
+pub trait Observer {
+    fn on_event(&mut self, event: &Event);
+}
+
+impl<F: FnMut(&Event)> Observer for F {
+    fn on_event(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+/// An identifier for a subscription, used to unsubscribe.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SubscriptionId(usize);
+
+/// A collection of subscribed observers.
+#[derive(Default)]
+pub(crate) struct Observers {
+    observers: Vec<(SubscriptionId, Box<dyn Observer>)>,
+    next_id: usize,
+}
+
+impl Observers {
+    pub(crate) fn subscribe(&mut self, observer: Box<dyn Observer>) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.observers.push((id, observer));
+        id
+    }
+
+    /// Remove the given subscription, returning true if it existed.
+    pub(crate) fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.observers.len();
+        self.observers.retain(|(i, _)| *i != id);
+        self.observers.len() != len
+    }
+
+    /// Notify all observers of the given event.
+    pub(crate) fn notify(&mut self, event: &Event) {
+        for (_, observer) in self.observers.iter_mut() {
+            observer.on_event(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn from_operation() {
+        let uuid = Uuid::new_v4();
+        assert_eq!(
+            Event::from_operation(&Operation::Create { uuid }, ChangeSource::Local),
+            Event::TaskCreated {
+                uuid,
+                source: ChangeSource::Local
+            }
+        );
+        assert_eq!(
+            Event::from_operation(&Operation::Delete { uuid }, ChangeSource::Sync),
+            Event::TaskDeleted {
+                uuid,
+                source: ChangeSource::Sync
+            }
+        );
+        let event = Event::from_operation(
+            &Operation::Update {
+                uuid,
+                property: "title".into(),
+                value: Some("x".into()),
+                timestamp: Utc::now(),
+            },
+            ChangeSource::Sync,
+        );
+        assert_eq!(
+            event,
+            Event::TaskUpdated {
+                uuid,
+                property: "title".into(),
+                value: Some("x".into()),
+                source: ChangeSource::Sync
+            }
+        );
+        assert_eq!(event.source(), ChangeSource::Sync);
+    }
+
+    #[test]
+    fn subscribe_unsubscribe() {
+        let seen = Rc::new(RefCell::new(vec![]));
+        let mut observers = Observers::default();
+
+        let seen1 = seen.clone();
+        let id = observers.subscribe(Box::new(move |e: &Event| {
+            seen1.borrow_mut().push(e.clone())
+        }));
+        observers.notify(&Event::WorkingSetRenumbered);
+        assert_eq!(*seen.borrow(), vec![Event::WorkingSetRenumbered]);
+
+        assert!(observers.unsubscribe(id));
+        assert!(!observers.unsubscribe(id));
+        observers.notify(&Event::WorkingSetRenumbered);
+        assert_eq!(seen.borrow().len(), 1);
+    }
+}
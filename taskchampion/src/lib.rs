@@ -11,6 +11,16 @@ synchronize with one another.
 The [`taskstorage`](crate::taskstorage) module supports pluggable storage for a replica's data.
 An implementation is provided, but users of this crate can provide their own implementation as well.
 
+# Taskwarrior Compatibility
+
+The [`taskwarrior`](crate::taskwarrior) module supports importing and exporting tasks in the JSON
+format used by Taskwarrior.
+
+# Observing Changes
+
+Applications such as GUIs can [subscribe](crate::Replica::subscribe) to a replica to be notified
+of each change to the task data, whether made locally or received during synchronization.
+
 # Server
 
 Replica synchronization takes place against a server.
@@ -23,20 +33,33 @@ for more information about the design and usage of the tool.
 
  */
 
+mod check;
 mod config;
 mod errors;
+mod events;
 mod replica;
 pub mod server;
+mod snapshot;
+mod stats;
 mod task;
 mod taskdb;
 pub mod taskstorage;
+pub mod taskwarrior;
+mod timetracking;
+mod urgency;
 mod utils;
 
+pub use check::Problem;
 pub use config::{ReplicaConfig, ServerConfig};
+pub use errors::Error;
+pub use events::{ChangeSource, Event, Observer, SubscriptionId};
 pub use replica::Replica;
+pub use stats::{Burndown, BurndownPeriod, Period, Statistics, StatusCounts};
 pub use task::Priority;
 pub use task::Status;
-pub use task::{Task, TaskMut};
+pub use task::{Annotation, Interval, Task, TaskMut, TIMESTAMP_PROPERTIES};
+pub use timetracking::{TimeReport, TrackedInterval};
+pub use urgency::{Urgency, UrgencyCalculator, UrgencyCoefficients, UrgencyTerm};
 
 /// Re-exported type from the `uuid` crate, for ease of compatibility for consumers of this crate.
 pub use uuid::Uuid;
@@ -1,6 +1,9 @@
+use crate::check::{self, is_timestamp, Problem};
 use crate::config::ReplicaConfig;
 use crate::errors::Error;
+use crate::events::{Observer, SubscriptionId};
 use crate::server::Server;
+use crate::snapshot::Snapshot;
 use crate::task::{Status, Task};
 use crate::taskdb::TaskDB;
 use crate::taskstorage::{KVStorage, Operation, TaskMap, TaskStorage};
@@ -8,6 +11,7 @@ use chrono::Utc;
 use failure::Fallible;
 use log::trace;
 use std::collections::HashMap;
+use std::io::{Read, Write};
 use uuid::Uuid;
 
 /// A replica represents an instance of a user's task data, providing an easy interface
@@ -21,17 +25,20 @@ use uuid::Uuid;
 /// ## Working Set
 ///
 /// A replica maintains a "working set" of tasks that are of current concern to the user,
-/// specifically pending tasks.  These are indexed with small, easy-to-type integers.  Newly
+/// by default pending tasks.  These are indexed with small, easy-to-type integers.  Newly
 /// pending tasks are automatically added to the working set, and the working set is "renumbered"
-/// during the garbage-collection process.
+/// only during the garbage-collection process.  The tasks included in the working set can be
+/// customized with [`Replica::set_working_set_predicate`].
 pub struct Replica {
     taskdb: TaskDB,
+    working_set_predicate: Box<dyn Fn(&Task) -> bool>,
 }
 
 impl Replica {
     pub fn new(storage: Box<dyn TaskStorage>) -> Replica {
         Replica {
             taskdb: TaskDB::new(storage),
+            working_set_predicate: Box::new(|t| t.get_status() == Status::Pending),
         }
     }
 
@@ -73,6 +80,23 @@ impl Replica {
         self.taskdb.add_to_working_set(uuid)
     }
 
+    /// Determine whether the given task belongs in the working set.
+    pub(crate) fn in_working_set(&self, task: &Task) -> bool {
+        (self.working_set_predicate)(task)
+    }
+
+    /// Set the predicate determining which tasks belong in the working set.  By default, this
+    /// is all pending tasks, but it may, for example, also include recently completed tasks.
+    ///
+    /// Tasks are added to the working set when they are modified to match the predicate, but
+    /// are only removed from it when the working set is rebuilt.
+    pub fn set_working_set_predicate<F>(&mut self, predicate: F)
+    where
+        F: Fn(&Task) -> bool + 'static,
+    {
+        self.working_set_predicate = Box::new(predicate);
+    }
+
     /// Get all tasks represented as a map keyed by UUID
     pub fn all_tasks(&mut self) -> Fallible<HashMap<Uuid, Task>> {
         let mut res = HashMap::new();
@@ -82,6 +106,23 @@ impl Replica {
         Ok(res)
     }
 
+    /// Get all tasks with the given status, represented as a map keyed by UUID.  This uses the
+    /// storage's status index, so it is much faster than filtering `all_tasks` when only a few
+    /// tasks have the given status.  A task with no status is considered pending.
+    pub fn tasks_with_status(&mut self, status: Status) -> Fallible<HashMap<Uuid, Task>> {
+        let mut res = HashMap::new();
+        let mut statuses = vec![status.to_taskmap()];
+        if status == Status::Pending {
+            statuses.push("");
+        }
+        for status in statuses {
+            for (uuid, tm) in self.taskdb.tasks_with_status(status)?.drain(..) {
+                res.insert(uuid, Task::new(uuid, tm));
+            }
+        }
+        Ok(res)
+    }
+
     /// Get the UUIDs of all tasks
     pub fn all_task_uuids(&mut self) -> Fallible<Vec<Uuid>> {
         self.taskdb.all_task_uuids()
@@ -112,6 +153,28 @@ impl Replica {
             .map(move |tm| Task::new(*uuid, tm)))
     }
 
+    /// Get an existing task by a prefix of its UUID, such as the first 8 characters of
+    /// `Uuid::to_string`.  This returns None if no task matches, and
+    /// [`crate::Error::AmbiguousUuidPrefix`], listing the matching UUIDs, if more than one does.
+    pub fn get_task_by_uuid_prefix(&mut self, prefix: &str) -> Fallible<Option<Task>> {
+        let prefix = prefix.to_ascii_lowercase();
+        let mut matches: Vec<Uuid> = self
+            .taskdb
+            .all_task_uuids()?
+            .drain(..)
+            .filter(|u| u.to_string().starts_with(&prefix))
+            .collect();
+        match matches.len() {
+            0 => Ok(None),
+            1 => self.get_task(&matches[0]),
+            _ => {
+                matches.sort();
+                let candidates: Vec<String> = matches.iter().map(|u| u.to_string()).collect();
+                Err(Error::AmbiguousUuidPrefix(prefix, candidates.join(", ")).into())
+            }
+        }
+    }
+
     /// Get an existing task by its working set index
     pub fn get_working_set_task(&mut self, i: usize) -> Fallible<Option<Task>> {
         let working_set = self.taskdb.working_set()?;
@@ -128,15 +191,7 @@ impl Replica {
 
     /// Get the working set index for the given task uuid
     pub fn get_working_set_index(&mut self, uuid: &Uuid) -> Fallible<Option<usize>> {
-        let working_set = self.taskdb.working_set()?;
-        for (i, u) in working_set.iter().enumerate() {
-            if let Some(ref u) = u {
-                if u == uuid {
-                    return Ok(Some(i));
-                }
-            }
-        }
-        Ok(None)
+        self.taskdb.working_set_index(uuid)
     }
 
     /// Create a new task.  The task must not already exist.
@@ -145,11 +200,20 @@ impl Replica {
         self.taskdb.apply(Operation::Create { uuid })?;
         trace!("task {} created", uuid);
         let mut task = Task::new(uuid, TaskMap::new()).into_mut(self);
+        task.set_entry(Some(Utc::now()))?;
         task.set_description(description)?;
         task.set_status(status)?;
         Ok(task.into_immut())
     }
 
+    /// Create a new, empty task with the given UUID.  The task must not already exist.  This is
+    /// used when the UUID is determined elsewhere, such as when importing tasks.
+    pub(crate) fn create_task(&mut self, uuid: Uuid) -> Fallible<()> {
+        self.taskdb.apply(Operation::Create { uuid })?;
+        trace!("task {} created", uuid);
+        Ok(())
+    }
+
     /// Delete a task.  The task must exist.  Note that this is different from setting status to
     /// Deleted; this is the final purge of the task.  This is not a public method as deletion
     /// should only occur through expiration.
@@ -165,25 +229,119 @@ impl Replica {
         Ok(())
     }
 
-    /// Synchronize this replica against the given server.
+    /// Subscribe to changes to this replica's task data.  The observer is called with an
+    /// [`crate::Event`] for each change, after that change is committed, including changes
+    /// received from the server during [`Replica::sync`].
+    pub fn subscribe(&mut self, observer: Box<dyn Observer>) -> SubscriptionId {
+        self.taskdb.subscribe(observer)
+    }
+
+    /// Remove a subscription created with [`Replica::subscribe`], returning true if it existed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.taskdb.unsubscribe(id)
+    }
+
+    /// Synchronize this replica against the given server.  The working set is then updated to
+    /// include any tasks received from the server, without renumbering existing tasks.
     pub fn sync(&mut self, server: &mut Box<dyn Server>) -> Fallible<()> {
-        self.taskdb.sync(server)
+        self.taskdb.sync(server)?;
+        self.rebuild_working_set(false)
+    }
+
+    /// Prepare to synchronize this replica with a different server than the one it has synchronized
+    /// with before.  Its next sync sends all of its tasks to the server, merging them with any
+    /// tasks the server already has.
+    pub fn reset_sync(&mut self) -> Fallible<()> {
+        self.taskdb.reset_sync()
+    }
+
+    /// Write a snapshot of this replica's entire state -- tasks, working set, base version, and
+    /// operations not yet synchronized -- to the given writer.  The snapshot is a single,
+    /// self-describing file including a checksum, suitable for backups.
+    pub fn export_snapshot<W: Write>(&mut self, writer: W) -> Fallible<()> {
+        self.taskdb.snapshot()?.write(writer)
+    }
+
+    /// Restore a snapshot written by [`Replica::export_snapshot`].  The replica's storage must be
+    /// empty.  The snapshot's checksum is verified before anything is restored.
+    pub fn restore_snapshot<R: Read>(&mut self, reader: R) -> Fallible<()> {
+        let snapshot = Snapshot::read(reader)?;
+        self.taskdb.restore(snapshot)
     }
 
     /// Perform "garbage collection" on this replica.  In particular, this renumbers the working
-    /// set to contain only pending tasks.
+    /// set to contain only tasks matching the working-set predicate.  This is the only operation
+    /// which renumbers the working set.
     pub fn gc(&mut self) -> Fallible<()> {
-        let pending = String::from(Status::Pending.to_taskmap());
-        self.taskdb
-            .rebuild_working_set(|t| t.get("status") == Some(&pending))?;
-        Ok(())
+        self.rebuild_working_set(true)
+    }
+
+    /// Rebuild the working set so that it contains exactly the tasks matching the working-set
+    /// predicate.  If `renumber` is false, tasks remaining in the working set keep their
+    /// existing indexes, leaving gaps where tasks were removed.
+    pub fn rebuild_working_set(&mut self, renumber: bool) -> Fallible<()> {
+        let predicate = &self.working_set_predicate;
+        self.taskdb.rebuild_working_set(
+            |uuid, tm| predicate(&Task::new(*uuid, tm.clone())),
+            renumber,
+        )
+    }
+
+    /// Check this replica's data for inconsistencies, such as working-set entries referring to
+    /// missing tasks or malformed task properties.  This does not modify anything.
+    pub fn check(&mut self) -> Fallible<Vec<Problem>> {
+        check::check(&mut self.taskdb)
+    }
+
+    /// Check this replica's data and fix any problems found, returning the problems that were
+    /// fixed.  Fixes to tasks are made as ordinary updates, and so are synchronized like any
+    /// other change.
+    pub fn repair(&mut self) -> Fallible<Vec<Problem>> {
+        let problems = self.check()?;
+        for problem in problems.iter() {
+            match problem {
+                Problem::MissingWorkingSetTask { index, .. }
+                | Problem::DuplicateWorkingSetEntry { index, .. } => {
+                    self.taskdb.remove_working_set_item(*index)?;
+                }
+                Problem::DanglingOperations { uuid, .. } => {
+                    self.taskdb.remove_operations(uuid)?;
+                }
+                Problem::InvalidTimestamp { uuid, property, .. } => {
+                    self.update_task::<_, &str>(*uuid, property, None)?;
+                }
+                Problem::UnknownStatus { uuid, .. } => {
+                    self.update_task(*uuid, "status", Some(Status::Pending.to_taskmap()))?;
+                }
+                Problem::OrphanedStart { uuid, property } => {
+                    let valid = is_timestamp(&property["start.".len()..]);
+                    let end = match self.get_task(uuid)? {
+                        Some(task) if valid => task
+                            .get_timestamp("end")
+                            .or_else(|| task.get_modified())
+                            .unwrap_or_else(Utc::now),
+                        _ => {
+                            self.update_task::<_, &str>(*uuid, property, None)?;
+                            continue;
+                        }
+                    };
+                    self.update_task(*uuid, property, Some(end.timestamp().to_string()))?;
+                }
+            }
+        }
+        Ok(problems)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::events::{ChangeSource, Event};
     use crate::task::Status;
+    use crate::taskstorage::{taskmap_with, InMemoryStorage};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use tempdir::TempDir;
     use uuid::Uuid;
 
     #[test]
@@ -277,10 +435,194 @@ mod tests {
         assert_eq!(rep.get_working_set_index(t.get_uuid()).unwrap().unwrap(), 1);
     }
 
+    #[test]
+    fn tasks_with_status() {
+        let mut rep = Replica::new_inmemory();
+
+        let t1 = rep.new_task(Status::Pending, "pending".into()).unwrap();
+        let t2 = rep.new_task(Status::Completed, "completed".into()).unwrap();
+
+        let pending = rep.tasks_with_status(Status::Pending).unwrap();
+        assert_eq!(pending.keys().collect::<Vec<_>>(), vec![t1.get_uuid()]);
+        let completed = rep.tasks_with_status(Status::Completed).unwrap();
+        assert_eq!(completed.keys().collect::<Vec<_>>(), vec![t2.get_uuid()]);
+        assert!(rep.tasks_with_status(Status::Deleted).unwrap().is_empty());
+    }
+
+    #[test]
+    fn subscribe() {
+        let mut rep = Replica::new_inmemory();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let seen1 = seen.clone();
+        rep.subscribe(Box::new(move |e: &Event| {
+            seen1.borrow_mut().push(e.clone())
+        }));
+
+        let t = rep.new_task(Status::Pending, "a task".into()).unwrap();
+        let uuid = *t.get_uuid();
+        rep.gc().unwrap();
+        // the working set did not change
+        assert!(!seen.borrow().contains(&Event::WorkingSetRenumbered));
+        let t = rep
+            .new_task(Status::Pending, "another task".into())
+            .unwrap();
+        rep.update_task(uuid, "status", Some("C")).unwrap();
+        rep.gc().unwrap();
+        assert_eq!(rep.get_working_set_index(t.get_uuid()).unwrap(), Some(1));
+
+        let seen = seen.borrow();
+        assert_eq!(
+            seen[0],
+            Event::TaskCreated {
+                uuid,
+                source: ChangeSource::Local
+            }
+        );
+        assert!(seen.contains(&Event::TaskUpdated {
+            uuid,
+            property: "description".into(),
+            value: Some("a task".into()),
+            source: ChangeSource::Local
+        }));
+        assert_eq!(seen.last(), Some(&Event::WorkingSetRenumbered));
+    }
+
+    #[test]
+    fn export_restore_snapshot() {
+        let mut rep = Replica::new_inmemory();
+        let t = rep.new_task(Status::Pending, "a task".into()).unwrap();
+
+        let mut buf = vec![];
+        rep.export_snapshot(&mut buf).unwrap();
+
+        let mut rep2 = Replica::new_inmemory();
+        rep2.restore_snapshot(&buf[..]).unwrap();
+        assert_eq!(rep2.all_tasks().unwrap(), rep.all_tasks().unwrap());
+        assert_eq!(
+            rep2.get_working_set_index(t.get_uuid()).unwrap(),
+            rep.get_working_set_index(t.get_uuid()).unwrap()
+        );
+
+        // restoring again fails, as the replica is no longer empty
+        assert!(rep2.restore_snapshot(&buf[..]).is_err());
+    }
+
+    #[test]
+    fn working_set_predicate() {
+        let mut rep = Replica::new_inmemory();
+        rep.set_working_set_predicate(|t| t.get_status() != Status::Deleted);
+
+        let t1 = rep.new_task(Status::Pending, "one".into()).unwrap();
+        let t2 = rep.new_task(Status::Completed, "two".into()).unwrap();
+        let t3 = rep.new_task(Status::Pending, "three".into()).unwrap();
+        // completed tasks are added to the working set when the predicate includes them
+        assert_eq!(rep.get_working_set_index(t2.get_uuid()).unwrap(), Some(2));
+
+        let mut t1 = t1.into_mut(&mut rep);
+        t1.set_status(Status::Deleted).unwrap();
+        let t1 = t1.into_immut();
+
+        // rebuilding without renumbering leaves a gap
+        rep.rebuild_working_set(false).unwrap();
+        assert_eq!(rep.get_working_set_index(t1.get_uuid()).unwrap(), None);
+        assert_eq!(rep.get_working_set_index(t3.get_uuid()).unwrap(), Some(3));
+
+        // gc renumbers
+        rep.gc().unwrap();
+        assert_eq!(rep.get_working_set_index(t2.get_uuid()).unwrap(), Some(1));
+        assert_eq!(rep.get_working_set_index(t3.get_uuid()).unwrap(), Some(2));
+    }
+
     #[test]
     fn get_does_not_exist() {
         let mut rep = Replica::new_inmemory();
         let uuid = Uuid::new_v4();
         assert_eq!(rep.get_task(&uuid).unwrap(), None);
     }
+
+    /// Check and repair a replica with problems, in the given storage
+    fn check_and_repair_storage(mut storage: Box<dyn TaskStorage>) -> Fallible<()> {
+        let good = Uuid::new_v4();
+        let bad = Uuid::new_v4();
+        let missing = Uuid::new_v4();
+
+        {
+            let mut txn = storage.txn()?;
+            txn.set_task(good, taskmap_with(vec![("status".into(), "P".into())]))?;
+            txn.set_task(
+                bad,
+                taskmap_with(vec![
+                    ("status".into(), "C".into()),
+                    ("end".into(), "2000".into()),
+                    ("due".into(), "someday".into()),
+                    ("wait".into(), "99999999999999999".into()),
+                    ("start.1000".into(), "".into()),
+                ]),
+            )?;
+            txn.add_to_working_set(&good)?;
+            txn.add_to_working_set(&missing)?;
+            txn.add_to_working_set(&good)?;
+            txn.add_operation(Operation::Create { uuid: missing })?;
+            txn.commit()?;
+        }
+        let mut rep = Replica::new(storage);
+
+        let problems = rep.check()?;
+        assert_eq!(problems.len(), 6);
+        assert!(problems.contains(&Problem::MissingWorkingSetTask {
+            index: 2,
+            uuid: missing
+        }));
+        assert!(problems.contains(&Problem::DuplicateWorkingSetEntry {
+            index: 3,
+            uuid: good
+        }));
+        assert!(problems.contains(&Problem::DanglingOperations {
+            uuid: missing,
+            count: 1
+        }));
+
+        assert_eq!(rep.repair()?, problems);
+        assert_eq!(rep.check()?, vec![]);
+
+        let ws = rep.taskdb.working_set()?;
+        assert_eq!(ws, vec![None, Some(good), None, None]);
+        assert_eq!(rep.get_working_set_index(&good)?, Some(1));
+        let task = rep.get_task(&bad)?.unwrap();
+        assert_eq!(task.get_taskmap().get("due"), None);
+        assert_eq!(task.get_taskmap().get("wait"), None);
+        assert_eq!(task.get_taskmap().get("start.1000").unwrap(), "2000");
+        Ok(())
+    }
+
+    #[test]
+    fn check_and_repair() -> Fallible<()> {
+        check_and_repair_storage(Box::new(InMemoryStorage::new()))
+    }
+
+    #[test]
+    fn check_and_repair_kv() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        check_and_repair_storage(Box::new(KVStorage::new(tmp_dir.path())?))
+    }
+
+    #[test]
+    fn get_task_by_uuid_prefix() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        let uuid1 = Uuid::parse_str("abcdef01-0000-0000-0000-000000000000")?;
+        let uuid2 = Uuid::parse_str("abcdef02-0000-0000-0000-000000000000")?;
+        rep.create_task(uuid1)?;
+        rep.create_task(uuid2)?;
+
+        let task = rep.get_task_by_uuid_prefix("ABCDEF01")?.unwrap();
+        assert_eq!(task.get_uuid(), &uuid1);
+        assert!(rep.get_task_by_uuid_prefix("abcdef03")?.is_none());
+
+        let err = rep.get_task_by_uuid_prefix("abcdef0").err().unwrap();
+        assert_eq!(
+            err.downcast::<Error>()?,
+            Error::AmbiguousUuidPrefix("abcdef0".into(), format!("{}, {}", uuid1, uuid2))
+        );
+        Ok(())
+    }
 }
@@ -23,6 +23,29 @@ impl RemoteServer {
             agent: ureq::agent(),
         }
     }
+
+    /// Fetch the server's index page, which identifies the server and its version.  This is
+    /// useful to check that the server is reachable and is a TaskChampion sync server.
+    pub fn server_info(&self) -> Fallible<String> {
+        let url = format!("{}/", self.origin);
+        let resp = self
+            .agent
+            .get(&url)
+            .timeout_connect(10_000)
+            .timeout_read(60_000)
+            .call();
+        if !resp.ok() {
+            return Err(resp_to_error(resp));
+        }
+        let info = resp.into_string()?;
+        if !info.starts_with("TaskChampion sync server") {
+            return Err(format_err!(
+                "{} does not appear to be a TaskChampion sync server",
+                self.origin
+            ));
+        }
+        Ok(info)
+    }
 }
 
 /// Convert a ureq::Response to an Error
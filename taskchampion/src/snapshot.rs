@@ -0,0 +1,170 @@
+use crate::errors::Error;
+use crate::taskstorage::{Operation, TaskMap, VersionId};
+use failure::Fallible;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use uuid::Uuid;
+
+/// The value of the `format` field in the snapshot header
+const FORMAT: &str = "taskchampion-snapshot";
+
+/// The current version of the snapshot format
+const FORMAT_VERSION: u32 = 1;
+
+/// A snapshot file consists of a one-line JSON header, followed by the JSON-encoded snapshot
+/// data.  The header identifies the file and carries a checksum of the data.
+#[derive(Serialize, Deserialize, Debug)]
+struct Header {
+    format: String,
+    version: u32,
+    /// CRC-32 of the data following the header, in hex
+    crc32: String,
+    /// Length of the data following the header, in bytes
+    length: usize,
+}
+
+/// A Snapshot is a full copy of the data in a replica's storage, suitable for backups.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Snapshot {
+    pub(crate) tasks: BTreeMap<Uuid, TaskMap>,
+    pub(crate) working_set: Vec<Option<Uuid>>,
+    pub(crate) base_version: VersionId,
+    pub(crate) operations: Vec<Operation>,
+}
+
+impl Snapshot {
+    /// Write this snapshot, including its header.
+    pub(crate) fn write<W: Write>(&self, mut writer: W) -> Fallible<()> {
+        let data = serde_json::to_vec(self)?;
+        let header = Header {
+            format: FORMAT.into(),
+            version: FORMAT_VERSION,
+            crc32: format!("{:08x}", crc32(&data)),
+            length: data.len(),
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+        writer.write_all(&data)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a snapshot, verifying its header and checksum.
+    pub(crate) fn read<R: Read>(reader: R) -> Fallible<Snapshot> {
+        let mut reader = BufReader::new(reader);
+        let invalid = |msg: &str| Error::SnapshotError(msg.into());
+
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header: Header =
+            serde_json::from_str(&header).map_err(|_| invalid("not a snapshot file"))?;
+        if header.format != FORMAT {
+            return Err(invalid("not a snapshot file").into());
+        }
+        if header.version > FORMAT_VERSION {
+            return Err(invalid(&format!(
+                "snapshot format version {} is not supported",
+                header.version
+            ))
+            .into());
+        }
+
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        if data.len() != header.length {
+            return Err(invalid("snapshot is truncated").into());
+        }
+        if format!("{:08x}", crc32(&data)) != header.crc32 {
+            return Err(invalid("snapshot checksum does not match").into());
+        }
+
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+/// Calculate the CRC-32 (IEEE) of the given data
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::taskstorage::taskmap_with;
+
+    fn snapshot() -> Snapshot {
+        let uuid = Uuid::new_v4();
+        let mut tasks = BTreeMap::new();
+        tasks.insert(
+            uuid,
+            taskmap_with(vec![("description".into(), "a task".into())]),
+        );
+        Snapshot {
+            tasks,
+            working_set: vec![None, Some(uuid)],
+            base_version: Uuid::new_v4(),
+            operations: vec![Operation::Create { uuid }],
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn round_trip() -> Fallible<()> {
+        let snapshot = snapshot();
+        let mut buf = vec![];
+        snapshot.write(&mut buf)?;
+        assert!(buf.starts_with(b"{\"format\":\"taskchampion-snapshot\""));
+        assert_eq!(Snapshot::read(&buf[..])?, snapshot);
+        Ok(())
+    }
+
+    #[test]
+    fn corrupted() -> Fallible<()> {
+        let mut buf = vec![];
+        snapshot().write(&mut buf)?;
+        let i = buf.len() - 5;
+        buf[i] = b'X';
+        assert_eq!(
+            Snapshot::read(&buf[..]).err().unwrap().to_string(),
+            "Snapshot Error: snapshot checksum does not match"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn truncated() -> Fallible<()> {
+        let mut buf = vec![];
+        snapshot().write(&mut buf)?;
+        buf.truncate(buf.len() - 1);
+        assert_eq!(
+            Snapshot::read(&buf[..]).err().unwrap().to_string(),
+            "Snapshot Error: snapshot is truncated"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn not_a_snapshot() {
+        assert_eq!(
+            Snapshot::read(&b"[1, 2, 3]\n"[..])
+                .err()
+                .unwrap()
+                .to_string(),
+            "Snapshot Error: not a snapshot file"
+        );
+    }
+}
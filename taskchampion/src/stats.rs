@@ -0,0 +1,420 @@
+use crate::replica::Replica;
+use crate::task::{Status, Task};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use failure::Fallible;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// The number of tasks with each status
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct StatusCounts {
+    pub pending: usize,
+    pub completed: usize,
+    pub deleted: usize,
+}
+
+impl StatusCounts {
+    pub fn total(&self) -> usize {
+        self.pending + self.completed + self.deleted
+    }
+
+    fn add(&mut self, status: &Status) {
+        match status {
+            Status::Pending => self.pending += 1,
+            Status::Completed => self.completed += 1,
+            Status::Deleted => self.deleted += 1,
+        }
+    }
+}
+
+/// Statistics summarize a set of tasks: the number with each status, overall and in each project,
+/// the average time taken to complete a task, and the oldest pending task.
+#[derive(Debug)]
+pub struct Statistics {
+    counts: StatusCounts,
+    by_project: BTreeMap<Option<String>, StatusCounts>,
+    completion_times: Vec<Duration>,
+    oldest_pending: Option<(DateTime<Utc>, Uuid)>,
+}
+
+impl Statistics {
+    /// Calculate statistics for all tasks in the replica
+    pub fn new(replica: &mut Replica) -> Fallible<Self> {
+        Ok(Self::from_tasks(replica.all_tasks()?.values()))
+    }
+
+    /// Calculate statistics for the given tasks
+    pub fn from_tasks<'a, I: IntoIterator<Item = &'a Task>>(tasks: I) -> Self {
+        let mut stats = Statistics {
+            counts: StatusCounts::default(),
+            by_project: BTreeMap::new(),
+            completion_times: vec![],
+            oldest_pending: None,
+        };
+        for task in tasks {
+            let status = task.get_status();
+            stats.counts.add(&status);
+            stats
+                .by_project
+                .entry(task.get_project().map(|p| p.into()))
+                .or_default()
+                .add(&status);
+            match (status, task.get_entry()) {
+                (Status::Completed, Some(entry)) => {
+                    if let Some(end) = task.get_end() {
+                        stats.completion_times.push(end - entry);
+                    }
+                }
+                (Status::Pending, Some(entry)) => {
+                    let candidate = (entry, *task.get_uuid());
+                    stats.oldest_pending = Some(match stats.oldest_pending {
+                        Some(oldest) => oldest.min(candidate),
+                        None => candidate,
+                    });
+                }
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    /// The number of tasks with each status
+    pub fn counts(&self) -> StatusCounts {
+        self.counts
+    }
+
+    /// The number of tasks with each status in each project, with None for tasks without a
+    /// project
+    pub fn by_project(&self) -> &BTreeMap<Option<String>, StatusCounts> {
+        &self.by_project
+    }
+
+    /// The average time from entry to end of the completed tasks, if there are any
+    pub fn average_completion_time(&self) -> Option<Duration> {
+        if self.completion_times.is_empty() {
+            return None;
+        }
+        let total = self
+            .completion_times
+            .iter()
+            .fold(Duration::zero(), |t, d| t + *d);
+        Some(total / self.completion_times.len() as i32)
+    }
+
+    /// The uuid of the pending task with the earliest entry, and its entry time
+    pub fn oldest_pending(&self) -> Option<(Uuid, DateTime<Utc>)> {
+        self.oldest_pending.map(|(entry, uuid)| (uuid, entry))
+    }
+}
+
+/// The length of the periods in a [`Burndown`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Period {
+    Daily,
+    /// Weeks, beginning on Monday
+    Weekly,
+}
+
+/// The tasks added and closed during one period of a [`Burndown`], and the number remaining
+/// open at its end
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BurndownPeriod {
+    /// The first day of the period
+    pub start: NaiveDate,
+    pub added: usize,
+    pub completed: usize,
+    pub deleted: usize,
+    /// The number of tasks pending at the end of the period (or at the time the burndown is
+    /// made, for the current period)
+    pub remaining: usize,
+}
+
+/// A Burndown shows how the number of pending tasks changes over time, by day or by week.  It is
+/// calculated from the tasks' `entry` and `end` timestamps; a completed or deleted task without
+/// an `end` is taken to have closed when it was last modified, or if that is not known either,
+/// when it was entered.  Tasks without an `entry` are omitted.
+#[derive(Debug)]
+pub struct Burndown {
+    periods: Vec<BurndownPeriod>,
+}
+
+impl Burndown {
+    /// Calculate a burndown of the given tasks over the periods from the one containing `from` to
+    /// the one containing `to`, with days beginning at midnight in the given timezone
+    pub fn new<'a, I, Tz>(
+        tasks: I,
+        period: Period,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        tz: &Tz,
+    ) -> Self
+    where
+        I: IntoIterator<Item = &'a Task>,
+        Tz: TimeZone,
+    {
+        let period_start = |t: DateTime<Utc>| {
+            let day = t.with_timezone(tz).date().naive_local();
+            match period {
+                Period::Daily => day,
+                Period::Weekly => day - Duration::days(day.weekday().num_days_from_monday().into()),
+            }
+        };
+        let length = match period {
+            Period::Daily => Duration::days(1),
+            Period::Weekly => Duration::weeks(1),
+        };
+
+        let mut starts = vec![];
+        let mut start = period_start(from);
+        let last = period_start(to);
+        while start <= last {
+            starts.push(start);
+            start += length;
+        }
+
+        // the periods in which each task was entered and (with its status) closed
+        let tasks: Vec<_> = tasks
+            .into_iter()
+            .filter_map(|t| {
+                let entry = t.get_entry()?;
+                let end = match t.get_status() {
+                    Status::Pending => None,
+                    status => {
+                        let end = t.get_end().or_else(|| t.get_modified()).unwrap_or(entry);
+                        Some((period_start(end), status))
+                    }
+                };
+                Some((period_start(entry), end))
+            })
+            .collect();
+        let periods = starts
+            .into_iter()
+            .map(|start| {
+                let mut p = BurndownPeriod {
+                    start,
+                    added: 0,
+                    completed: 0,
+                    deleted: 0,
+                    remaining: 0,
+                };
+                for (entered, closed) in tasks.iter() {
+                    if *entered == start {
+                        p.added += 1;
+                    }
+                    match closed {
+                        Some((closed, Status::Completed)) if *closed == start => p.completed += 1,
+                        Some((closed, _)) if *closed == start => p.deleted += 1,
+                        _ => {}
+                    }
+                    if *entered <= start && !matches!(closed, Some((closed, _)) if *closed <= start)
+                    {
+                        p.remaining += 1;
+                    }
+                }
+                p
+            })
+            .collect();
+        Burndown { periods }
+    }
+
+    /// The periods, in chronological order
+    pub fn periods(&self) -> &[BurndownPeriod] {
+        &self.periods
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 3, day).and_hms(hour, 0, 0)
+    }
+
+    /// A task's status, entry, end, and project
+    type TestTask<'a> = (
+        Status,
+        DateTime<Utc>,
+        Option<DateTime<Utc>>,
+        Option<&'a str>,
+    );
+
+    /// Create a replica with the given tasks, returning their uuids
+    fn replica(tasks: &[TestTask]) -> Fallible<(Replica, Vec<Uuid>)> {
+        let mut rep = Replica::new_inmemory();
+        let mut uuids = vec![];
+        for (i, (status, entry, end, project)) in tasks.iter().enumerate() {
+            let task = rep.new_task(status.clone(), format!("task {}", i))?;
+            let uuid = *task.get_uuid();
+            rep.update_task(uuid, "entry", Some(entry.timestamp().to_string()))?;
+            let end = end.map(|e| e.timestamp().to_string());
+            rep.update_task(uuid, "end", end.clone())?;
+            if let Some(end) = end {
+                rep.update_task(uuid, "modified", Some(end))?;
+            }
+            rep.update_task(uuid, "project", *project)?;
+            uuids.push(uuid);
+        }
+        Ok((rep, uuids))
+    }
+
+    #[test]
+    fn statistics() -> Fallible<()> {
+        let (mut rep, uuids) = replica(&[
+            (Status::Pending, at(2, 0), None, Some("work")),
+            (Status::Pending, at(1, 0), None, None),
+            (Status::Completed, at(1, 0), Some(at(1, 6)), Some("work")),
+            (Status::Completed, at(1, 0), Some(at(2, 0)), None),
+            (Status::Deleted, at(1, 0), Some(at(3, 0)), Some("work")),
+        ])?;
+        let stats = Statistics::new(&mut rep)?;
+        assert_eq!(
+            stats.counts(),
+            StatusCounts {
+                pending: 2,
+                completed: 2,
+                deleted: 1
+            }
+        );
+        assert_eq!(stats.counts().total(), 5);
+        assert_eq!(
+            stats.by_project()[&Some("work".into())],
+            StatusCounts {
+                pending: 1,
+                completed: 1,
+                deleted: 1
+            }
+        );
+        assert_eq!(stats.by_project()[&None].total(), 2);
+        assert_eq!(stats.average_completion_time(), Some(Duration::hours(15)));
+        assert_eq!(stats.oldest_pending(), Some((uuids[1], at(1, 0))));
+        Ok(())
+    }
+
+    #[test]
+    fn statistics_empty() {
+        let stats = Statistics::from_tasks(vec![]);
+        assert_eq!(stats.counts().total(), 0);
+        assert_eq!(stats.average_completion_time(), None);
+        assert_eq!(stats.oldest_pending(), None);
+    }
+
+    #[test]
+    fn burndown_daily() -> Fallible<()> {
+        let (mut rep, _) = replica(&[
+            (Status::Pending, at(1, 10), None, None),
+            (Status::Pending, at(2, 10), None, None),
+            (Status::Completed, at(1, 10), Some(at(2, 12)), None),
+            (Status::Deleted, at(1, 10), Some(at(3, 12)), None),
+            (Status::Completed, at(2, 10), Some(at(2, 11)), None),
+        ])?;
+        let tasks = rep.all_tasks()?;
+        let burndown = Burndown::new(tasks.values(), Period::Daily, at(1, 12), at(3, 12), &Utc);
+        let day = |d| NaiveDate::from_ymd(2021, 3, d);
+        assert_eq!(
+            burndown.periods(),
+            &[
+                BurndownPeriod {
+                    start: day(1),
+                    added: 3,
+                    completed: 0,
+                    deleted: 0,
+                    remaining: 3,
+                },
+                BurndownPeriod {
+                    start: day(2),
+                    added: 2,
+                    completed: 2,
+                    deleted: 0,
+                    remaining: 3,
+                },
+                BurndownPeriod {
+                    start: day(3),
+                    added: 0,
+                    completed: 0,
+                    deleted: 1,
+                    remaining: 2,
+                },
+            ]
+        );
+
+        // in UTC+13, the tasks completed at 3/2 11:00 and 12:00 are completed on 3/3
+        let burndown = Burndown::new(
+            tasks.values(),
+            Period::Daily,
+            at(2, 12),
+            at(2, 12),
+            &FixedOffset::east(13 * 3600),
+        );
+        assert_eq!(burndown.periods().len(), 1);
+        assert_eq!(burndown.periods()[0].start, day(3));
+        assert_eq!(burndown.periods()[0].completed, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn burndown_without_end() -> Fallible<()> {
+        let (mut rep, uuids) = replica(&[
+            (Status::Completed, at(1, 10), None, None),
+            (Status::Deleted, at(2, 10), None, None),
+            (Status::Completed, at(1, 10), None, None),
+        ])?;
+        // the first task was modified on 3/2, and the others were never modified
+        rep.update_task(
+            uuids[0],
+            "modified",
+            Some(at(2, 12).timestamp().to_string()),
+        )?;
+        rep.update_task(uuids[1], "modified", None::<String>)?;
+        rep.update_task(uuids[2], "modified", None::<String>)?;
+        let tasks = rep.all_tasks()?;
+        let burndown = Burndown::new(tasks.values(), Period::Daily, at(1, 12), at(2, 12), &Utc);
+        let day = |d| NaiveDate::from_ymd(2021, 3, d);
+        assert_eq!(
+            burndown.periods(),
+            &[
+                BurndownPeriod {
+                    start: day(1),
+                    added: 2,
+                    completed: 1,
+                    deleted: 0,
+                    remaining: 1,
+                },
+                BurndownPeriod {
+                    start: day(2),
+                    added: 1,
+                    completed: 1,
+                    deleted: 1,
+                    remaining: 0,
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn burndown_weekly() -> Fallible<()> {
+        // 2021-03-01 is a Monday
+        let (mut rep, _) = replica(&[
+            (Status::Pending, at(1, 10), None, None),
+            (Status::Completed, at(2, 10), Some(at(9, 10)), None),
+            (Status::Pending, at(10, 10), None, None),
+        ])?;
+        let tasks = rep.all_tasks()?;
+        let burndown = Burndown::new(tasks.values(), Period::Weekly, at(3, 0), at(10, 0), &Utc);
+        let periods: Vec<_> = burndown
+            .periods()
+            .iter()
+            .map(|p| (p.start, p.added, p.completed, p.remaining))
+            .collect();
+        assert_eq!(
+            periods,
+            vec![
+                (NaiveDate::from_ymd(2021, 3, 1), 2, 0, 2),
+                (NaiveDate::from_ymd(2021, 3, 8), 1, 1, 2),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -1,14 +1,25 @@
 use crate::replica::Replica;
 use crate::taskstorage::TaskMap;
 use chrono::prelude::*;
-use failure::Fallible;
+use failure::{format_err, Fallible};
 use log::trace;
 use uuid::Uuid;
 
 pub type Timestamp = DateTime<Utc>;
 
+/// Properties whose values are timestamps
+pub const TIMESTAMP_PROPERTIES: &[&str] = &[
+    "entry",
+    "modified",
+    "due",
+    "wait",
+    "end",
+    "scheduled",
+    "until",
+];
+
 /// The priority of a task
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Priority {
     /// Low
     L,
@@ -18,7 +29,6 @@ pub enum Priority {
     H,
 }
 
-#[allow(dead_code)]
 impl Priority {
     /// Get a Priority from the 1-character value in a TaskMap,
     /// defaulting to M
@@ -32,7 +42,7 @@ impl Priority {
     }
 
     /// Get the 1-character value for this priority to use in the TaskMap.
-    pub(crate) fn to_taskmap(&self) -> &str {
+    pub(crate) fn to_taskmap(self) -> &'static str {
         match self {
             Priority::L => "L",
             Priority::M => "M",
@@ -42,7 +52,7 @@ impl Priority {
 }
 
 /// The status of a task.  The default status in "Pending".
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Status {
     Pending,
     Completed,
@@ -81,12 +91,21 @@ impl Status {
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// An annotation on a task: a note, identified by the time it was made
+#[derive(Debug, PartialEq, Clone)]
 pub struct Annotation {
     pub entry: Timestamp,
     pub description: String,
 }
 
+/// A period of time during which a task was active, from `start` until it was stopped.  The
+/// `end` of an interval that is still open is None.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Interval {
+    pub start: Timestamp,
+    pub end: Option<Timestamp>,
+}
+
 /// A task, as publicly exposed by this crate.
 ///
 /// Note that Task objects represent a snapshot of the task at a moment in time, and are not
@@ -159,12 +178,134 @@ impl Task {
         self.get_timestamp("modified")
     }
 
+    /// Get the time the task was created
+    pub fn get_entry(&self) -> Option<DateTime<Utc>> {
+        self.get_timestamp("entry")
+    }
+
+    /// Get the time the task was completed or deleted
+    pub fn get_end(&self) -> Option<DateTime<Utc>> {
+        self.get_timestamp("end")
+    }
+
+    pub fn get_project(&self) -> Option<&str> {
+        self.taskmap.get("project").map(|s| s.as_ref())
+    }
+
+    pub fn get_priority(&self) -> Option<Priority> {
+        self.taskmap
+            .get("priority")
+            .map(|s| Priority::from_taskmap(s))
+    }
+
+    pub fn get_due(&self) -> Option<DateTime<Utc>> {
+        self.get_timestamp("due")
+    }
+
+    pub fn get_wait(&self) -> Option<DateTime<Utc>> {
+        self.get_timestamp("wait")
+    }
+
+    /// Determine whether this task is waiting -- that is, that its wait time is in the future.
+    pub fn is_waiting(&self) -> bool {
+        match self.get_wait() {
+            Some(wait) => wait > Utc::now(),
+            None => false,
+        }
+    }
+
+    /// Get the task's tags, in sorted order
+    pub fn get_tags(&self) -> Vec<&str> {
+        let mut tags: Vec<&str> = self
+            .taskmap
+            .keys()
+            .filter_map(|k| k.strip_prefix("tag."))
+            .collect();
+        tags.sort_unstable();
+        tags
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.taskmap.contains_key(&format!("tag.{}", tag))
+    }
+
+    /// Get the UUIDs of the tasks this task depends on, in sorted order
+    pub fn get_dependencies(&self) -> Vec<Uuid> {
+        let mut deps: Vec<Uuid> = self
+            .taskmap
+            .keys()
+            .filter_map(|k| k.strip_prefix("dep."))
+            .filter_map(|u| Uuid::parse_str(u).ok())
+            .collect();
+        deps.sort_unstable();
+        deps
+    }
+
+    /// Get the intervals during which this task was active, from its `start.<timestamp>` keys, in
+    /// order by start time
+    pub fn get_intervals(&self) -> Vec<Interval> {
+        let mut intervals: Vec<Interval> = self
+            .taskmap
+            .iter()
+            .filter_map(|(k, v)| {
+                let start = Utc
+                    .timestamp_opt(k.strip_prefix("start.")?.parse().ok()?, 0)
+                    .single()?;
+                let end = if v.is_empty() {
+                    None
+                } else {
+                    Some(Utc.timestamp_opt(v.parse().ok()?, 0).single()?)
+                };
+                Some(Interval { start, end })
+            })
+            .collect();
+        intervals.sort_by_key(|i| i.start);
+        intervals
+    }
+
+    /// Get the task's annotations, from its `annotation.<timestamp>` keys, in order by entry time
+    pub fn get_annotations(&self) -> Vec<Annotation> {
+        let mut annotations: Vec<Annotation> = self
+            .taskmap
+            .iter()
+            .filter_map(|(k, v)| {
+                let entry = k.strip_prefix("annotation.")?.parse().ok()?;
+                Some(Annotation {
+                    entry: Utc.timestamp_opt(entry, 0).single()?,
+                    description: v.clone(),
+                })
+            })
+            .collect();
+        annotations.sort_by_key(|a| a.entry);
+        annotations
+    }
+
+    /// Get the task's user-defined attributes (UDAs), from its `uda.<name>` keys, as (name, value)
+    /// pairs in order by name
+    pub fn get_udas(&self) -> Vec<(&str, &str)> {
+        let mut udas: Vec<(&str, &str)> = self
+            .taskmap
+            .iter()
+            .filter_map(|(k, v)| Some((k.strip_prefix("uda.")?, v.as_ref())))
+            .collect();
+        udas.sort_unstable();
+        udas
+    }
+
+    /// Get the number of annotations on this task
+    pub fn annotation_count(&self) -> usize {
+        self.taskmap
+            .keys()
+            .filter(|k| k.starts_with("annotation."))
+            .count()
+    }
+
     // -- utility functions
 
     pub fn get_timestamp(&self, property: &str) -> Option<DateTime<Utc>> {
         if let Some(ts) = self.taskmap.get(property) {
             if let Ok(ts) = ts.parse() {
-                return Some(Utc.timestamp(ts, 0));
+                return Utc.timestamp_opt(ts, 0).single();
             }
             // if the value does not parse as an integer, default to None
         }
@@ -180,13 +321,27 @@ impl<'r> TaskMut<'r> {
     }
 
     /// Set the task's status.  This also adds the task to the working set if the
-    /// new status puts it in that set.
+    /// new status puts it in that set, and sets the task's end time if the new status is
+    /// Completed or Deleted.
     pub fn set_status(&mut self, status: Status) -> Fallible<()> {
-        if status == Status::Pending {
+        match status {
+            Status::Pending => {
+                if self.taskmap.contains_key("end") {
+                    self.set_timestamp("end", None)?;
+                }
+            }
+            Status::Completed | Status::Deleted => {
+                if self.get_status() != status {
+                    self.set_timestamp("end", Some(Utc::now()))?;
+                }
+            }
+        }
+        self.set_string("status", Some(String::from(status.to_taskmap())))?;
+        if self.replica.in_working_set(&self.task) {
             let uuid = self.uuid;
             self.replica.add_to_working_set(&uuid)?;
         }
-        self.set_string("status", Some(String::from(status.to_taskmap())))
+        Ok(())
     }
 
     pub fn set_description(&mut self, description: String) -> Fallible<()> {
@@ -197,6 +352,69 @@ impl<'r> TaskMut<'r> {
         self.set_timestamp("modified", Some(modified))
     }
 
+    pub fn set_entry(&mut self, entry: Option<DateTime<Utc>>) -> Fallible<()> {
+        self.set_timestamp("entry", entry)
+    }
+
+    pub fn set_project(&mut self, project: Option<String>) -> Fallible<()> {
+        self.set_string("project", project)
+    }
+
+    pub fn set_priority(&mut self, priority: Option<Priority>) -> Fallible<()> {
+        self.set_string("priority", priority.map(|p| p.to_taskmap().to_string()))
+    }
+
+    pub fn set_due(&mut self, due: Option<DateTime<Utc>>) -> Fallible<()> {
+        self.set_timestamp("due", due)
+    }
+
+    pub fn set_wait(&mut self, wait: Option<DateTime<Utc>>) -> Fallible<()> {
+        self.set_timestamp("wait", wait)
+    }
+
+    /// Add a tag to the task.  Tags must be non-empty and cannot contain whitespace.
+    pub fn add_tag(&mut self, tag: &str) -> Fallible<()> {
+        if tag.is_empty() || tag.contains(char::is_whitespace) {
+            return Err(format_err!("Invalid tag {:?}", tag));
+        }
+        if self.has_tag(tag) {
+            return Ok(());
+        }
+        self.set_string(&format!("tag.{}", tag), Some(String::new()))
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) -> Fallible<()> {
+        if !self.has_tag(tag) {
+            return Ok(());
+        }
+        self.set_string(&format!("tag.{}", tag), None)
+    }
+
+    /// Add an annotation to the task.  Annotations are identified by their entry time, so this
+    /// replaces any existing annotation with the same entry time.
+    pub fn add_annotation(&mut self, annotation: Annotation) -> Fallible<()> {
+        let k = format!("annotation.{}", annotation.entry.timestamp());
+        self.set_string(&k, Some(annotation.description))
+    }
+
+    /// Remove the annotation with the given entry time, if there is one
+    pub fn remove_annotation(&mut self, entry: Timestamp) -> Fallible<()> {
+        let k = format!("annotation.{}", entry.timestamp());
+        if !self.taskmap.contains_key(&k) {
+            return Ok(());
+        }
+        self.set_string(&k, None)
+    }
+
+    /// Set or (with None) remove a user-defined attribute.  UDA names must be non-empty and cannot
+    /// contain whitespace.
+    pub fn set_uda(&mut self, name: &str, value: Option<String>) -> Fallible<()> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format_err!("Invalid UDA name {:?}", name));
+        }
+        self.set_string(&format!("uda.{}", name), value)
+    }
+
     /// Start the task by creating "start.<timestamp": "", if the task is not already
     /// active.
     pub fn start(&mut self) -> Fallible<()> {
@@ -218,12 +436,23 @@ impl<'r> TaskMut<'r> {
             .collect::<Vec<_>>();
         let now = Utc::now();
         for key in keys {
-            println!("{}", key);
             self.set_timestamp(&key, Some(now))?;
         }
         Ok(())
     }
 
+    /// Set or (with None) remove a property directly, for changes made in another format.  Setting
+    /// the status adds the task to the working set if that puts it in the set, as `set_status`
+    /// does.
+    pub(crate) fn set_property(&mut self, property: &str, value: Option<String>) -> Fallible<()> {
+        self.set_string(property, value)?;
+        if property == "status" && self.replica.in_working_set(&self.task) {
+            let uuid = self.uuid;
+            self.replica.add_to_working_set(&uuid)?;
+        }
+        Ok(())
+    }
+
     // -- utility functions
 
     fn lastmod(&mut self) -> Fallible<()> {
@@ -289,6 +518,7 @@ impl<'r> std::ops::Deref for TaskMut<'r> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use chrono::Duration;
 
     fn with_mut_task<F: FnOnce(TaskMut)>(f: F) {
         let mut replica = Replica::new_inmemory();
@@ -297,6 +527,109 @@ mod test {
         f(task)
     }
 
+    #[test]
+    fn test_setters() {
+        with_mut_task(|mut task| {
+            let due = Utc.timestamp(1_600_000_000, 0);
+            task.set_project(Some("home".into())).unwrap();
+            task.set_priority(Some(Priority::H)).unwrap();
+            task.set_due(Some(due)).unwrap();
+            task.set_wait(Some(due)).unwrap();
+            task.add_tag("next").unwrap();
+            task.add_tag("car").unwrap();
+            task.reload().unwrap();
+            assert_eq!(task.get_project(), Some("home"));
+            assert_eq!(task.get_priority(), Some(Priority::H));
+            assert_eq!(task.get_due(), Some(due));
+            assert_eq!(task.get_wait(), Some(due));
+            assert_eq!(task.get_tags(), vec!["car", "next"]);
+            assert!(task.has_tag("car"));
+
+            task.set_project(None).unwrap();
+            task.set_priority(None).unwrap();
+            task.set_due(None).unwrap();
+            task.remove_tag("car").unwrap();
+            task.remove_tag("nosuch").unwrap();
+            task.reload().unwrap();
+            assert_eq!(task.get_project(), None);
+            assert_eq!(task.get_priority(), None);
+            assert_eq!(task.get_due(), None);
+            assert_eq!(task.get_tags(), vec!["next"]);
+        });
+    }
+
+    #[test]
+    fn test_annotations() {
+        with_mut_task(|mut task| {
+            let first = Annotation {
+                entry: Utc.timestamp(1_600_000_000, 0),
+                description: "first".into(),
+            };
+            let second = Annotation {
+                entry: Utc.timestamp(1_600_000_100, 0),
+                description: "second".into(),
+            };
+            task.add_annotation(second.clone()).unwrap();
+            task.add_annotation(first.clone()).unwrap();
+            task.reload().unwrap();
+            assert_eq!(task.get_annotations(), vec![first.clone(), second.clone()]);
+
+            task.remove_annotation(second.entry).unwrap();
+            task.remove_annotation(Utc.timestamp(1, 0)).unwrap();
+            task.reload().unwrap();
+            assert_eq!(task.get_annotations(), vec![first]);
+        });
+    }
+
+    #[test]
+    fn test_udas() {
+        with_mut_task(|mut task| {
+            task.set_uda("size", Some("L".into())).unwrap();
+            task.set_uda("estimate", Some("2h".into())).unwrap();
+            task.reload().unwrap();
+            assert_eq!(task.get_udas(), vec![("estimate", "2h"), ("size", "L")]);
+
+            task.set_uda("size", None).unwrap();
+            assert!(task.set_uda("has space", Some("x".into())).is_err());
+            task.reload().unwrap();
+            assert_eq!(task.get_udas(), vec![("estimate", "2h")]);
+        });
+    }
+
+    #[test]
+    fn test_set_status_end() {
+        with_mut_task(|mut task| {
+            assert_eq!(task.get_end(), None);
+            task.set_status(Status::Completed).unwrap();
+            let end = task.get_end();
+            assert!(end.is_some());
+            task.set_status(Status::Completed).unwrap();
+            assert_eq!(task.get_end(), end);
+            task.set_status(Status::Pending).unwrap();
+            task.reload().unwrap();
+            assert_eq!(task.get_end(), None);
+        });
+    }
+
+    #[test]
+    fn test_is_waiting() {
+        with_mut_task(|mut task| {
+            assert!(!task.is_waiting());
+            task.set_wait(Some(Utc::now() + Duration::days(1))).unwrap();
+            assert!(task.is_waiting());
+            task.set_wait(Some(Utc::now() - Duration::days(1))).unwrap();
+            assert!(!task.is_waiting());
+        });
+    }
+
+    #[test]
+    fn test_add_tag_invalid() {
+        with_mut_task(|mut task| {
+            assert!(task.add_tag("").is_err());
+            assert!(task.add_tag("two words").is_err());
+        });
+    }
+
     #[test]
     fn test_is_active_never_started() {
         let task = Task::new(Uuid::new_v4(), TaskMap::new());
@@ -416,6 +749,54 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_get_intervals() {
+        let task = Task::new(
+            Uuid::new_v4(),
+            vec![
+                (String::from("start.5678"), String::from("")),
+                (String::from("start.1234"), String::from("2345")),
+                (String::from("start.bogus"), String::from("")),
+                // out of the range of dates that can be represented
+                (String::from("start.99999999999999999"), String::from("")),
+                (String::from("start.1"), String::from("99999999999999999")),
+            ]
+            .drain(..)
+            .collect(),
+        );
+        assert_eq!(
+            task.get_intervals(),
+            vec![
+                Interval {
+                    start: Utc.timestamp(1234, 0),
+                    end: Some(Utc.timestamp(2345, 0)),
+                },
+                Interval {
+                    start: Utc.timestamp(5678, 0),
+                    end: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_timestamps() {
+        let task = Task::new(
+            Uuid::new_v4(),
+            vec![
+                (String::from("due"), String::from("99999999999999999")),
+                (
+                    String::from("annotation.99999999999999999"),
+                    String::from("far future"),
+                ),
+            ]
+            .drain(..)
+            .collect(),
+        );
+        assert_eq!(task.get_due(), None);
+        assert_eq!(task.get_annotations(), vec![]);
+    }
+
     #[test]
     fn test_priority() {
         assert_eq!(Priority::L.to_taskmap(), "L");
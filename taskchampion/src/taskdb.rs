@@ -1,6 +1,9 @@
 use crate::errors::Error;
+use crate::events::{ChangeSource, Event, Observer, Observers, SubscriptionId};
 use crate::server::{AddVersionResult, GetVersionResult, Server};
-use crate::taskstorage::{Operation, TaskMap, TaskStorage, TaskStorageTxn};
+use crate::snapshot::Snapshot;
+use crate::taskstorage::{Operation, TaskMap, TaskStorage, TaskStorageTxn, DEFAULT_BASE_VERSION};
+use chrono::Utc;
 use failure::{format_err, Fallible};
 use log::{info, trace, warn};
 use serde::{Deserialize, Serialize};
@@ -10,6 +13,7 @@ use uuid::Uuid;
 
 pub struct TaskDB {
     storage: Box<dyn TaskStorage>,
+    observers: Observers,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -20,7 +24,10 @@ struct Version {
 impl TaskDB {
     /// Create a new TaskDB with the given backend storage
     pub fn new(storage: Box<dyn TaskStorage>) -> TaskDB {
-        TaskDB { storage }
+        TaskDB {
+            storage,
+            observers: Observers::default(),
+        }
     }
 
     #[cfg(test)]
@@ -37,11 +44,24 @@ impl TaskDB {
         if let err @ Err(_) = TaskDB::apply_op(txn.as_mut(), &op) {
             return err;
         }
+        let event = Event::from_operation(&op, ChangeSource::Local);
         txn.add_operation(op)?;
         txn.commit()?;
+        self.observers.notify(&event);
         Ok(())
     }
 
+    /// Subscribe an observer to all subsequent changes to this TaskDB, both local and from
+    /// synchronization.
+    pub fn subscribe(&mut self, observer: Box<dyn Observer>) -> SubscriptionId {
+        self.observers.subscribe(observer)
+    }
+
+    /// Remove a subscription, returning true if it existed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.observers.unsubscribe(id)
+    }
+
     fn apply_op(txn: &mut dyn TaskStorageTxn, op: &Operation) -> Fallible<()> {
         match op {
             Operation::Create { uuid } => {
@@ -101,15 +121,37 @@ impl TaskDB {
         txn.get_task(uuid)
     }
 
-    /// Rebuild the working set using a function to identify tasks that should be in the set.  This
-    /// renumbers the existing working-set tasks to eliminate gaps, and also adds any tasks that
-    /// are not already in the working set but should be.  The rebuild occurs in a single
-    /// trasnsaction against the storage backend.
-    pub fn rebuild_working_set<F>(&mut self, in_working_set: F) -> Fallible<()>
+    /// Get all tasks with the given value of the `status` property (an empty string matches
+    /// tasks with no status).  This uses the storage's status index.
+    pub fn tasks_with_status(&mut self, status: &str) -> Fallible<Vec<(Uuid, TaskMap)>> {
+        let mut txn = self.storage.txn()?;
+        let mut res = vec![];
+        for uuid in txn.task_uuids_with_status(status)? {
+            if let Some(task) = txn.get_task(&uuid)? {
+                res.push((uuid, task));
+            }
+        }
+        Ok(res)
+    }
+
+    /// Get the working-set index of the given task, if it is in the working set.
+    pub fn working_set_index(&mut self, uuid: &Uuid) -> Fallible<Option<usize>> {
+        let mut txn = self.storage.txn()?;
+        txn.get_working_set_index(uuid)
+    }
+
+    /// Rebuild the working set using a function to identify tasks that should be in the set.
+    /// Tasks that are no longer in the set are removed, and any tasks that are not already in the
+    /// working set but should be are added at the end.  If `renumber` is true, the remaining
+    /// tasks are also renumbered to eliminate gaps; otherwise, the indexes of the remaining tasks
+    /// do not change.  The rebuild occurs in a single trasnsaction against the storage backend.
+    /// Observers are notified only if the working set changed.
+    pub fn rebuild_working_set<F>(&mut self, in_working_set: F, renumber: bool) -> Fallible<()>
     where
-        F: Fn(&TaskMap) -> bool,
+        F: Fn(&Uuid, &TaskMap) -> bool,
     {
         let mut txn = self.storage.txn()?;
+        let old_ws = txn.get_working_set()?;
 
         let mut new_ws = vec![];
         let mut seen = HashSet::new();
@@ -117,33 +159,58 @@ impl TaskDB {
         // The goal here is for existing working-set items to be "compressed' down to index 1, so
         // we begin by scanning the current working set and inserting any tasks that should still
         // be in the set into new_ws, implicitly dropping any tasks that are no longer in the
-        // working set.
+        // working set.  When not renumbering, dropped tasks leave a gap instead.
         for elt in txn.get_working_set()? {
             if let Some(uuid) = elt {
                 if let Some(task) = txn.get_task(&uuid)? {
-                    if in_working_set(&task) {
-                        new_ws.push(uuid);
+                    if in_working_set(&uuid, &task) {
+                        new_ws.push(Some(uuid));
                         seen.insert(uuid);
+                        continue;
                     }
                 }
             }
+            if !renumber {
+                new_ws.push(None);
+            }
         }
 
         // Now go hunting for tasks that should be in this list but are not, adding them at the
         // end of the list.
         for (uuid, task) in txn.all_tasks()? {
-            if !seen.contains(&uuid) && in_working_set(&task) {
-                new_ws.push(uuid);
+            if !seen.contains(&uuid) && in_working_set(&uuid, &task) {
+                new_ws.push(Some(uuid));
             }
         }
 
         // clear and re-write the entire working set, in order
         txn.clear_working_set()?;
-        for uuid in new_ws.drain(0..new_ws.len()) {
-            txn.add_to_working_set(&uuid)?;
+        if renumber {
+            for uuid in new_ws.drain(..).flatten() {
+                txn.add_to_working_set(&uuid)?;
+            }
+        } else {
+            // the first element of the existing working set is always None, so skip it
+            for (i, uuid) in new_ws.drain(..).enumerate().skip(1) {
+                if let Some(uuid) = uuid {
+                    txn.set_working_set_item(i, Some(uuid))?;
+                }
+            }
         }
 
+        // gaps at the end of the working set do not affect any index
+        let trim = |mut ws: Vec<Option<Uuid>>| {
+            while ws.last() == Some(&None) {
+                ws.pop();
+            }
+            ws
+        };
+        let changed = trim(txn.get_working_set()?) != trim(old_ws);
+
         txn.commit()?;
+        if changed {
+            self.observers.notify(&Event::WorkingSetRenumbered);
+        }
         Ok(())
     }
 
@@ -151,12 +218,8 @@ impl TaskDB {
     /// set, its index is returned.  This does *not* renumber any existing tasks.
     pub fn add_to_working_set(&mut self, uuid: &Uuid) -> Fallible<usize> {
         let mut txn = self.storage.txn()?;
-        // search for an existing entry for this task..
-        for (i, elt) in txn.get_working_set()?.iter().enumerate() {
-            if *elt == Some(*uuid) {
-                // (note that this drops the transaction with no changes made)
-                return Ok(i);
-            }
+        if let Some(i) = txn.get_working_set_index(uuid)? {
+            return Ok(i);
         }
         // and if not found, add one
         let i = txn.add_to_working_set(uuid)?;
@@ -164,9 +227,98 @@ impl TaskDB {
         Ok(i)
     }
 
+    /// Get the operations that have not yet been synchronized to the server.
+    pub(crate) fn local_operations(&mut self) -> Fallible<Vec<Operation>> {
+        let mut txn = self.storage.txn()?;
+        txn.operations()
+    }
+
+    /// Remove the entry at the given index of the working set, leaving a gap.  This is used to
+    /// repair inconsistencies; the working set is otherwise only changed by rebuilding it.
+    pub(crate) fn remove_working_set_item(&mut self, index: usize) -> Fallible<()> {
+        let mut txn = self.storage.txn()?;
+        txn.set_working_set_item(index, None)?;
+        txn.commit()
+    }
+
+    /// Drop all unsynchronized operations for the given task.  This is used to repair
+    /// inconsistencies, and will lose data if the task exists.
+    pub(crate) fn remove_operations(&mut self, uuid: &Uuid) -> Fallible<()> {
+        let mut txn = self.storage.txn()?;
+        let operations = txn
+            .operations()?
+            .drain(..)
+            .filter(|op| op.uuid() != uuid)
+            .collect();
+        txn.set_operations(operations)?;
+        txn.commit()
+    }
+
+    /// Take a snapshot of the entire contents of the storage, in a single transaction.
+    pub(crate) fn snapshot(&mut self) -> Fallible<Snapshot> {
+        let mut txn = self.storage.txn()?;
+        Ok(Snapshot {
+            tasks: txn.all_tasks()?.drain(..).collect(),
+            working_set: txn.get_working_set()?,
+            base_version: txn.base_version()?,
+            operations: txn.operations()?,
+        })
+    }
+
+    /// Restore a snapshot into this TaskDB, which must be empty.
+    pub(crate) fn restore(&mut self, snapshot: Snapshot) -> Fallible<()> {
+        let mut txn = self.storage.txn()?;
+        if !txn.all_task_uuids()?.is_empty()
+            || !txn.operations()?.is_empty()
+            || txn.base_version()? != DEFAULT_BASE_VERSION
+            || txn.get_working_set()?.iter().any(|u| u.is_some())
+        {
+            return Err(
+                Error::DBError("Cannot restore into a non-empty task database".into()).into(),
+            );
+        }
+
+        for (uuid, task) in snapshot.tasks {
+            txn.set_task(uuid, task)?;
+        }
+        for (i, uuid) in snapshot.working_set.iter().enumerate().skip(1) {
+            txn.set_working_set_item(i, *uuid)?;
+        }
+        txn.set_base_version(snapshot.base_version)?;
+        txn.set_operations(snapshot.operations)?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Forget the server this TaskDB has synchronized with, replacing any unsynchronized
+    /// operations with operations that create every task as it is now.  The next sync then sends
+    /// all tasks to the server, whichever server that is.
+    pub(crate) fn reset_sync(&mut self) -> Fallible<()> {
+        let mut txn = self.storage.txn()?;
+        let now = Utc::now();
+        let mut operations = vec![];
+        for (uuid, task) in txn.all_tasks()? {
+            operations.push(Operation::Create { uuid });
+            for (property, value) in task {
+                operations.push(Operation::Update {
+                    uuid,
+                    property,
+                    value: Some(value),
+                    timestamp: now,
+                });
+            }
+        }
+        txn.set_base_version(DEFAULT_BASE_VERSION)?;
+        txn.set_operations(operations)?;
+        txn.commit()?;
+        Ok(())
+    }
+
     /// Sync to the given server, pulling remote changes and pushing local changes.
     pub fn sync(&mut self, server: &mut Box<dyn Server>) -> Fallible<()> {
         let mut txn = self.storage.txn()?;
+        // events for server operations, delivered once the transaction is committed
+        let mut events = vec![];
 
         // retry synchronizing until the server accepts our version (this allows for races between
         // replicas trying to sync to the same server).  If the server insists on the same base
@@ -190,7 +342,7 @@ impl TaskDB {
 
                     // apply this verison and update base_version in storage
                     info!("applying version {:?} from server", version_id);
-                    TaskDB::apply_version(txn.as_mut(), version)?;
+                    TaskDB::apply_version(txn.as_mut(), version, &mut events)?;
                     txn.set_base_version(version_id)?;
                     base_version_id = version_id;
                 } else {
@@ -238,10 +390,19 @@ impl TaskDB {
         }
 
         txn.commit()?;
+        for event in events.iter() {
+            self.observers.notify(event);
+        }
         Ok(())
     }
 
-    fn apply_version(txn: &mut dyn TaskStorageTxn, mut version: Version) -> Fallible<()> {
+    /// Apply the given version from the server, appending an event for each server operation
+    /// that is applied locally.
+    fn apply_version(
+        txn: &mut dyn TaskStorageTxn,
+        mut version: Version,
+        events: &mut Vec<Event>,
+    ) -> Fallible<()> {
         // The situation here is that the server has already applied all server operations, and we
         // have already applied all local operations, so states have diverged by several
         // operations.  We need to figure out what operations to apply locally and on the server in
@@ -292,8 +453,9 @@ impl TaskDB {
                 }
             }
             if let Some(o) = svr_op {
-                if let Err(e) = TaskDB::apply_op(txn, &o) {
-                    warn!("Invalid operation when syncing: {} (ignored)", e);
+                match TaskDB::apply_op(txn, &o) {
+                    Ok(()) => events.push(Event::from_operation(&o, ChangeSource::Sync)),
+                    Err(e) => warn!("Invalid operation when syncing: {} (ignored)", e),
                 }
             }
             local_operations = new_local_ops;
@@ -341,7 +503,9 @@ mod tests {
     use crate::taskstorage::InMemoryStorage;
     use chrono::Utc;
     use proptest::prelude::*;
+    use std::cell::RefCell;
     use std::collections::HashMap;
+    use std::rc::Rc;
     use uuid::Uuid;
 
     #[test]
@@ -527,13 +691,16 @@ mod tests {
             ]
         );
 
-        db.rebuild_working_set(|t| {
-            if let Some(status) = t.get("status") {
-                status == "pending"
-            } else {
-                false
-            }
-        })?;
+        db.rebuild_working_set(
+            |_, t| {
+                if let Some(status) = t.get("status") {
+                    status == "pending"
+                } else {
+                    false
+                }
+            },
+            true,
+        )?;
 
         // uuids[1] and uuids[4] are already in the working set, so are compressed
         // to the top, and then uuids[0] is added.
@@ -550,6 +717,152 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn rebuild_working_set_no_renumber() -> Fallible<()> {
+        let mut db = TaskDB::new_inmemory();
+        let uuids = vec![
+            Uuid::new_v4(), // 0: in working set, not already in working set
+            Uuid::new_v4(), // 1: in working set, already in working set
+            Uuid::new_v4(), // 2: not in working set, already in working set
+            Uuid::new_v4(), // 3: in working set, already in working set
+        ];
+        for uuid in &uuids {
+            db.apply(Operation::Create { uuid: *uuid })?;
+        }
+        for i in &[1usize, 2, 3] {
+            db.add_to_working_set(&uuids[*i])?;
+        }
+
+        db.rebuild_working_set(|u, _| *u != uuids[2], false)?;
+
+        // uuids[2] leaves a gap, and uuids[0] is added at the end
+        assert_eq!(
+            db.working_set()?,
+            vec![None, Some(uuids[1]), None, Some(uuids[3]), Some(uuids[0])]
+        );
+        assert_eq!(db.working_set_index(&uuids[0])?, Some(4));
+        assert_eq!(db.working_set_index(&uuids[2])?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn working_set_index() -> Fallible<()> {
+        let mut db = TaskDB::new_inmemory();
+        let uuid1 = Uuid::new_v4();
+        let uuid2 = Uuid::new_v4();
+
+        assert_eq!(db.working_set_index(&uuid1)?, None);
+        assert_eq!(db.add_to_working_set(&uuid1)?, 1);
+        assert_eq!(db.add_to_working_set(&uuid2)?, 2);
+        // adding again returns the existing index
+        assert_eq!(db.add_to_working_set(&uuid1)?, 1);
+        assert_eq!(db.working_set_index(&uuid1)?, Some(1));
+        assert_eq!(db.working_set_index(&uuid2)?, Some(2));
+        assert_eq!(db.working_set()?, vec![None, Some(uuid1), Some(uuid2)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_notifies_observers() {
+        let mut db = TaskDB::new_inmemory();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let seen1 = seen.clone();
+        db.subscribe(Box::new(move |e: &Event| {
+            seen1.borrow_mut().push(e.clone())
+        }));
+
+        let uuid = Uuid::new_v4();
+        db.apply(Operation::Create { uuid }).unwrap();
+        // a failed operation produces no event
+        assert!(db.apply(Operation::Create { uuid }).is_err());
+        db.apply(Operation::Delete { uuid }).unwrap();
+
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                Event::TaskCreated {
+                    uuid,
+                    source: ChangeSource::Local
+                },
+                Event::TaskDeleted {
+                    uuid,
+                    source: ChangeSource::Local
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rebuild_working_set_notifies_observers() -> Fallible<()> {
+        let mut db = TaskDB::new_inmemory();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let seen1 = seen.clone();
+        db.subscribe(Box::new(move |e: &Event| {
+            seen1.borrow_mut().push(e.clone())
+        }));
+
+        // a rebuild that changes nothing notifies no one
+        db.rebuild_working_set(|_, _| true, true)?;
+        assert_eq!(*seen.borrow(), vec![]);
+
+        db.apply(Operation::Create {
+            uuid: Uuid::new_v4(),
+        })?;
+        seen.borrow_mut().clear();
+        db.rebuild_working_set(|_, _| true, true)?;
+        assert_eq!(*seen.borrow(), vec![Event::WorkingSetRenumbered]);
+
+        seen.borrow_mut().clear();
+        db.rebuild_working_set(|_, _| true, false)?;
+        assert_eq!(*seen.borrow(), vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_restore() -> Fallible<()> {
+        let mut db = TaskDB::new_inmemory();
+        let uuid1 = Uuid::new_v4();
+        let uuid2 = Uuid::new_v4();
+        db.apply(Operation::Create { uuid: uuid1 })?;
+        db.apply(Operation::Create { uuid: uuid2 })?;
+        db.apply(Operation::Update {
+            uuid: uuid2,
+            property: "title".into(),
+            value: Some("two".into()),
+            timestamp: Utc::now(),
+        })?;
+        {
+            let mut txn = db.storage.txn()?;
+            txn.set_working_set_item(2, Some(uuid2))?;
+            txn.set_base_version(Uuid::new_v4())?;
+            txn.commit()?;
+        }
+
+        let snapshot = db.snapshot()?;
+        let mut db2 = TaskDB::new_inmemory();
+        db2.restore(snapshot)?;
+
+        assert_eq!(db2.sorted_tasks(), db.sorted_tasks());
+        assert_eq!(db2.operations(), db.operations());
+        assert_eq!(db2.working_set()?, vec![None, None, Some(uuid2)]);
+        assert_eq!(db2.snapshot()?, db.snapshot()?);
+        Ok(())
+    }
+
+    #[test]
+    fn restore_not_empty() -> Fallible<()> {
+        let mut db = TaskDB::new_inmemory();
+        db.apply(Operation::Create {
+            uuid: Uuid::new_v4(),
+        })?;
+        let snapshot = db.snapshot()?;
+        assert_eq!(
+            db.restore(snapshot).err().unwrap().to_string(),
+            "Task Database Error: Cannot restore into a non-empty task database"
+        );
+        Ok(())
+    }
+
     fn newdb() -> TaskDB {
         TaskDB::new(Box::new(InMemoryStorage::new()))
     }
@@ -667,6 +980,83 @@ mod tests {
         assert_eq!(db1.sorted_tasks(), db2.sorted_tasks());
     }
 
+    #[test]
+    fn test_reset_sync() {
+        let mut server_a: Box<dyn Server> = Box::new(TestServer::new());
+        let mut server_b: Box<dyn Server> = Box::new(TestServer::new());
+
+        // db2 already syncs with server B
+        let mut db2 = newdb();
+        let uuid2 = Uuid::new_v4();
+        db2.apply(Operation::Create { uuid: uuid2 }).unwrap();
+        db2.sync(&mut server_b).unwrap();
+
+        let mut db1 = newdb();
+        let uuid1 = Uuid::new_v4();
+        db1.apply(Operation::Create { uuid: uuid1 }).unwrap();
+        db1.apply(Operation::Update {
+            uuid: uuid1,
+            property: "title".into(),
+            value: Some("my first task".into()),
+            timestamp: Utc::now(),
+        })
+        .unwrap();
+        db1.sync(&mut server_a).unwrap();
+
+        // after a reset, db1 sends its task to server B, and receives db2's
+        db1.reset_sync().unwrap();
+        db1.sync(&mut server_b).unwrap();
+        db2.sync(&mut server_b).unwrap();
+        assert_eq!(db1.sorted_tasks(), db2.sorted_tasks());
+        assert_eq!(db1.sorted_tasks().len(), 2);
+    }
+
+    #[test]
+    fn test_sync_notifies_observers() {
+        let mut server: Box<dyn Server> = Box::new(TestServer::new());
+
+        let mut db1 = newdb();
+        let mut db2 = newdb();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let seen1 = seen.clone();
+        let id = db2.subscribe(Box::new(move |e: &Event| {
+            seen1.borrow_mut().push(e.clone())
+        }));
+
+        let uuid = Uuid::new_v4();
+        db1.apply(Operation::Create { uuid }).unwrap();
+        db1.apply(Operation::Update {
+            uuid,
+            property: "title".into(),
+            value: Some("my task".into()),
+            timestamp: Utc::now(),
+        })
+        .unwrap();
+        db1.sync(&mut server).unwrap();
+        db2.sync(&mut server).unwrap();
+
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                Event::TaskCreated {
+                    uuid,
+                    source: ChangeSource::Sync
+                },
+                Event::TaskUpdated {
+                    uuid,
+                    property: "title".into(),
+                    value: Some("my task".into()),
+                    source: ChangeSource::Sync
+                },
+            ]
+        );
+
+        // after unsubscribing, nothing further is seen
+        assert!(db2.unsubscribe(id));
+        db2.apply(Operation::Delete { uuid }).unwrap();
+        assert_eq!(seen.borrow().len(), 2);
+    }
+
     #[derive(Debug)]
     enum Action {
         Op(Operation),
@@ -1,11 +1,11 @@
 #![allow(clippy::new_without_default)]
 
 use crate::taskstorage::{
-    Operation, TaskMap, TaskStorage, TaskStorageTxn, VersionId, DEFAULT_BASE_VERSION,
+    task_status, Operation, TaskMap, TaskStorage, TaskStorageTxn, VersionId, DEFAULT_BASE_VERSION,
 };
 use failure::Fallible;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
 #[derive(PartialEq, Debug, Clone)]
@@ -14,6 +14,53 @@ struct Data {
     base_version: VersionId,
     operations: Vec<Operation>,
     working_set: Vec<Option<Uuid>>,
+    /// reverse index of the working set, giving the lowest index of each task in it
+    working_set_index: HashMap<Uuid, usize>,
+    /// index of tasks by status
+    status_index: HashMap<String, HashSet<Uuid>>,
+}
+
+impl Data {
+    /// Update the status index for a change in the given task's status
+    fn index_status(&mut self, uuid: &Uuid, old: Option<&str>, new: Option<&str>) {
+        if old == new {
+            return;
+        }
+        if let Some(old) = old {
+            if let Some(uuids) = self.status_index.get_mut(old) {
+                uuids.remove(uuid);
+            }
+        }
+        if let Some(new) = new {
+            self.status_index
+                .entry(new.into())
+                .or_default()
+                .insert(*uuid);
+        }
+    }
+
+    /// Set the working-set item at the given index, keeping the reverse index up to date
+    fn set_working_set_item(&mut self, index: usize, uuid: Option<Uuid>) {
+        if self.working_set.len() <= index {
+            self.working_set.resize(index + 1, None);
+        }
+        let old = std::mem::replace(&mut self.working_set[index], uuid);
+
+        // if the reverse index refers to this slot, fall back to the task's next entry, if any
+        if let Some(old) = old {
+            if self.working_set_index.get(&old) == Some(&index) {
+                match self.working_set.iter().position(|u| u == &Some(old)) {
+                    Some(i) => self.working_set_index.insert(old, i),
+                    None => self.working_set_index.remove(&old),
+                };
+            }
+        }
+
+        if let Some(uuid) = uuid {
+            let i = self.working_set_index.entry(uuid).or_insert(index);
+            *i = (*i).min(index);
+        }
+    }
 }
 
 struct Txn<'t> {
@@ -51,8 +98,10 @@ impl<'t> TaskStorageTxn for Txn<'t> {
     }
 
     fn create_task(&mut self, uuid: Uuid) -> Fallible<bool> {
-        if let ent @ Entry::Vacant(_) = self.mut_data_ref().tasks.entry(uuid) {
+        let data = self.mut_data_ref();
+        if let ent @ Entry::Vacant(_) = data.tasks.entry(uuid) {
             ent.or_insert_with(TaskMap::new);
+            data.index_status(&uuid, None, Some(""));
             Ok(true)
         } else {
             Ok(false)
@@ -60,12 +109,25 @@ impl<'t> TaskStorageTxn for Txn<'t> {
     }
 
     fn set_task(&mut self, uuid: Uuid, task: TaskMap) -> Fallible<()> {
-        self.mut_data_ref().tasks.insert(uuid, task);
+        let data = self.mut_data_ref();
+        let new = task_status(&task).to_string();
+        if let Some(old) = data.tasks.insert(uuid, task) {
+            data.index_status(&uuid, Some(task_status(&old)), Some(&new));
+        } else {
+            data.index_status(&uuid, None, Some(&new));
+        }
         Ok(())
     }
 
     fn delete_task(&mut self, uuid: &Uuid) -> Fallible<bool> {
-        Ok(self.mut_data_ref().tasks.remove(uuid).is_some())
+        let data = self.mut_data_ref();
+        match data.tasks.remove(uuid) {
+            Some(old) => {
+                data.index_status(uuid, Some(task_status(&old)), None);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
     }
 
     fn all_tasks<'a>(&mut self) -> Fallible<Vec<(Uuid, TaskMap)>> {
@@ -81,6 +143,13 @@ impl<'t> TaskStorageTxn for Txn<'t> {
         Ok(self.data_ref().tasks.keys().copied().collect())
     }
 
+    fn task_uuids_with_status(&mut self, status: &str) -> Fallible<Vec<Uuid>> {
+        Ok(match self.data_ref().status_index.get(status) {
+            Some(uuids) => uuids.iter().copied().collect(),
+            None => vec![],
+        })
+    }
+
     fn base_version(&mut self) -> Fallible<VersionId> {
         Ok(self.data_ref().base_version)
     }
@@ -108,14 +177,26 @@ impl<'t> TaskStorageTxn for Txn<'t> {
         Ok(self.data_ref().working_set.clone())
     }
 
+    fn get_working_set_index(&mut self, uuid: &Uuid) -> Fallible<Option<usize>> {
+        Ok(self.data_ref().working_set_index.get(uuid).copied())
+    }
+
     fn add_to_working_set(&mut self, uuid: &Uuid) -> Fallible<usize> {
-        let working_set = &mut self.mut_data_ref().working_set;
-        working_set.push(Some(*uuid));
-        Ok(working_set.len())
+        let data = self.mut_data_ref();
+        let index = data.working_set.len();
+        data.set_working_set_item(index, Some(*uuid));
+        Ok(index)
+    }
+
+    fn set_working_set_item(&mut self, index: usize, uuid: Option<Uuid>) -> Fallible<()> {
+        self.mut_data_ref().set_working_set_item(index, uuid);
+        Ok(())
     }
 
     fn clear_working_set(&mut self) -> Fallible<()> {
-        self.mut_data_ref().working_set = vec![None];
+        let data = self.mut_data_ref();
+        data.working_set = vec![None];
+        data.working_set_index.clear();
         Ok(())
     }
 
@@ -129,7 +210,8 @@ impl<'t> TaskStorageTxn for Txn<'t> {
 }
 
 /// InMemoryStorage is a simple in-memory task storage implementation.  It is not useful for
-/// production data, but is useful for testing purposes.
+/// production data, but is useful for testing and for scratch replicas whose contents are not
+/// kept.
 #[derive(PartialEq, Debug, Clone)]
 pub struct InMemoryStorage {
     data: Data,
@@ -143,6 +225,8 @@ impl InMemoryStorage {
                 base_version: DEFAULT_BASE_VERSION,
                 operations: vec![],
                 working_set: vec![None],
+                working_set_index: HashMap::new(),
+                status_index: HashMap::new(),
             },
         }
     }
@@ -160,6 +244,7 @@ impl TaskStorage for InMemoryStorage {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::taskstorage::{test_status_index, test_working_set_duplicates};
 
     // (note: this module is heavily used in tests so most of its functionality is well-tested
     // elsewhere and not tested here)
@@ -199,6 +284,29 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn set_working_set_item() -> Fallible<()> {
+        let mut storage = InMemoryStorage::new();
+        let uuid1 = Uuid::new_v4();
+        let uuid2 = Uuid::new_v4();
+
+        {
+            let mut txn = storage.txn()?;
+            txn.add_to_working_set(&uuid1)?;
+            txn.set_working_set_item(3, Some(uuid2))?;
+            txn.set_working_set_item(1, None)?;
+            txn.commit()?;
+        }
+
+        {
+            let mut txn = storage.txn()?;
+            let ws = txn.get_working_set()?;
+            assert_eq!(ws, vec![None, None, None, Some(uuid2)]);
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn clear_working_set() -> Fallible<()> {
         let mut storage = InMemoryStorage::new();
@@ -228,4 +336,32 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn get_working_set_index() -> Fallible<()> {
+        let mut storage = InMemoryStorage::new();
+        let uuid1 = Uuid::new_v4();
+        let uuid2 = Uuid::new_v4();
+
+        {
+            let mut txn = storage.txn()?;
+            txn.add_to_working_set(&uuid1)?;
+            txn.set_working_set_item(3, Some(uuid2))?;
+            assert_eq!(txn.get_working_set_index(&uuid1)?, Some(1));
+            assert_eq!(txn.get_working_set_index(&uuid2)?, Some(3));
+            assert_eq!(txn.get_working_set_index(&Uuid::new_v4())?, None);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn task_uuids_with_status() -> Fallible<()> {
+        test_status_index(&mut InMemoryStorage::new())
+    }
+
+    #[test]
+    fn working_set_duplicates() -> Fallible<()> {
+        test_working_set_duplicates(&mut InMemoryStorage::new())
+    }
 }
@@ -1,5 +1,5 @@
 use crate::taskstorage::{
-    Operation, TaskMap, TaskStorage, TaskStorageTxn, VersionId, DEFAULT_BASE_VERSION,
+    task_status, Operation, TaskMap, TaskStorage, TaskStorageTxn, VersionId, DEFAULT_BASE_VERSION,
 };
 use crate::utils::Key;
 use failure::Fallible;
@@ -8,6 +8,8 @@ use kv::{Bucket, Config, Error, Integer, Serde, Store, ValueBuf};
 use std::path::Path;
 use uuid::Uuid;
 
+mod migrations;
+
 /// KVStorage is an on-disk storage backend which uses LMDB via the `kv` crate.
 pub struct KVStorage<'t> {
     store: Store,
@@ -16,11 +18,28 @@ pub struct KVStorage<'t> {
     uuids_bucket: Bucket<'t, Integer, ValueBuf<Msgpack<Uuid>>>,
     operations_bucket: Bucket<'t, Integer, ValueBuf<Msgpack<Operation>>>,
     working_set_bucket: Bucket<'t, Integer, ValueBuf<Msgpack<Uuid>>>,
+    working_set_index_bucket: Bucket<'t, Key, ValueBuf<Msgpack<u64>>>,
+    status_index_bucket: Bucket<'t, Vec<u8>, ValueBuf<Msgpack<Uuid>>>,
 }
 
 const BASE_VERSION: u64 = 1;
 const NEXT_OPERATION: u64 = 2;
 const NEXT_WORKING_SET_INDEX: u64 = 3;
+const SCHEMA_VERSION: u64 = 4;
+
+/// Get the key in the status index for the given task.  Keys begin with the status and a NUL
+/// separator, so all tasks with a given status can be found with a prefix scan.
+fn status_key(status: &str, uuid: &Uuid) -> Vec<u8> {
+    let mut key = status_prefix(status);
+    key.extend_from_slice(uuid.as_bytes());
+    key
+}
+
+fn status_prefix(status: &str) -> Vec<u8> {
+    let mut key = status.as_bytes().to_vec();
+    key.push(0);
+    key
+}
 
 impl<'t> KVStorage<'t> {
     pub fn new<P: AsRef<Path>>(directory: P) -> Fallible<KVStorage<'t>> {
@@ -30,6 +49,8 @@ impl<'t> KVStorage<'t> {
         config.bucket("uuids", None);
         config.bucket("operations", None);
         config.bucket("working_set", None);
+        config.bucket("working_set_index", None);
+        config.bucket("status_index", None);
         let store = Store::new(config)?;
 
         // tasks are stored indexed by uuid
@@ -51,14 +72,36 @@ impl<'t> KVStorage<'t> {
         let working_set_bucket =
             store.int_bucket::<ValueBuf<Msgpack<Uuid>>>(Some("working_set"))?;
 
-        Ok(KVStorage {
+        // this bucket is a reverse index of the working set, mapping uuid to index
+        let working_set_index_bucket =
+            store.bucket::<Key, ValueBuf<Msgpack<u64>>>(Some("working_set_index"))?;
+
+        // this bucket indexes tasks by status; see `status_key`
+        let status_index_bucket =
+            store.bucket::<Vec<u8>, ValueBuf<Msgpack<Uuid>>>(Some("status_index"))?;
+
+        let storage = KVStorage {
             store,
             tasks_bucket,
             numbers_bucket,
             uuids_bucket,
             operations_bucket,
             working_set_bucket,
-        })
+            working_set_index_bucket,
+            status_index_bucket,
+        };
+
+        // bring the schema up to date before any other access to the data
+        {
+            let mut txn = Txn {
+                storage: &storage,
+                txn: Some(storage.store.write_txn()?),
+            };
+            migrations::migrate(&mut txn)?;
+            txn.commit()?;
+        }
+
+        Ok(storage)
     }
 }
 
@@ -102,6 +145,96 @@ impl<'t> Txn<'t> {
     fn working_set_bucket(&self) -> &'t Bucket<'t, Integer, ValueBuf<Msgpack<Uuid>>> {
         &self.storage.working_set_bucket
     }
+    fn working_set_index_bucket(&self) -> &'t Bucket<'t, Key, ValueBuf<Msgpack<u64>>> {
+        &self.storage.working_set_index_bucket
+    }
+    fn status_index_bucket(&self) -> &'t Bucket<'t, Vec<u8>, ValueBuf<Msgpack<Uuid>>> {
+        &self.storage.status_index_bucket
+    }
+
+    /// Update the status index for a change in the given task's status
+    fn index_status(&mut self, uuid: &Uuid, old: Option<&str>, new: Option<&str>) -> Fallible<()> {
+        if old == new {
+            return Ok(());
+        }
+        let bucket = self.status_index_bucket();
+        let kvtxn = self.kvtxn();
+        if let Some(old) = old {
+            match kvtxn.del(bucket, status_key(old, uuid)) {
+                Ok(_) | Err(Error::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if let Some(new) = new {
+            kvtxn.set(bucket, status_key(new, uuid), Msgpack::to_value_buf(*uuid)?)?;
+        }
+        Ok(())
+    }
+
+    /// Record that the given task is in the working set at the given index.  If the task is
+    /// already in the working set at a lower index, the reverse index keeps that index.
+    fn index_working_set_item(&mut self, uuid: &Uuid, index: u64) -> Fallible<()> {
+        let bucket = self.working_set_index_bucket();
+        let kvtxn = self.kvtxn();
+        match kvtxn.get(bucket, uuid.into()) {
+            Ok(buf) => {
+                let existing: u64 = buf.inner()?.to_serde();
+                if existing <= index {
+                    return Ok(());
+                }
+            }
+            Err(Error::NotFound) => {}
+            Err(e) => return Err(e.into()),
+        }
+        kvtxn.set(bucket, uuid.into(), Msgpack::to_value_buf(index)?)?;
+        Ok(())
+    }
+
+    /// Record that the given task is no longer in the working set at the given index.  The reverse
+    /// index only changes if it refers to that index, in which case it moves to the task's next
+    /// entry in the working set, if any.
+    fn unindex_working_set_item(&mut self, uuid: &Uuid, index: u64) -> Fallible<()> {
+        if self.get_working_set_index(uuid)? != Some(index as usize) {
+            return Ok(());
+        }
+        let bucket = self.working_set_index_bucket();
+        // duplicates are rare, so it is sufficient to scan for another entry
+        match self
+            .get_working_set()?
+            .iter()
+            .position(|u| u.as_ref() == Some(uuid))
+        {
+            Some(i) => {
+                self.kvtxn()
+                    .set(bucket, uuid.into(), Msgpack::to_value_buf(i as u64)?)?;
+            }
+            None => {
+                self.kvtxn().del(bucket, Key::from(uuid))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the schema version of the data in this storage; data written before schema versions
+    /// were introduced has version 0.
+    fn schema_version(&mut self) -> Fallible<u64> {
+        let bucket = self.numbers_bucket();
+        match self.kvtxn().get(bucket, SCHEMA_VERSION.into()) {
+            Ok(buf) => Ok(buf.inner()?.to_serde()),
+            Err(Error::NotFound) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_schema_version(&mut self, version: u64) -> Fallible<()> {
+        let bucket = self.numbers_bucket();
+        self.kvtxn().set(
+            bucket,
+            SCHEMA_VERSION.into(),
+            Msgpack::to_value_buf(version)?,
+        )?;
+        Ok(())
+    }
 }
 
 impl<'t> TaskStorageTxn for Txn<'t> {
@@ -122,6 +255,7 @@ impl<'t> TaskStorageTxn for Txn<'t> {
         match kvtxn.get(bucket, uuid.into()) {
             Err(Error::NotFound) => {
                 kvtxn.set(bucket, uuid.into(), Msgpack::to_value_buf(TaskMap::new())?)?;
+                self.index_status(&uuid, None, Some(""))?;
                 Ok(true)
             }
             Err(e) => Err(e.into()),
@@ -130,6 +264,12 @@ impl<'t> TaskStorageTxn for Txn<'t> {
     }
 
     fn set_task(&mut self, uuid: Uuid, task: TaskMap) -> Fallible<()> {
+        let old = self.get_task(&uuid)?;
+        self.index_status(
+            &uuid,
+            old.as_ref().map(task_status),
+            Some(task_status(&task)),
+        )?;
         let bucket = self.tasks_bucket();
         let kvtxn = self.kvtxn();
         kvtxn.set(bucket, uuid.into(), Msgpack::to_value_buf(task)?)?;
@@ -137,13 +277,15 @@ impl<'t> TaskStorageTxn for Txn<'t> {
     }
 
     fn delete_task(&mut self, uuid: &Uuid) -> Fallible<bool> {
+        let old = match self.get_task(uuid)? {
+            Some(old) => old,
+            None => return Ok(false),
+        };
+        self.index_status(uuid, Some(task_status(&old)), None)?;
         let bucket = self.tasks_bucket();
         let kvtxn = self.kvtxn();
-        match kvtxn.del(bucket, uuid.into()) {
-            Err(Error::NotFound) => Ok(false),
-            Err(e) => Err(e.into()),
-            Ok(_) => Ok(true),
-        }
+        kvtxn.del(bucket, uuid.into())?;
+        Ok(true)
     }
 
     fn all_tasks(&mut self) -> Fallible<Vec<(Uuid, TaskMap)>> {
@@ -167,6 +309,20 @@ impl<'t> TaskStorageTxn for Txn<'t> {
             .collect())
     }
 
+    fn task_uuids_with_status(&mut self, status: &str) -> Fallible<Vec<Uuid>> {
+        let bucket = self.status_index_bucket();
+        let kvtxn = self.kvtxn();
+        let prefix = status_prefix(status);
+        let mut res = vec![];
+        for (k, v) in kvtxn.read_cursor(bucket)?.iter_from(&prefix) {
+            if !k.starts_with(&prefix) {
+                break;
+            }
+            res.push(v.inner()?.to_serde());
+        }
+        Ok(res)
+    }
+
     fn base_version(&mut self) -> Fallible<VersionId> {
         let bucket = self.uuids_bucket();
         let base_version = match self.kvtxn().get(bucket, BASE_VERSION.into()) {
@@ -275,6 +431,18 @@ impl<'t> TaskStorageTxn for Txn<'t> {
         Ok(res)
     }
 
+    fn get_working_set_index(&mut self, uuid: &Uuid) -> Fallible<Option<usize>> {
+        let bucket = self.working_set_index_bucket();
+        match self.kvtxn().get(bucket, uuid.into()) {
+            Ok(buf) => {
+                let i: u64 = buf.inner()?.to_serde();
+                Ok(Some(i as usize))
+            }
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     fn add_to_working_set(&mut self, uuid: &Uuid) -> Fallible<usize> {
         let working_set_bucket = self.working_set_bucket();
         let numbers_bucket = self.numbers_bucket();
@@ -296,15 +464,66 @@ impl<'t> TaskStorageTxn for Txn<'t> {
             NEXT_WORKING_SET_INDEX.into(),
             Msgpack::to_value_buf(next_index + 1)?,
         )?;
+        self.index_working_set_item(uuid, next_index)?;
         Ok(next_index as usize)
     }
 
+    fn set_working_set_item(&mut self, index: usize, uuid: Option<Uuid>) -> Fallible<()> {
+        let working_set_bucket = self.working_set_bucket();
+        let numbers_bucket = self.numbers_bucket();
+        let kvtxn = self.kvtxn();
+        let index = index as u64;
+
+        let old: Option<Uuid> = match kvtxn.get(working_set_bucket, index.into()) {
+            Ok(buf) => Some(buf.inner()?.to_serde()),
+            Err(Error::NotFound) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let next_index = match kvtxn.get(numbers_bucket, NEXT_WORKING_SET_INDEX.into()) {
+            Ok(buf) => buf.inner()?.to_serde(),
+            Err(Error::NotFound) => 1,
+            Err(e) => return Err(e.into()),
+        };
+
+        match uuid {
+            Some(uuid) => {
+                kvtxn.set(
+                    working_set_bucket,
+                    index.into(),
+                    Msgpack::to_value_buf(uuid)?,
+                )?;
+            }
+            None => match kvtxn.del(working_set_bucket, index.into()) {
+                Ok(_) | Err(Error::NotFound) => {}
+                Err(e) => return Err(e.into()),
+            },
+        }
+
+        if index >= next_index {
+            kvtxn.set(
+                numbers_bucket,
+                NEXT_WORKING_SET_INDEX.into(),
+                Msgpack::to_value_buf(index + 1)?,
+            )?;
+        }
+        if let Some(old) = old {
+            self.unindex_working_set_item(&old, index)?;
+        }
+        if let Some(uuid) = uuid {
+            self.index_working_set_item(&uuid, index)?;
+        }
+        Ok(())
+    }
+
     fn clear_working_set(&mut self) -> Fallible<()> {
         let working_set_bucket = self.working_set_bucket();
+        let working_set_index_bucket = self.working_set_index_bucket();
         let numbers_bucket = self.numbers_bucket();
         let kvtxn = self.kvtxn();
 
         kvtxn.clear_db(working_set_bucket)?;
+        kvtxn.clear_db(working_set_index_bucket)?;
         kvtxn.set(
             numbers_bucket,
             NEXT_WORKING_SET_INDEX.into(),
@@ -327,7 +546,7 @@ impl<'t> TaskStorageTxn for Txn<'t> {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::taskstorage::taskmap_with;
+    use crate::taskstorage::{taskmap_with, test_status_index, test_working_set_duplicates};
     use failure::Fallible;
     use tempdir::TempDir;
 
@@ -625,6 +844,32 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn set_working_set_item() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut storage = KVStorage::new(tmp_dir.path())?;
+        let uuid1 = Uuid::new_v4();
+        let uuid2 = Uuid::new_v4();
+
+        {
+            let mut txn = storage.txn()?;
+            txn.add_to_working_set(&uuid1)?;
+            txn.set_working_set_item(3, Some(uuid2))?;
+            txn.set_working_set_item(1, None)?;
+            txn.commit()?;
+        }
+
+        {
+            let mut txn = storage.txn()?;
+            let ws = txn.get_working_set()?;
+            assert_eq!(ws, vec![None, None, None, Some(uuid2)]);
+            // adding continues after the highest index
+            assert_eq!(txn.add_to_working_set(&uuid1)?, 4);
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn clear_working_set() -> Fallible<()> {
         let tmp_dir = TempDir::new("test")?;
@@ -655,4 +900,44 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn get_working_set_index() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let mut storage = KVStorage::new(tmp_dir.path())?;
+        let uuid1 = Uuid::new_v4();
+        let uuid2 = Uuid::new_v4();
+        let uuid3 = Uuid::new_v4();
+
+        {
+            let mut txn = storage.txn()?;
+            txn.add_to_working_set(&uuid1)?;
+            txn.add_to_working_set(&uuid2)?;
+            txn.set_working_set_item(2, Some(uuid3))?;
+            txn.commit()?;
+        }
+
+        {
+            let mut txn = storage.txn()?;
+            assert_eq!(txn.get_working_set_index(&uuid1)?, Some(1));
+            assert_eq!(txn.get_working_set_index(&uuid2)?, None);
+            assert_eq!(txn.get_working_set_index(&uuid3)?, Some(2));
+            txn.clear_working_set()?;
+            assert_eq!(txn.get_working_set_index(&uuid1)?, None);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn task_uuids_with_status() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        test_status_index(&mut KVStorage::new(tmp_dir.path())?)
+    }
+
+    #[test]
+    fn working_set_duplicates() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        test_working_set_duplicates(&mut KVStorage::new(tmp_dir.path())?)
+    }
 }
@@ -0,0 +1,190 @@
+//! Schema migrations for KVStorage.
+//!
+//! Each database records the version of its schema in the `numbers` bucket.  When a database is
+//! opened, the migrations between its version and the current version are applied in order, in
+//! the same transaction that records the new version.  A database with a newer schema than this
+//! code supports is refused, rather than risking misinterpreting (and then overwriting) its
+//! contents.
+
+use super::{status_key, task_status, Txn};
+use crate::errors::Error;
+use crate::taskstorage::TaskStorageTxn;
+use crate::utils::Key;
+use failure::Fallible;
+use kv::msgpack::Msgpack;
+use kv::Serde;
+use log::info;
+
+/// A Migration upgrades a database from one schema version to the next.
+struct Migration {
+    description: &'static str,
+    apply: fn(&mut Txn) -> Fallible<()>,
+}
+
+/// All migrations, in order.  The migration at index `i` upgrades the schema from version `i` to
+/// version `i + 1`; version 0 is the original layout, which did not record a version.  New
+/// migrations must only ever be appended to this list.
+const MIGRATIONS: &[Migration] = &[Migration {
+    description: "build working-set and status indexes",
+    apply: build_indexes,
+}];
+
+/// The schema version written by this version of TaskChampion.
+pub(super) const CURRENT_SCHEMA_VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// Bring the database up to the current schema version.  The caller is responsible for
+/// committing the transaction.
+pub(super) fn migrate(txn: &mut Txn) -> Fallible<()> {
+    let version = txn.schema_version()?;
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(Error::DBError(format!(
+            "task database has schema version {}, but this version of TaskChampion only supports up to version {}",
+            version, CURRENT_SCHEMA_VERSION
+        ))
+        .into());
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!(
+            "Migrating task database to schema version {}: {}",
+            i + 1,
+            migration.description
+        );
+        (migration.apply)(txn)?;
+    }
+
+    if version != CURRENT_SCHEMA_VERSION {
+        txn.set_schema_version(CURRENT_SCHEMA_VERSION)?;
+    }
+    Ok(())
+}
+
+/// Version 1: build the working-set and status indexes from the existing data
+fn build_indexes(txn: &mut Txn) -> Fallible<()> {
+    let tasks = txn.all_tasks()?;
+    let working_set = txn.get_working_set()?;
+
+    let status_index_bucket = txn.status_index_bucket();
+    let working_set_index_bucket = txn.working_set_index_bucket();
+    let kvtxn = txn.kvtxn();
+
+    kvtxn.clear_db(status_index_bucket)?;
+    for (uuid, task) in tasks {
+        kvtxn.set(
+            status_index_bucket,
+            status_key(task_status(&task), &uuid),
+            Msgpack::to_value_buf(uuid)?,
+        )?;
+    }
+
+    kvtxn.clear_db(working_set_index_bucket)?;
+    // a task appearing more than once is indexed at its lowest index
+    for (i, uuid) in working_set.iter().enumerate().rev() {
+        if let Some(uuid) = uuid {
+            kvtxn.set(
+                working_set_index_bucket,
+                Key::from(uuid),
+                Msgpack::to_value_buf(i as u64)?,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::taskstorage::{KVStorage, TaskStorage};
+    use std::fs;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    /// Copy the named fixture directory from `tests/fixtures` into a new temporary directory, so
+    /// that migrating it does not modify the fixture.
+    fn fixture(name: &str) -> Fallible<TempDir> {
+        let src = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name);
+        let tmp_dir = TempDir::new("test")?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            fs::copy(entry.path(), tmp_dir.path().join(entry.file_name()))?;
+        }
+        Ok(tmp_dir)
+    }
+
+    #[test]
+    fn new_database_is_current() -> Fallible<()> {
+        let tmp_dir = TempDir::new("test")?;
+        let storage = KVStorage::new(tmp_dir.path())?;
+        let mut txn = Txn {
+            storage: &storage,
+            txn: Some(storage.store.write_txn()?),
+        };
+        assert_eq!(txn.schema_version()?, CURRENT_SCHEMA_VERSION);
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_v0() -> Fallible<()> {
+        // this database was written by a version of TaskChampion that did not record a schema
+        // version.  It contains two pending tasks and one completed task, all in the working set.
+        let tmp_dir = fixture("kv-v0")?;
+        let mut storage = KVStorage::new(tmp_dir.path())?;
+        let mut txn = storage.txn()?;
+
+        let pending = txn.task_uuids_with_status("P")?;
+        assert_eq!(pending.len(), 2);
+        let completed = txn.task_uuids_with_status("C")?;
+        assert_eq!(completed.len(), 1);
+        let task = txn.get_task(&completed[0])?.unwrap();
+        assert_eq!(task.get("description").unwrap(), "completed task");
+
+        let working_set = txn.get_working_set()?;
+        assert_eq!(working_set.len(), 4);
+        for (i, uuid) in working_set.iter().enumerate().skip(1) {
+            assert_eq!(txn.get_working_set_index(&uuid.unwrap())?, Some(i));
+        }
+
+        // the existing operations are intact
+        assert!(!txn.operations()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_is_idempotent() -> Fallible<()> {
+        let tmp_dir = fixture("kv-v0")?;
+        KVStorage::new(tmp_dir.path())?;
+        let mut storage = KVStorage::new(tmp_dir.path())?;
+        let mut txn = storage.txn()?;
+        assert_eq!(txn.task_uuids_with_status("P")?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn refuse_newer_version() -> Fallible<()> {
+        let tmp_dir = fixture("kv-v0")?;
+        {
+            let storage = KVStorage::new(tmp_dir.path())?;
+            let mut txn = Txn {
+                storage: &storage,
+                txn: Some(storage.store.write_txn()?),
+            };
+            txn.set_schema_version(CURRENT_SCHEMA_VERSION + 1)?;
+            txn.commit()?;
+        }
+
+        let err = KVStorage::new(tmp_dir.path()).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Task Database Error: task database has schema version {}, but this version of TaskChampion only supports up to version {}",
+                CURRENT_SCHEMA_VERSION + 1,
+                CURRENT_SCHEMA_VERSION
+            )
+        );
+        Ok(())
+    }
+}
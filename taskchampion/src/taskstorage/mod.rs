@@ -17,7 +17,7 @@ pub use operation::Operation;
 pub type TaskMap = HashMap<String, String>;
 
 #[cfg(test)]
-fn taskmap_with(mut properties: Vec<(String, String)>) -> TaskMap {
+pub(crate) fn taskmap_with(mut properties: Vec<(String, String)>) -> TaskMap {
     let mut rv = TaskMap::new();
     for (p, v) in properties.drain(..) {
         rv.insert(p, v);
@@ -25,6 +25,97 @@ fn taskmap_with(mut properties: Vec<(String, String)>) -> TaskMap {
     rv
 }
 
+/// Check that the storage's status index follows changes to tasks.  This is shared by the tests
+/// of each storage implementation.
+#[cfg(test)]
+pub(crate) fn test_status_index(storage: &mut dyn TaskStorage) -> Fallible<()> {
+    let uuid1 = Uuid::new_v4();
+    let uuid2 = Uuid::new_v4();
+    let uuid3 = Uuid::new_v4();
+
+    {
+        let mut txn = storage.txn()?;
+        txn.create_task(uuid1)?;
+        txn.set_task(uuid2, taskmap_with(vec![("status".into(), "P".into())]))?;
+        txn.set_task(uuid3, taskmap_with(vec![("status".into(), "P".into())]))?;
+        txn.set_task(uuid3, taskmap_with(vec![("status".into(), "C".into())]))?;
+        txn.commit()?;
+    }
+
+    {
+        let mut txn = storage.txn()?;
+        assert_eq!(txn.task_uuids_with_status("")?, vec![uuid1]);
+        assert_eq!(txn.task_uuids_with_status("P")?, vec![uuid2]);
+        assert_eq!(txn.task_uuids_with_status("C")?, vec![uuid3]);
+        assert_eq!(txn.task_uuids_with_status("D")?, vec![]);
+        txn.delete_task(&uuid2)?;
+        assert_eq!(txn.task_uuids_with_status("P")?, vec![]);
+    }
+
+    Ok(())
+}
+
+/// Check that the storage's reverse index of the working set gives the lowest index of a task
+/// appearing more than once, as entries are added, replaced, and cleared.  This is shared by the tests of each storage implementation.
+#[cfg(test)]
+pub(crate) fn test_working_set_duplicates(storage: &mut dyn TaskStorage) -> Fallible<()> {
+    let uuid1 = Uuid::new_v4();
+    let uuid2 = Uuid::new_v4();
+    let uuid3 = Uuid::new_v4();
+    let uuid4 = Uuid::new_v4();
+
+    {
+        let mut txn = storage.txn()?;
+        assert_eq!(txn.add_to_working_set(&uuid1)?, 1);
+        assert_eq!(txn.add_to_working_set(&uuid2)?, 2);
+        assert_eq!(txn.add_to_working_set(&uuid1)?, 3);
+        assert_eq!(txn.get_working_set_index(&uuid1)?, Some(1));
+        txn.set_working_set_item(5, Some(uuid2))?;
+        assert_eq!(txn.get_working_set_index(&uuid2)?, Some(2));
+        txn.set_working_set_item(6, Some(uuid3))?;
+        txn.set_working_set_item(4, Some(uuid3))?;
+        assert_eq!(txn.get_working_set_index(&uuid3)?, Some(4));
+        txn.set_working_set_item(4, Some(uuid4))?;
+        txn.commit()?;
+    }
+
+    {
+        let mut txn = storage.txn()?;
+        assert_eq!(
+            txn.get_working_set()?,
+            vec![
+                None,
+                Some(uuid1),
+                Some(uuid2),
+                Some(uuid1),
+                Some(uuid4),
+                Some(uuid2),
+                Some(uuid3)
+            ]
+        );
+        assert_eq!(txn.get_working_set_index(&uuid1)?, Some(1));
+        assert_eq!(txn.get_working_set_index(&uuid2)?, Some(2));
+        assert_eq!(txn.get_working_set_index(&uuid4)?, Some(4));
+
+        // clearing a duplicate leaves the index unchanged, while clearing the indexed entry
+        // moves the index to the remaining duplicate
+        txn.set_working_set_item(5, None)?;
+        assert_eq!(txn.get_working_set_index(&uuid2)?, Some(2));
+        txn.set_working_set_item(1, None)?;
+        assert_eq!(txn.get_working_set_index(&uuid1)?, Some(3));
+        txn.set_working_set_item(3, Some(uuid4))?;
+        assert_eq!(txn.get_working_set_index(&uuid1)?, None);
+        assert_eq!(txn.get_working_set_index(&uuid4)?, Some(3));
+    }
+
+    Ok(())
+}
+
+/// Get the indexed status of a task (an empty string if it has no status)
+fn task_status(task: &TaskMap) -> &str {
+    task.get("status").map(|s| s.as_ref()).unwrap_or("")
+}
+
 /// The type of VersionIds
 pub use crate::server::VersionId;
 
@@ -65,6 +156,11 @@ pub trait TaskStorageTxn {
     /// Get the uuids of all tasks in the storage, in undefined order.
     fn all_task_uuids(&mut self) -> Fallible<Vec<Uuid>>;
 
+    /// Get the uuids of all tasks with the given value of the `status` property, in undefined
+    /// order.  Tasks with no status are found with an empty string.  Implementations should
+    /// answer this from an index, without scanning all tasks.
+    fn task_uuids_with_status(&mut self, status: &str) -> Fallible<Vec<Uuid>>;
+
     /// Get the current base_version for this storage -- the last version synced from the server.
     fn base_version(&mut self) -> Fallible<VersionId>;
 
@@ -86,10 +182,21 @@ pub trait TaskStorageTxn {
     /// Element 0 is always None.
     fn get_working_set(&mut self) -> Fallible<Vec<Option<Uuid>>>;
 
+    /// Get the (one-based) working-set index of the given task, if it is in the working set.  A
+    /// task should appear in the working set only once, but if it appears more than once (see
+    /// [`crate::Problem::DuplicateWorkingSetEntry`]), this is its lowest index.  Implementations
+    /// should answer this from an index, without reading the whole working set.
+    fn get_working_set_index(&mut self, uuid: &Uuid) -> Fallible<Option<usize>>;
+
     /// Add a task to the working set and return its (one-based) index.  This index will be one greater
-    /// than the highest used index.
+    /// than the highest used index.  This does not check whether the task is already in the
+    /// working set.
     fn add_to_working_set(&mut self, uuid: &Uuid) -> Fallible<usize>;
 
+    /// Set the working-set item at the given (one-based) index, extending the working set if
+    /// necessary.  A value of None leaves a gap at that index.
+    fn set_working_set_item(&mut self, index: usize, uuid: Option<Uuid>) -> Fallible<()>;
+
     /// Clear all tasks from the working set in preparation for a garbage-collection operation.
     /// Note that this is the only way items are removed from the set.
     fn clear_working_set(&mut self) -> Fallible<()>;
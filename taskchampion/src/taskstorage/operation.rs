@@ -30,6 +30,15 @@ pub enum Operation {
 use Operation::*;
 
 impl Operation {
+    /// Get the UUID of the task this operation affects
+    pub(crate) fn uuid(&self) -> &Uuid {
+        match self {
+            Create { uuid } => uuid,
+            Delete { uuid } => uuid,
+            Update { uuid, .. } => uuid,
+        }
+    }
+
     // Transform takes two operations A and B that happened concurrently and produces two
     // operations A' and B' such that `apply(apply(S, A), B') = apply(apply(S, B), A')`. This
     // function is used to serialize operations in a process similar to a Git "rebase".
@@ -0,0 +1,653 @@
+/*!
+Import and export of tasks in the JSON format used by Taskwarrior 2.x (`task import` and
+`task export`).
+
+Taskwarrior's task properties are mapped to TaskMap keys as follows:
+
+* `uuid` is the task's UUID
+* `status` is mapped to `P`, `C`, or `D` (`waiting` tasks are pending, with a `wait` property)
+* `description`, `project`, and `priority` are copied as-is
+* `entry`, `modified`, `due`, `wait`, `end`, `scheduled`, and `until` are converted to epoch
+  timestamps
+* `start` becomes an open `start.<timestamp>` interval
+* each tag in `tags` becomes a `tag.<tag>` key
+* each annotation in `annotations` becomes an `annotation.<timestamp>` key
+* each UUID in `depends` becomes a `dep.<uuid>` key
+* any other property is a user-defined attribute (UDA), and is stored as `uda.<name>`
+
+The derived `id` and `urgency` properties are ignored.  Recurring tasks are not supported.
+*/
+
+use crate::replica::Replica;
+use crate::task::{Task, TaskMut, TIMESTAMP_PROPERTIES};
+use crate::taskstorage::TaskMap;
+use chrono::prelude::*;
+use failure::{format_err, Fallible};
+use serde_json::{Map, Value};
+use std::io::{Read, Write};
+use uuid::Uuid;
+
+/// The format Taskwarrior uses for timestamps
+const TW_DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Properties containing strings, which are copied directly
+const STRING_PROPERTIES: &[&str] = &["description", "project", "priority"];
+
+/// Taskwarrior properties that are derived, and thus not imported
+const IGNORED_PROPERTIES: &[&str] = &["id", "urgency"];
+
+/// The result of an import operation
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportReport {
+    /// Number of tasks that did not exist and were created
+    pub created: usize,
+    /// Number of existing tasks that were modified
+    pub updated: usize,
+    /// Number of existing tasks that already matched the imported data
+    pub unchanged: usize,
+    /// Records that could not be imported, as (0-based record index, reason)
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// Import tasks from Taskwarrior's JSON format, which may be either a JSON array of tasks or a
+/// sequence of JSON objects, one per line.  Task UUIDs are preserved, and importing the same data
+/// again makes no changes.  Records which cannot be mapped are reported in the result, rather
+/// than causing the import to fail.
+pub fn import_json<R: Read>(replica: &mut Replica, mut reader: R) -> Fallible<ImportReport> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+
+    let records: Vec<Value> = if input.trim_start().starts_with('[') {
+        serde_json::from_str(&input)?
+    } else {
+        input
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?
+    };
+
+    let mut report = ImportReport::default();
+    for (i, record) in records.iter().enumerate() {
+        let (uuid, taskmap) = match record_to_taskmap(record) {
+            Ok(r) => r,
+            Err(e) => {
+                report.skipped.push((i, e.to_string()));
+                continue;
+            }
+        };
+        import_task(replica, uuid, taskmap, &mut report)?;
+    }
+    Ok(report)
+}
+
+/// Export all tasks in Taskwarrior's JSON format, as a JSON array.
+pub fn export_json<W: Write>(replica: &mut Replica, writer: W) -> Fallible<()> {
+    let mut tasks: Vec<_> = replica.all_tasks()?.drain().collect();
+    tasks.sort_by_key(|(uuid, _)| *uuid);
+    let records: Vec<Value> = tasks
+        .iter()
+        .map(|(uuid, task)| taskmap_to_record(uuid, task.get_taskmap()))
+        .collect();
+    serde_json::to_writer(writer, &records)?;
+    Ok(())
+}
+
+/// Convert a single task to a record in Taskwarrior's JSON format, as in `export_json`.
+pub fn task_to_json(task: &Task) -> Value {
+    taskmap_to_record(task.get_uuid(), task.get_taskmap())
+}
+
+/// Apply a single record in Taskwarrior's JSON format to the replica, as in `import_json`,
+/// creating the task if necessary.  Unlike `import_json`, a record which cannot be mapped is an
+/// error.  Returns the task's UUID.
+pub fn apply_json(replica: &mut Replica, record: &Value) -> Fallible<Uuid> {
+    let (uuid, taskmap) = record_to_taskmap(record)?;
+    import_task(replica, uuid, taskmap, &mut ImportReport::default())?;
+    Ok(uuid)
+}
+
+/// Apply the differences between two records in Taskwarrior's JSON format for this task, such as
+/// the task before and after a hook changed it.  Only properties whose values differ are changed,
+/// so properties which Taskwarrior's format does not represent, such as earlier intervals during
+/// which the task was active, are kept.
+pub fn apply_json_changes(task: &mut TaskMut, before: &Value, after: &Value) -> Fallible<()> {
+    let (_, mut before) = record_to_taskmap(before)?;
+    let (uuid, mut after) = record_to_taskmap(after)?;
+    if &uuid != task.get_uuid() {
+        return Err(format_err!(
+            "record is for task {}, not {}",
+            uuid,
+            task.get_uuid()
+        ));
+    }
+
+    // Taskwarrior only records the start of the current interval, so start or stop the task
+    // only when that differs
+    match (before.remove("start"), after.remove("start")) {
+        (None, Some(start)) => task.set_property(&format!("start.{}", start), Some("".into()))?,
+        (Some(_), None) => task.stop()?,
+        _ => {}
+    }
+    for (k, v) in after.iter() {
+        if before.get(k) != Some(v) {
+            task.set_property(k, Some(v.clone()))?;
+        }
+    }
+    for k in before.keys() {
+        if !after.contains_key(k) {
+            task.set_property(k, None)?;
+        }
+    }
+    Ok(())
+}
+
+/// Apply the given taskmap to the replica, creating the task if necessary.
+fn import_task(
+    replica: &mut Replica,
+    uuid: Uuid,
+    mut taskmap: TaskMap,
+    report: &mut ImportReport,
+) -> Fallible<()> {
+    let existing = replica.get_task(&uuid)?;
+    let created = existing.is_none();
+    let existing = match existing {
+        Some(task) => task.get_taskmap().clone(),
+        None => {
+            replica.create_task(uuid)?;
+            report.created += 1;
+            TaskMap::new()
+        }
+    };
+
+    // Taskwarrior only records the start of the current interval, so open or close intervals
+    // only when the active state differs.
+    let active = existing
+        .iter()
+        .any(|(k, v)| k.starts_with("start.") && v.is_empty());
+    let start = taskmap.remove("start");
+    match (start, active) {
+        (Some(start), false) => {
+            taskmap.insert(format!("start.{}", start), String::new());
+        }
+        (None, true) => {
+            let stop = taskmap
+                .get("end")
+                .or_else(|| taskmap.get("modified"))
+                .cloned()
+                .unwrap_or_else(|| Utc::now().timestamp().to_string());
+            for (k, v) in existing.iter() {
+                if k.starts_with("start.") && v.is_empty() {
+                    taskmap.insert(k.clone(), stop.clone());
+                }
+            }
+        }
+        _ => {}
+    }
+
+    let mut changed = false;
+    for (k, v) in taskmap.iter() {
+        if existing.get(k) != Some(v) {
+            replica.update_task(uuid, k, Some(v))?;
+            changed = true;
+        }
+    }
+    for k in existing.keys() {
+        if is_imported_property(k) && !taskmap.contains_key(k) {
+            replica.update_task::<_, &str>(uuid, k, None)?;
+            changed = true;
+        }
+    }
+
+    if let Some(task) = replica.get_task(&uuid)? {
+        if replica.in_working_set(&task) {
+            replica.add_to_working_set(&uuid)?;
+        }
+    }
+
+    if !created {
+        if changed {
+            report.updated += 1;
+        } else {
+            report.unchanged += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Determine whether the given TaskMap key is derived from a Taskwarrior property during import;
+/// such keys are removed when re-importing a task without that property.
+fn is_imported_property(key: &str) -> bool {
+    key == "status"
+        || TIMESTAMP_PROPERTIES.contains(&key)
+        || STRING_PROPERTIES.contains(&key)
+        || key.starts_with("tag.")
+        || key.starts_with("annotation.")
+        || key.starts_with("dep.")
+        || key.starts_with("uda.")
+}
+
+/// Convert a Taskwarrior JSON record to a UUID and TaskMap.  The Taskwarrior `start` property is
+/// returned in the TaskMap as `start`, for `import_task` to handle.
+fn record_to_taskmap(record: &Value) -> Fallible<(Uuid, TaskMap)> {
+    let record = record
+        .as_object()
+        .ok_or_else(|| format_err!("record is not an object"))?;
+
+    let uuid = match record.get("uuid") {
+        Some(Value::String(u)) => {
+            Uuid::parse_str(u).map_err(|_| format_err!("invalid uuid {:?}", u))?
+        }
+        _ => return Err(format_err!("record has no uuid")),
+    };
+
+    let mut taskmap = TaskMap::new();
+    for (k, v) in record.iter() {
+        match k.as_ref() {
+            "uuid" => {}
+            "status" => {
+                let status = match v.as_str() {
+                    Some("pending") | Some("waiting") => "P",
+                    Some("completed") => "C",
+                    Some("deleted") => "D",
+                    Some("recurring") => {
+                        return Err(format_err!("recurring tasks are not supported"))
+                    }
+                    _ => return Err(format_err!("invalid status {}", v)),
+                };
+                taskmap.insert("status".into(), status.into());
+            }
+            "start" => {
+                taskmap.insert("start".into(), timestamp_value(k, v)?);
+            }
+            "tags" => {
+                for tag in string_array(k, v)? {
+                    taskmap.insert(format!("tag.{}", tag), String::new());
+                }
+            }
+            "depends" => {
+                // Taskwarrior 2.5 uses a comma-separated string, while 2.6 uses an array
+                let deps = match v {
+                    Value::String(s) => s.split(',').map(|s| s.to_string()).collect(),
+                    _ => string_array(k, v)?,
+                };
+                for dep in deps {
+                    let dep = Uuid::parse_str(dep.trim())
+                        .map_err(|_| format_err!("invalid dependency {:?}", dep))?;
+                    taskmap.insert(format!("dep.{}", dep), String::new());
+                }
+            }
+            "annotations" => {
+                let annotations = v
+                    .as_array()
+                    .ok_or_else(|| format_err!("annotations is not an array"))?;
+                for annotation in annotations {
+                    let entry = annotation
+                        .get("entry")
+                        .ok_or_else(|| format_err!("annotation has no entry"))?;
+                    let description = annotation
+                        .get("description")
+                        .and_then(|d| d.as_str())
+                        .ok_or_else(|| format_err!("annotation has no description"))?;
+                    taskmap.insert(
+                        format!("annotation.{}", timestamp_value("annotation", entry)?),
+                        description.into(),
+                    );
+                }
+            }
+            k if TIMESTAMP_PROPERTIES.contains(&k) => {
+                taskmap.insert(k.into(), timestamp_value(k, v)?);
+            }
+            k if STRING_PROPERTIES.contains(&k) => {
+                let s = v
+                    .as_str()
+                    .ok_or_else(|| format_err!("{} is not a string", k))?;
+                taskmap.insert(k.into(), s.into());
+            }
+            k if IGNORED_PROPERTIES.contains(&k) => {}
+            "recur" | "mask" | "imask" | "parent" => {
+                return Err(format_err!("recurring tasks are not supported"));
+            }
+            _ => {
+                let value = match v {
+                    Value::String(s) => s.clone(),
+                    Value::Number(n) => n.to_string(),
+                    _ => return Err(format_err!("unsupported value for UDA {}", k)),
+                };
+                taskmap.insert(format!("uda.{}", k), value);
+            }
+        }
+    }
+
+    Ok((uuid, taskmap))
+}
+
+/// Convert a UUID and TaskMap to a Taskwarrior JSON record.  Keys which do not correspond to a
+/// Taskwarrior property are omitted.
+fn taskmap_to_record(uuid: &Uuid, taskmap: &TaskMap) -> Value {
+    let mut record = Map::new();
+    let mut tags = vec![];
+    let mut depends = vec![];
+    let mut annotations = vec![];
+    let mut start: Option<i64> = None;
+
+    record.insert("uuid".into(), Value::String(uuid.to_string()));
+    for (k, v) in taskmap.iter() {
+        if k == "status" {
+            let status = match v.as_ref() {
+                "C" => "completed",
+                "D" => "deleted",
+                _ => "pending",
+            };
+            record.insert(k.clone(), Value::String(status.into()));
+        } else if TIMESTAMP_PROPERTIES.contains(&k.as_ref()) {
+            if let Some(ts) = format_timestamp(v) {
+                record.insert(k.clone(), Value::String(ts));
+            }
+        } else if STRING_PROPERTIES.contains(&k.as_ref()) {
+            record.insert(k.clone(), Value::String(v.clone()));
+        } else if let Some(tag) = k.strip_prefix("tag.") {
+            tags.push(Value::String(tag.into()));
+        } else if let Some(dep) = k.strip_prefix("dep.") {
+            depends.push(Value::String(dep.into()));
+        } else if let Some(ts) = k.strip_prefix("annotation.") {
+            if let Some(entry) = format_timestamp(ts) {
+                annotations.push((entry, v.clone()));
+            }
+        } else if let Some(uda) = k.strip_prefix("uda.") {
+            record.insert(uda.into(), Value::String(v.clone()));
+        } else if let Some(ts) = k.strip_prefix("start.") {
+            // only the latest open interval is represented in Taskwarrior
+            if v.is_empty() {
+                if let Ok(ts) = ts.parse() {
+                    start = Some(start.map_or(ts, |s: i64| s.max(ts)));
+                }
+            }
+        }
+    }
+
+    if !record.contains_key("status") {
+        record.insert("status".into(), Value::String("pending".into()));
+    }
+    if let Some(start) = start.and_then(|s| format_timestamp(&s.to_string())) {
+        record.insert("start".into(), Value::String(start));
+    }
+    if !tags.is_empty() {
+        tags.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+        record.insert("tags".into(), Value::Array(tags));
+    }
+    if !depends.is_empty() {
+        depends.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+        record.insert("depends".into(), Value::Array(depends));
+    }
+    if !annotations.is_empty() {
+        annotations.sort();
+        let annotations = annotations
+            .drain(..)
+            .map(|(entry, description)| {
+                let mut a = Map::new();
+                a.insert("entry".into(), Value::String(entry));
+                a.insert("description".into(), Value::String(description));
+                Value::Object(a)
+            })
+            .collect();
+        record.insert("annotations".into(), Value::Array(annotations));
+    }
+
+    Value::Object(record)
+}
+
+/// Parse a Taskwarrior timestamp value into an epoch timestamp string.  This accepts
+/// Taskwarrior's own format, RFC 3339, and integer epoch timestamps.
+fn timestamp_value(property: &str, value: &Value) -> Fallible<String> {
+    let invalid = || format_err!("invalid timestamp for {}: {}", property, value);
+    // an epoch timestamp must also be within the range of dates that can be represented
+    let epoch = |ts: i64| Utc.timestamp_opt(ts, 0).single().map(|_| ts.to_string());
+    match value {
+        Value::Number(n) => n.as_i64().and_then(epoch).ok_or_else(invalid),
+        Value::String(s) => {
+            if let Ok(dt) = Utc.datetime_from_str(s, TW_DATE_FORMAT) {
+                Ok(dt.timestamp().to_string())
+            } else if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+                Ok(dt.timestamp().to_string())
+            } else {
+                s.parse::<i64>().ok().and_then(epoch).ok_or_else(invalid)
+            }
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Format an epoch timestamp string in Taskwarrior's format, if it is valid.
+fn format_timestamp(ts: &str) -> Option<String> {
+    let ts = Utc.timestamp_opt(ts.parse().ok()?, 0).single()?;
+    Some(ts.format(TW_DATE_FORMAT).to_string())
+}
+
+fn string_array(property: &str, value: &Value) -> Fallible<Vec<String>> {
+    value
+        .as_array()
+        .and_then(|a| {
+            a.iter()
+                .map(|v| v.as_str().map(|s| s.to_string()))
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| format_err!("{} is not an array of strings", property))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::task::Status;
+
+    const UUID1: &str = "8f8e5f3a-3e4c-4c5a-9d0b-6a1c7b0d2e11";
+    const UUID2: &str = "1b2c3d4e-5f60-4718-8a9b-0c1d2e3f4a5b";
+
+    fn sample() -> String {
+        format!(
+            r#"[
+            {{"id":1,"uuid":"{}","status":"pending","description":"buy milk",
+              "entry":"20201123T142122Z","modified":"20201124T080000Z","due":"20201201T000000Z",
+              "start":"20201124T070000Z","tags":["home","errand"],"project":"house",
+              "priority":"H","depends":"{}","urgency":12.3,"estimate":"2h",
+              "annotations":[{{"entry":"20201123T150000Z","description":"2 litres"}}]}},
+            {{"uuid":"{}","status":"completed","description":"old","end":"20201120T000000Z"}},
+            {{"uuid":"not-a-uuid","status":"pending","description":"bad"}},
+            {{"uuid":"{}","status":"recurring","description":"weekly","recur":"weekly"}}
+        ]"#,
+            UUID1,
+            UUID2,
+            UUID2,
+            Uuid::new_v4()
+        )
+    }
+
+    #[test]
+    fn import() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        let report = import_json(&mut rep, sample().as_bytes())?;
+        assert_eq!(report.created, 2);
+        assert_eq!(report.updated, 0);
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(report.skipped[0].0, 2);
+        assert_eq!(report.skipped[1].0, 3);
+
+        let uuid1 = Uuid::parse_str(UUID1)?;
+        let task = rep.get_task(&uuid1)?.unwrap();
+        assert_eq!(task.get_description(), "buy milk");
+        assert_eq!(task.get_status(), Status::Pending);
+        assert!(task.is_active());
+        let tm = task.get_taskmap();
+        assert_eq!(tm.get("entry"), Some(&"1606141282".to_string()));
+        assert_eq!(tm.get("project"), Some(&"house".to_string()));
+        assert_eq!(tm.get("priority"), Some(&"H".to_string()));
+        assert_eq!(tm.get("tag.home"), Some(&"".to_string()));
+        assert_eq!(tm.get("tag.errand"), Some(&"".to_string()));
+        assert_eq!(tm.get(&format!("dep.{}", UUID2)), Some(&"".to_string()));
+        assert_eq!(
+            tm.get("annotation.1606143600"),
+            Some(&"2 litres".to_string())
+        );
+        assert_eq!(tm.get("uda.estimate"), Some(&"2h".to_string()));
+        assert!(tm.get("id").is_none());
+        assert!(tm.get("urgency").is_none());
+        assert!(rep.get_working_set_index(&uuid1)?.is_some());
+
+        let task = rep.get_task(&Uuid::parse_str(UUID2)?)?.unwrap();
+        assert_eq!(task.get_status(), Status::Completed);
+        Ok(())
+    }
+
+    #[test]
+    fn import_idempotent() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        import_json(&mut rep, sample().as_bytes())?;
+        let before = rep.all_tasks()?;
+
+        let report = import_json(&mut rep, sample().as_bytes())?;
+        assert_eq!(report.created, 0);
+        assert_eq!(report.updated, 0);
+        assert_eq!(report.unchanged, 2);
+        assert_eq!(rep.all_tasks()?, before);
+        Ok(())
+    }
+
+    #[test]
+    fn import_updates() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        import_json(&mut rep, sample().as_bytes())?;
+
+        let update = format!(
+            r#"{{"uuid":"{}","status":"completed","description":"buy milk","tags":["home"],"end":"20201125T000000Z"}}"#,
+            UUID1
+        );
+        let report = import_json(&mut rep, update.as_bytes())?;
+        assert_eq!(report.updated, 1);
+
+        let task = rep.get_task(&Uuid::parse_str(UUID1)?)?.unwrap();
+        assert_eq!(task.get_status(), Status::Completed);
+        assert!(!task.is_active());
+        let tm = task.get_taskmap();
+        assert!(tm.get("tag.errand").is_none());
+        assert!(tm.get("project").is_none());
+        // the interval was closed at the end time
+        assert_eq!(tm.get("start.1606201200"), Some(&"1606262400".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn import_lines() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        let input = format!(
+            "{{\"uuid\":\"{}\",\"description\":\"one\"}}\n\n{{\"uuid\":\"{}\",\"description\":\"two\"}}\n",
+            UUID1, UUID2
+        );
+        let report = import_json(&mut rep, input.as_bytes())?;
+        assert_eq!(report.created, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn import_invalid_json() {
+        let mut rep = Replica::new_inmemory();
+        assert!(import_json(&mut rep, "[{".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn import_out_of_range() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        for due in &["99999999999999999", "\"99999999999999999\""] {
+            let json = format!(
+                r#"[{{"uuid":"{}","status":"pending","description":"x","due":{}}}]"#,
+                UUID1, due
+            );
+            let report = import_json(&mut rep, json.as_bytes())?;
+            assert_eq!(report.skipped.len(), 1);
+            assert!(report.skipped[0].1.starts_with("invalid timestamp for due"));
+        }
+        assert_eq!(rep.get_task(&Uuid::parse_str(UUID1)?)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        import_json(&mut rep, sample().as_bytes())?;
+        let mut exported = vec![];
+        export_json(&mut rep, &mut exported)?;
+
+        let records: Vec<Value> = serde_json::from_slice(&exported)?;
+        assert_eq!(records.len(), 2);
+        let record = records.iter().find(|r| r["uuid"] == UUID1).unwrap();
+        assert_eq!(record["status"], "pending");
+        assert_eq!(record["entry"], "20201123T142122Z");
+        assert_eq!(record["start"], "20201124T070000Z");
+        assert_eq!(record["tags"], serde_json::json!(["errand", "home"]));
+        assert_eq!(record["depends"], serde_json::json!([UUID2]));
+        assert_eq!(record["estimate"], "2h");
+        assert_eq!(
+            record["annotations"],
+            serde_json::json!([{"entry": "20201123T150000Z", "description": "2 litres"}])
+        );
+
+        // importing the export into a new replica gives the same tasks
+        let mut rep2 = Replica::new_inmemory();
+        let report = import_json(&mut rep2, &exported[..])?;
+        assert_eq!(report.created, 2);
+        assert!(report.skipped.is_empty());
+        assert_eq!(rep2.all_tasks()?, rep.all_tasks()?);
+        Ok(())
+    }
+
+    #[test]
+    fn single_task() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        import_json(&mut rep, sample().as_bytes())?;
+        let uuid1 = Uuid::parse_str(UUID1)?;
+        let mut record = task_to_json(&rep.get_task(&uuid1)?.unwrap());
+        assert_eq!(record["description"], "buy milk");
+
+        record["description"] = "buy oat milk".into();
+        assert_eq!(apply_json(&mut rep, &record)?, uuid1);
+        let task = rep.get_task(&uuid1)?.unwrap();
+        assert_eq!(task.get_description(), "buy oat milk");
+        assert!(task.is_active());
+
+        assert!(apply_json(&mut rep, &serde_json::json!({"description": "no uuid"})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn json_changes() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        import_json(&mut rep, sample().as_bytes())?;
+        let uuid1 = Uuid::parse_str(UUID1)?;
+        // an earlier interval, which Taskwarrior's format does not represent
+        rep.update_task(uuid1, "start.1000", Some("2000"))?;
+        let task = rep.get_task(&uuid1)?.unwrap();
+        let before = task_to_json(&task);
+
+        let mut after = before.clone();
+        after["description"] = "buy oat milk".into();
+        after["tags"] = serde_json::json!(["errand", "shop"]);
+        after.as_object_mut().unwrap().remove("project");
+        after.as_object_mut().unwrap().remove("start");
+        let mut task = task.into_mut(&mut rep);
+        apply_json_changes(&mut task, &before, &after)?;
+        let task = task.into_immut();
+
+        assert_eq!(task.get_description(), "buy oat milk");
+        assert_eq!(task.get_tags(), vec!["errand", "shop"]);
+        assert_eq!(task.get_project(), None);
+        assert!(!task.is_active());
+        assert_eq!(task.get_intervals().len(), 2);
+        assert_eq!(task.get_taskmap()["start.1000"], "2000");
+        assert_eq!(task.get_taskmap()["uda.estimate"], "2h");
+
+        let mut other = before.clone();
+        other["uuid"] = UUID2.into();
+        let mut task = task.into_mut(&mut rep);
+        assert!(apply_json_changes(&mut task, &before, &other).is_err());
+        Ok(())
+    }
+}
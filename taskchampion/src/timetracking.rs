@@ -0,0 +1,226 @@
+use crate::replica::Replica;
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use failure::Fallible;
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+
+/// A period of time during which a task was active, clipped to the range of a [`TimeReport`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct TrackedInterval {
+    pub uuid: Uuid,
+    pub description: String,
+    pub project: Option<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// True if the task is still active, in which case `end` is the time the report was made
+    /// (or the end of its range)
+    pub open: bool,
+}
+
+impl TrackedInterval {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// A TimeReport summarizes the time tracked with [`crate::TaskMut::start`] and
+/// [`crate::TaskMut::stop`] over a range of time.  Intervals are clipped to the range, and
+/// intervals that are still open are counted up to the time the report is made.
+#[derive(Debug)]
+pub struct TimeReport {
+    intervals: Vec<TrackedInterval>,
+}
+
+impl TimeReport {
+    /// Create a report of time tracked in the replica from `from` (inclusive) to `to` (exclusive)
+    pub fn new(replica: &mut Replica, from: DateTime<Utc>, to: DateTime<Utc>) -> Fallible<Self> {
+        Self::new_at(replica, from, to, Utc::now())
+    }
+
+    /// Create a report, as if the current time were `now`
+    pub fn new_at(
+        replica: &mut Replica,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Fallible<Self> {
+        let mut intervals = vec![];
+        for (uuid, task) in replica.all_tasks()? {
+            for interval in task.get_intervals() {
+                let end = interval.end.unwrap_or(now);
+                let start = interval.start.max(from);
+                let end = end.min(to);
+                if start >= end {
+                    continue;
+                }
+                intervals.push(TrackedInterval {
+                    uuid,
+                    description: task.get_description().into(),
+                    project: task.get_project().map(|p| p.into()),
+                    start,
+                    end,
+                    open: interval.end.is_none(),
+                });
+            }
+        }
+        intervals.sort_by_key(|i| (i.start, i.uuid));
+        Ok(TimeReport { intervals })
+    }
+
+    /// The tracked intervals, in order by start time
+    pub fn intervals(&self) -> &[TrackedInterval] {
+        &self.intervals
+    }
+
+    /// The total time tracked
+    pub fn total(&self) -> Duration {
+        self.intervals
+            .iter()
+            .fold(Duration::zero(), |t, i| t + i.duration())
+    }
+
+    /// The time tracked for each task
+    pub fn by_task(&self) -> HashMap<Uuid, Duration> {
+        let mut totals = HashMap::new();
+        for i in self.intervals.iter() {
+            add(
+                totals.entry(i.uuid).or_insert_with(Duration::zero),
+                i.duration(),
+            );
+        }
+        totals
+    }
+
+    /// The time tracked for each project, with None for tasks without a project
+    pub fn by_project(&self) -> HashMap<Option<String>, Duration> {
+        let mut totals = HashMap::new();
+        for i in self.intervals.iter() {
+            add(
+                totals
+                    .entry(i.project.clone())
+                    .or_insert_with(Duration::zero),
+                i.duration(),
+            );
+        }
+        totals
+    }
+
+    /// The time tracked on each day, in the given timezone.  Intervals spanning midnight are
+    /// divided between the days.
+    pub fn by_day<Tz: TimeZone>(&self, tz: &Tz) -> BTreeMap<NaiveDate, Duration> {
+        let mut totals = BTreeMap::new();
+        for i in self.intervals.iter() {
+            let mut start = i.start;
+            while start < i.end {
+                let day = start.with_timezone(tz).date();
+                let midnight = day
+                    .succ()
+                    .and_hms_opt(0, 0, 0)
+                    .map(|m| m.with_timezone(&Utc))
+                    .unwrap_or(i.end);
+                let end = midnight.min(i.end);
+                add(
+                    totals
+                        .entry(day.naive_local())
+                        .or_insert_with(Duration::zero),
+                    end - start,
+                );
+                start = end;
+            }
+        }
+        totals
+    }
+}
+
+/// Add to a total (chrono's Duration does not implement AddAssign)
+fn add(total: &mut Duration, d: Duration) {
+    *total = *total + d;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::task::Status;
+    use chrono::FixedOffset;
+
+    fn at(day: u32, hour: u32, min: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 3, day).and_hms(hour, min, 0)
+    }
+
+    /// Create a replica with a task in project "work" active from 3/1 10:00-11:30 and from 3/2
+    /// 23:00-01:00, and a task without a project active since 3/3 09:00.
+    fn replica() -> Fallible<(Replica, Uuid, Uuid)> {
+        let mut rep = Replica::new_inmemory();
+        let work = rep.new_task(Status::Pending, "work".into())?;
+        let work = *work.get_uuid();
+        rep.update_task(work, "project", Some("work"))?;
+        for (start, end) in &[(at(1, 10, 0), at(1, 11, 30)), (at(2, 23, 0), at(3, 1, 0))] {
+            rep.update_task(
+                work,
+                format!("start.{}", start.timestamp()),
+                Some(end.timestamp().to_string()),
+            )?;
+        }
+        let play = rep.new_task(Status::Pending, "play".into())?;
+        let play = *play.get_uuid();
+        rep.update_task(play, format!("start.{}", at(3, 9, 0).timestamp()), Some(""))?;
+        Ok((rep, work, play))
+    }
+
+    #[test]
+    fn totals() -> Fallible<()> {
+        let (mut rep, work, play) = replica()?;
+        let report = TimeReport::new_at(&mut rep, at(1, 0, 0), at(8, 0, 0), at(3, 9, 30))?;
+        assert_eq!(report.intervals().len(), 3);
+        assert!(report.intervals()[2].open);
+        assert_eq!(report.total(), Duration::minutes(90 + 120 + 30));
+
+        let by_task = report.by_task();
+        assert_eq!(by_task[&work], Duration::minutes(210));
+        assert_eq!(by_task[&play], Duration::minutes(30));
+
+        let by_project = report.by_project();
+        assert_eq!(by_project[&Some("work".into())], Duration::minutes(210));
+        assert_eq!(by_project[&None], Duration::minutes(30));
+        Ok(())
+    }
+
+    #[test]
+    fn clipped() -> Fallible<()> {
+        let (mut rep, _, _) = replica()?;
+        let report = TimeReport::new_at(&mut rep, at(1, 11, 0), at(3, 0, 0), at(3, 9, 30))?;
+        assert_eq!(report.total(), Duration::minutes(30 + 60));
+        assert_eq!(report.intervals()[1].end, at(3, 0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn by_day() -> Fallible<()> {
+        let (mut rep, _, _) = replica()?;
+        let report = TimeReport::new_at(&mut rep, at(1, 0, 0), at(8, 0, 0), at(3, 9, 30))?;
+        let days: Vec<_> = report.by_day(&Utc).into_iter().collect();
+        assert_eq!(
+            days,
+            vec![
+                (NaiveDate::from_ymd(2021, 3, 1), Duration::minutes(90)),
+                (NaiveDate::from_ymd(2021, 3, 2), Duration::minutes(60)),
+                (NaiveDate::from_ymd(2021, 3, 3), Duration::minutes(90)),
+            ]
+        );
+
+        // in UTC-2, the interval spanning midnight falls entirely on 3/2
+        let days: Vec<_> = report
+            .by_day(&FixedOffset::west(2 * 3600))
+            .into_iter()
+            .collect();
+        assert_eq!(
+            days,
+            vec![
+                (NaiveDate::from_ymd(2021, 3, 1), Duration::minutes(90)),
+                (NaiveDate::from_ymd(2021, 3, 2), Duration::minutes(120)),
+                (NaiveDate::from_ymd(2021, 3, 3), Duration::minutes(30)),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+use crate::replica::Replica;
+use crate::task::{Priority, Status, Task};
+use chrono::{DateTime, Utc};
+use failure::Fallible;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Coefficients for the urgency calculation.  The defaults match those of TaskWarrior.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UrgencyCoefficients {
+    /// Coefficients for tasks with high, medium, and low priority
+    pub priority_h: f64,
+    pub priority_m: f64,
+    pub priority_l: f64,
+    /// Coefficient for tasks with a due date, scaled by how close the due date is
+    pub due: f64,
+    /// Coefficient for the age of a task, scaled by the fraction of `age_max_days`
+    pub age: f64,
+    /// The age, in days, at which a task's age term is at its maximum
+    pub age_max_days: f64,
+    /// Coefficient for tasks that have been started
+    pub active: f64,
+    /// Coefficient for tasks with tags, scaled by the number of tags
+    pub tags: f64,
+    /// Coefficient for tasks with a project
+    pub project: f64,
+    /// Coefficient for tasks that depend on a pending task
+    pub blocked: f64,
+    /// Coefficient for tasks on which a pending task depends
+    pub blocking: f64,
+    /// Coefficient for tasks with annotations, scaled by the number of annotations
+    pub annotations: f64,
+    /// Additional coefficients for tasks with specific tags
+    pub tag: HashMap<String, f64>,
+}
+
+impl Default for UrgencyCoefficients {
+    fn default() -> Self {
+        let mut tag = HashMap::new();
+        tag.insert("next".into(), 15.0);
+        UrgencyCoefficients {
+            priority_h: 6.0,
+            priority_m: 3.9,
+            priority_l: 1.8,
+            due: 12.0,
+            age: 2.0,
+            age_max_days: 365.0,
+            active: 4.0,
+            tags: 1.0,
+            project: 1.0,
+            blocked: -5.0,
+            blocking: 8.0,
+            annotations: 1.0,
+            tag,
+        }
+    }
+}
+
+/// One term of a task's urgency: a factor between 0 and 1 describing the task, and the
+/// coefficient by which it is multiplied.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UrgencyTerm {
+    /// The name of the term, such as `due` or `tag.next`
+    pub name: String,
+    pub factor: f64,
+    pub coefficient: f64,
+}
+
+impl UrgencyTerm {
+    /// The contribution of this term to the task's urgency
+    pub fn value(&self) -> f64 {
+        self.factor * self.coefficient
+    }
+}
+
+/// The urgency of a task, as the sum of a number of terms.  Only terms that apply to the task
+/// are included.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Urgency {
+    pub terms: Vec<UrgencyTerm>,
+}
+
+impl Urgency {
+    /// The total urgency
+    pub fn value(&self) -> f64 {
+        self.terms.iter().map(|t| t.value()).sum()
+    }
+}
+
+/// An UrgencyCalculator calculates the urgency of tasks in a replica.  Some terms depend on other
+/// tasks (such as whether a task is blocked), so the calculator captures that information from the
+/// replica when it is created, and should not be used after the replica changes.
+pub struct UrgencyCalculator {
+    coefficients: UrgencyCoefficients,
+    now: DateTime<Utc>,
+    /// UUIDs of pending tasks
+    pending: HashSet<Uuid>,
+    /// UUIDs of tasks on which some pending task depends
+    blocking: HashSet<Uuid>,
+}
+
+impl UrgencyCalculator {
+    /// Create a new calculator for tasks in the given replica
+    pub fn new(replica: &mut Replica, coefficients: UrgencyCoefficients) -> Fallible<Self> {
+        Self::new_at(replica, coefficients, Utc::now())
+    }
+
+    /// Create a new calculator, as if the current time were `now`
+    pub fn new_at(
+        replica: &mut Replica,
+        coefficients: UrgencyCoefficients,
+        now: DateTime<Utc>,
+    ) -> Fallible<Self> {
+        let pending_tasks = replica.tasks_with_status(Status::Pending)?;
+        let pending = pending_tasks.keys().copied().collect();
+        let blocking = pending_tasks
+            .values()
+            .flat_map(|t| t.get_dependencies())
+            .collect();
+        Ok(UrgencyCalculator {
+            coefficients,
+            now,
+            pending,
+            blocking,
+        })
+    }
+
+    /// Determine whether a task is blocked, that is, whether it depends on a pending task
+    pub fn is_blocked(&self, task: &Task) -> bool {
+        task.get_dependencies()
+            .iter()
+            .any(|d| self.pending.contains(d))
+    }
+
+    /// Calculate the urgency of a task
+    pub fn urgency(&self, task: &Task) -> Urgency {
+        let c = &self.coefficients;
+        let mut terms = vec![];
+        let mut term = |name: &str, factor: f64, coefficient: f64| {
+            if factor != 0.0 && coefficient != 0.0 {
+                terms.push(UrgencyTerm {
+                    name: name.into(),
+                    factor,
+                    coefficient,
+                });
+            }
+        };
+
+        if let Some(priority) = task.get_priority() {
+            let coefficient = match priority {
+                Priority::H => c.priority_h,
+                Priority::M => c.priority_m,
+                Priority::L => c.priority_l,
+            };
+            term("priority", 1.0, coefficient);
+        }
+
+        if let Some(due) = task.get_due() {
+            term("due", due_factor(self.now, due), c.due);
+        }
+
+        if let Some(entry) = task.get_entry() {
+            let days = (self.now - entry).num_seconds() as f64 / 86400.0;
+            if c.age_max_days > 0.0 && days > 0.0 {
+                term("age", (days / c.age_max_days).min(1.0), c.age);
+            }
+        }
+
+        term("active", task.is_active() as u8 as f64, c.active);
+
+        let tags = task.get_tags();
+        let tags_factor = match tags.len() {
+            0 => 0.0,
+            1 => 0.8,
+            2 => 0.9,
+            _ => 1.0,
+        };
+        term("tags", tags_factor, c.tags);
+        for tag in tags {
+            if let Some(coefficient) = c.tag.get(tag) {
+                term(&format!("tag.{}", tag), 1.0, *coefficient);
+            }
+        }
+
+        term(
+            "project",
+            task.get_project().is_some() as u8 as f64,
+            c.project,
+        );
+
+        term("blocked", self.is_blocked(task) as u8 as f64, c.blocked);
+        let blocking = self.blocking.contains(task.get_uuid());
+        term("blocking", blocking as u8 as f64, c.blocking);
+
+        let annotations_factor = match task.annotation_count() {
+            0 => 0.0,
+            1 => 0.8,
+            2 => 0.9,
+            _ => 1.0,
+        };
+        term("annotations", annotations_factor, c.annotations);
+
+        Urgency { terms }
+    }
+}
+
+/// The due factor rises linearly from 0.2, for tasks due in 14 or more days, to 1.0, for tasks
+/// 7 or more days overdue.
+fn due_factor(now: DateTime<Utc>, due: DateTime<Utc>) -> f64 {
+    let days_overdue = (now - due).num_seconds() as f64 / 86400.0;
+    if days_overdue >= 7.0 {
+        1.0
+    } else if days_overdue >= -14.0 {
+        ((days_overdue + 14.0) * 0.8 / 21.0) + 0.2
+    } else {
+        0.2
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)
+    }
+
+    #[test]
+    fn due_factor_range() {
+        assert_eq!(due_factor(now(), now() - Duration::days(10)), 1.0);
+        assert_eq!(due_factor(now(), now() + Duration::days(30)), 0.2);
+        assert!((due_factor(now(), now()) - (14.0 * 0.8 / 21.0 + 0.2)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn urgency_terms() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        let blocker = rep.new_task(Status::Pending, "blocker".into())?;
+        let task = rep.new_task(Status::Pending, "task".into())?;
+        let mut task = task.into_mut(&mut rep);
+        task.set_priority(Some(Priority::H))?;
+        task.set_due(Some(now()))?;
+        task.set_entry(Some(now() - Duration::days(73)))?;
+        task.set_project(Some("home".into()))?;
+        task.add_tag("next")?;
+        let uuid = *task.get_uuid();
+        rep.update_task(uuid, format!("dep.{}", blocker.get_uuid()), Some(""))?;
+        let task = rep.get_task(&uuid)?.unwrap();
+
+        let calc = UrgencyCalculator::new_at(&mut rep, UrgencyCoefficients::default(), now())?;
+        let urgency = calc.urgency(&task);
+        let names: Vec<&str> = urgency.terms.iter().map(|t| t.name.as_ref()).collect();
+        assert_eq!(
+            names,
+            vec!["priority", "due", "age", "tags", "tag.next", "project", "blocked"]
+        );
+        let expected = 6.0 + 12.0 * (14.0 * 0.8 / 21.0 + 0.2) + 2.0 * 0.2 + 0.8 + 15.0 + 1.0 - 5.0;
+        assert!((urgency.value() - expected).abs() < 1e-9);
+
+        let blocker_urgency = calc.urgency(&blocker);
+        let names: Vec<&str> = blocker_urgency
+            .terms
+            .iter()
+            .map(|t| t.name.as_ref())
+            .collect();
+        assert_eq!(names, vec!["blocking"]);
+        assert!(calc.is_blocked(&task));
+        assert!(!calc.is_blocked(&blocker));
+        Ok(())
+    }
+
+    #[test]
+    fn custom_coefficients() -> Fallible<()> {
+        let mut rep = Replica::new_inmemory();
+        let task = rep.new_task(Status::Pending, "task".into())?;
+        let mut task = task.into_mut(&mut rep);
+        task.set_project(Some("home".into()))?;
+        task.start()?;
+        let task = task.into_immut();
+
+        let coefficients = UrgencyCoefficients {
+            project: 0.0,
+            active: 10.0,
+            ..Default::default()
+        };
+        let calc = UrgencyCalculator::new_at(&mut rep, coefficients, now())?;
+        assert_eq!(calc.urgency(&task).value(), 10.0);
+        Ok(())
+    }
+}